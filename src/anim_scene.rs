@@ -1,4 +1,5 @@
 use cgmath::Point2;
+use engine::EngineData;
 use ggez::event::{KeyCode, KeyMods};
 use ggez::graphics::{self, Canvas, DrawParam, Text};
 use ggez::{timer, Context, GameResult};
@@ -21,7 +22,7 @@ pub struct AnimScene {
 }
 
 impl<'a> AnimScene {
-    pub fn new(ctx: &mut Context) -> BoxedScene<'a, SceneName, ()> {
+    pub fn new(ctx: &mut Context) -> BoxedScene<'a, SceneName, EngineData> {
         let reader = AnimReader::new(
             &Path::new("./assets/anim.idx"),
             &Path::new("./assets/Anim.mul"),
@@ -164,8 +165,8 @@ impl<'a> AnimScene {
     }
 }
 
-impl Scene<SceneName, ()> for AnimScene {
-    fn draw(&mut self, ctx: &mut Context, _engine_data: &mut ()) -> GameResult<()> {
+impl Scene<SceneName, EngineData> for AnimScene {
+    fn draw(&mut self, ctx: &mut Context, _engine_data: &mut EngineData) -> GameResult<()> {
         if self.textures.len() > 0 {
             graphics::draw(
                 ctx,
@@ -179,7 +180,7 @@ impl Scene<SceneName, ()> for AnimScene {
     fn update(
         &mut self,
         ctx: &mut Context,
-        _engine_data: &mut (),
+        _engine_data: &mut EngineData,
     ) -> GameResult<Option<SceneChangeEvent<SceneName>>> {
         const DESIRED_FPS: u32 = 15;
 
@@ -203,7 +204,7 @@ impl Scene<SceneName, ()> for AnimScene {
         keycode: KeyCode,
         _keymods: KeyMods,
         _repeat: bool,
-        _engine_data: &mut (),
+        _engine_data: &mut EngineData,
     ) {
         match keycode {
             KeyCode::Escape => self.exiting = true,
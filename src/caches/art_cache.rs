@@ -1,21 +1,30 @@
+use caches::lru_cache::{CacheStats, LruCache};
 use ggez::graphics::Image;
 use ggez::Context;
 use image_convert::image_to_surface;
-use std::collections::HashMap;
 use std::fs::File;
 use std::path::Path;
 use uorustlibs::art::{Art, ArtReader};
 use uorustlibs::tiledata::{MapTileData, StaticTileData, TileDataReader};
 
+// Counted in pieces of art, whatever their size. Tiles are all 44x44, but statics run from a
+// few pixels to whole buildings.
+pub const DEFAULT_TILE_CAPACITY: usize = 2048;
+pub const DEFAULT_STATIC_CAPACITY: usize = 4096;
+
 pub struct ArtCache {
-    tile_cache: HashMap<u32, Option<(Image, MapTileData)>>,
-    static_cache: HashMap<u32, Option<(Image, StaticTileData)>>,
+    tile_cache: LruCache<u32, Option<(Image, MapTileData)>>,
+    static_cache: LruCache<u32, Option<(Image, StaticTileData)>>,
     reader: ArtReader<File>,
     tiledata_reader: TileDataReader<File>,
 }
 
 impl ArtCache {
     pub fn new() -> ArtCache {
+        ArtCache::with_capacity(DEFAULT_TILE_CAPACITY, DEFAULT_STATIC_CAPACITY)
+    }
+
+    pub fn with_capacity(tile_capacity: usize, static_capacity: usize) -> ArtCache {
         let reader = ArtReader::new(
             &Path::new("./assets/artidx.mul"),
             &Path::new("./assets/art.mul"),
@@ -24,52 +33,59 @@ impl ArtCache {
         let tiledata_reader = TileDataReader::new(&Path::new("./assets/tiledata.mul"))
             .expect("Could not load tiledata");
         ArtCache {
-            tile_cache: HashMap::new(),
-            static_cache: HashMap::new(),
+            tile_cache: LruCache::new(tile_capacity),
+            static_cache: LruCache::new(static_capacity),
             reader,
             tiledata_reader,
         }
     }
 
     pub fn read_tile(&mut self, ctx: &mut Context, id: u32) -> &Option<(Image, MapTileData)> {
-        if self.tile_cache.contains_key(&id) {
-            self.tile_cache.get(&id).unwrap()
-        } else {
-            match (
+        if self.tile_cache.get(&id).is_none() {
+            let entry = match (
                 self.reader.read_tile(id),
                 self.tiledata_reader.read_map_tile_data(id),
             ) {
                 (Ok(tile), Ok(tiledata)) => {
                     let image = tile.to_image();
                     let tile_image = image_to_surface(ctx, &image);
-                    self.tile_cache.insert(id, Some((tile_image, tiledata)));
+                    Some((tile_image, tiledata))
                 }
-                _ => {
-                    self.tile_cache.insert(id, None);
-                }
-            }
-            self.tile_cache.get(&id).unwrap()
+                _ => None,
+            };
+            self.tile_cache.insert(id, entry);
         }
+        self.tile_cache.peek(&id).unwrap()
     }
 
     pub fn read_static(&mut self, ctx: &mut Context, id: u32) -> &Option<(Image, StaticTileData)> {
-        if self.static_cache.contains_key(&id) {
-            self.static_cache.get(&id).unwrap()
-        } else {
-            match (
+        if self.static_cache.get(&id).is_none() {
+            let entry = match (
                 self.reader.read_static(id),
                 self.tiledata_reader.read_static_tile_data(id),
             ) {
                 (Ok(tile), Ok(tiledata)) => {
                     let image = tile.to_image();
                     let tile_image = image_to_surface(ctx, &image);
-                    self.static_cache.insert(id, Some((tile_image, tiledata)));
-                }
-                _ => {
-                    self.static_cache.insert(id, None);
+                    Some((tile_image, tiledata))
                 }
-            }
-            self.static_cache.get(&id).unwrap()
+                _ => None,
+            };
+            self.static_cache.insert(id, entry);
         }
+        self.static_cache.peek(&id).unwrap()
+    }
+
+    pub fn flush(&mut self) {
+        self.tile_cache.clear();
+        self.static_cache.clear();
+    }
+
+    pub fn tile_stats(&self) -> CacheStats {
+        self.tile_cache.stats()
+    }
+
+    pub fn static_stats(&self) -> CacheStats {
+        self.static_cache.stats()
     }
 }
@@ -0,0 +1,94 @@
+use caches::lru_cache::LruCache;
+use image::RgbaImage;
+use std::fs::File;
+use std::path::Path;
+use std::rc::Rc;
+use uorustlibs::art::{Art, ArtReader};
+
+// Counted in masks, at a bit a pixel of whatever size the art is
+pub const DEFAULT_TILE_MASK_CAPACITY: usize = 512;
+pub const DEFAULT_STATIC_MASK_CAPACITY: usize = 1024;
+
+// Which pixels of a piece of art are drawn, a bit each
+pub struct ArtMask {
+    width: u32,
+    height: u32,
+    bits: Vec<u64>,
+}
+
+impl ArtMask {
+    pub fn new(image: &RgbaImage) -> ArtMask {
+        let (width, height) = image.dimensions();
+        let mut bits = vec![0; ((width * height + 63) / 64) as usize];
+        for (x, y, pixel) in image.enumerate_pixels() {
+            if pixel[3] > 0 {
+                let index = y * width + x;
+                bits[(index / 64) as usize] |= 1 << (index % 64);
+            }
+        }
+        ArtMask {
+            width,
+            height,
+            bits,
+        }
+    }
+
+    // Relative to where the art is drawn
+    pub fn opaque_at(&self, x: f32, y: f32) -> bool {
+        if x < 0.0 || y < 0.0 || x >= self.width as f32 || y >= self.height as f32 {
+            return false;
+        }
+        let index = y as u32 * self.width + x as u32;
+        self.bits[(index / 64) as usize] & (1 << (index % 64)) != 0
+    }
+}
+
+// Hit testing only needs to know where art is drawn, so this keeps masks rather than a second
+// copy of the art the GPU caches already hold
+pub struct ArtMaskCache {
+    tile_cache: LruCache<u32, Option<Rc<ArtMask>>>,
+    static_cache: LruCache<u32, Option<Rc<ArtMask>>>,
+    reader: ArtReader<File>,
+}
+
+impl ArtMaskCache {
+    pub fn new() -> ArtMaskCache {
+        let reader = ArtReader::new(
+            &Path::new("./assets/artidx.mul"),
+            &Path::new("./assets/art.mul"),
+        )
+        .expect("Could not load art");
+        ArtMaskCache {
+            tile_cache: LruCache::new(DEFAULT_TILE_MASK_CAPACITY),
+            static_cache: LruCache::new(DEFAULT_STATIC_MASK_CAPACITY),
+            reader,
+        }
+    }
+
+    pub fn read_tile(&mut self, id: u32) -> Option<Rc<ArtMask>> {
+        if self.tile_cache.get(&id).is_none() {
+            let entry = match self.reader.read_tile(id) {
+                Ok(tile) => Some(Rc::new(ArtMask::new(&tile.to_image()))),
+                Err(_) => None,
+            };
+            self.tile_cache.insert(id, entry);
+        }
+        self.tile_cache.peek(&id).unwrap().clone()
+    }
+
+    pub fn read_static(&mut self, id: u32) -> Option<Rc<ArtMask>> {
+        if self.static_cache.get(&id).is_none() {
+            let entry = match self.reader.read_static(id) {
+                Ok(tile) => Some(Rc::new(ArtMask::new(&tile.to_image()))),
+                Err(_) => None,
+            };
+            self.static_cache.insert(id, entry);
+        }
+        self.static_cache.peek(&id).unwrap().clone()
+    }
+
+    pub fn flush(&mut self) {
+        self.tile_cache.clear();
+        self.static_cache.clear();
+    }
+}
@@ -1,9 +1,13 @@
+use caches::lru_cache::{CacheStats, LruCache};
+use caches::prefetcher::Prefetcher;
+use map::patches::{patch_index, Patches};
+use std::cmp::min;
 use std::collections::HashMap;
 use std::fs::File;
-use std::io::Result;
-use std::path::Path;
+use std::rc::Rc;
+use std::sync::Arc;
 
-use uorustlibs::map::{Block, Cell, MapReader, StaticLocation, StaticReader};
+use uorustlibs::map::{Block, MapReader, StaticLocation, StaticReader};
 
 #[derive(Clone, Copy)]
 pub struct Altitudes {
@@ -13,11 +17,96 @@ pub struct Altitudes {
     pub x2y2: i8,
 }
 
+// Everything needed to draw a block. Records are immutable once built, so the cache hands out
+// shared handles to them rather than copies.
+#[derive(Clone)]
+pub struct BlockRecord {
+    pub block: Block,
+    pub statics: Vec<StaticLocation>,
+    pub altitudes: Vec<Altitudes>,
+}
+
+impl BlockRecord {
+    pub fn new(
+        block: Block,
+        statics: Vec<StaticLocation>,
+        block_x2y1: Option<&Block>,
+        block_x1y2: Option<&Block>,
+        block_x2y2: Option<&Block>,
+    ) -> BlockRecord {
+        let altitudes = read_altitudes(&block, block_x2y1, block_x1y2, block_x2y2);
+        BlockRecord {
+            block,
+            statics,
+            altitudes,
+        }
+    }
+}
+
 pub struct FacetCache {
     map_reader: MapReader,
     static_reader: StaticReader<File>,
-    block_cache: HashMap<(u32, u32), (Block, Vec<StaticLocation>)>,
-    height_cache: HashMap<(u32, u32), Vec<Altitudes>>,
+    width_blocks: u32,
+    height_blocks: u32,
+    // Bare land blocks, kept around so neighbours can be used for altitudes without
+    // building a whole record for them
+    land_cache: LruCache<(u32, u32), Option<Block>>,
+    record_cache: LruCache<(u32, u32), Option<Rc<BlockRecord>>>,
+    prefetcher: Option<Prefetcher>,
+    // Blocks asked of the prefetcher, and whether they were asked for patched
+    pending: HashMap<(u32, u32), bool>,
+    // Edited land and statics. They aren't in either cache, as nothing can be allowed to
+    // evict them.
+    land_overrides: HashMap<(u32, u32), Block>,
+    statics_overrides: HashMap<(u32, u32), Vec<StaticLocation>>,
+    // Shared with the prefetcher, which applies them the same way
+    patches: Arc<Patches>,
+    patched: bool,
+}
+
+// In records, not bytes. A record is about half a kilobyte plus its statics, so this is roughly
+// 10MB on an ordinary facet, more where statics are dense.
+pub const DEFAULT_BLOCK_CAPACITY: usize = 16384;
+const PREFETCH_MARGIN: u32 = 2;
+// A record's altitudes come from its own block and the ones east and south of it, so a change
+// to one block reaches the records to its north and west
+const DEPENDENT_RECORDS: [(u32, u32); 4] = [(0, 0), (1, 0), (0, 1), (1, 1)];
+
+// A truncated map file can still fail inside the facet bounds, so treat that as empty
+pub fn load_land(map_reader: &mut MapReader, x: u32, y: u32) -> Option<Block> {
+    map_reader.read_block_from_coordinates(x, y, None).ok()
+}
+
+pub fn load_statics(static_reader: &mut StaticReader<File>, x: u32, y: u32) -> Vec<StaticLocation> {
+    static_reader
+        .read_block_from_coordinates(x, y, None)
+        .unwrap_or(vec![])
+}
+
+fn altitude_at(
+    block_x1y1: &Block,
+    block_x2y1: Option<&Block>,
+    block_x1y2: Option<&Block>,
+    block_x2y2: Option<&Block>,
+    x: usize,
+    y: usize,
+) -> i8 {
+    // Coordinates of 8 fall into the neighbouring blocks. Missing neighbours clamp back to the
+    // nearest cell we do have, so the edges of a facet come out flat rather than panicking.
+    match (x >= 8, y >= 8) {
+        (false, false) => block_x1y1.cells[y * 8 + x].altitude,
+        (true, false) => block_x2y1
+            .map(|block| block.cells[y * 8].altitude)
+            .unwrap_or_else(|| block_x1y1.cells[y * 8 + 7].altitude),
+        (false, true) => block_x1y2
+            .map(|block| block.cells[x].altitude)
+            .unwrap_or_else(|| block_x1y1.cells[7 * 8 + x].altitude),
+        (true, true) => block_x2y2
+            .map(|block| block.cells[0].altitude)
+            .or_else(|| block_x2y1.map(|block| block.cells[7 * 8].altitude))
+            .or_else(|| block_x1y2.map(|block| block.cells[7].altitude))
+            .unwrap_or_else(|| block_x1y1.cells[63].altitude),
+    }
 }
 
 pub fn read_altitudes(
@@ -29,43 +118,12 @@ pub fn read_altitudes(
     let mut collector = vec![];
     for y in 0..8 {
         for x in 0..8 {
-            let cell = block_x1y1.cells[y * 8 + x];
-            let x1y1 = cell.altitude;
-            let x2y1 = if x < 7 {
-                block_x1y1.cells[y * 8 + x + 1].altitude
-            } else {
-                block_x2y1
-                    .map(|block| block.cells[y * 8].altitude)
-                    .unwrap_or_else(|| block_x1y1.cells[y * 8 + x].altitude)
-            };
-            let x1y2 = if y < 7 {
-                block_x1y1.cells[(y + 1) * 8 + x].altitude
-            } else {
-                block_x1y2
-                    .map(|block| block.cells[x].altitude)
-                    .unwrap_or_else(|| block_x1y1.cells[y * 8 + x].altitude)
-            };
-            let default_x2y2 = block_x1y1.cells[(y) * 8 + x].altitude;
-            let x2y2 = if x == 7 && y == 7 {
-                block_x2y2
-                    .map(|block| block.cells[0].altitude)
-                    .unwrap_or(default_x2y2)
-            } else if x == 7 {
-                block_x2y1
-                    .map(|block| block.cells[(y + 1) * 8].altitude)
-                    .unwrap_or(default_x2y2)
-            } else if y == 7 {
-                block_x1y2
-                    .map(|block| block.cells[x + 1].altitude)
-                    .unwrap_or(default_x2y2)
-            } else {
-                block_x1y1.cells[(y + 1) * 8 + x + 1].altitude
-            };
+            let at = |x, y| altitude_at(block_x1y1, block_x2y1, block_x1y2, block_x2y2, x, y);
             collector.push(Altitudes {
-                x1y1,
-                x2y1,
-                x1y2,
-                x2y2,
+                x1y1: at(x, y),
+                x2y1: at(x + 1, y),
+                x1y2: at(x, y + 1),
+                x2y2: at(x + 1, y + 1),
             });
         }
     }
@@ -73,44 +131,460 @@ pub fn read_altitudes(
 }
 
 impl FacetCache {
-    pub fn new(map_reader: MapReader, static_reader: StaticReader<File>) -> FacetCache {
+    pub fn new(
+        map_reader: MapReader,
+        static_reader: StaticReader<File>,
+        width_blocks: u32,
+        height_blocks: u32,
+    ) -> FacetCache {
+        FacetCache::with_capacity(
+            map_reader,
+            static_reader,
+            width_blocks,
+            height_blocks,
+            DEFAULT_BLOCK_CAPACITY,
+        )
+    }
+
+    pub fn with_capacity(
+        map_reader: MapReader,
+        static_reader: StaticReader<File>,
+        width_blocks: u32,
+        height_blocks: u32,
+        block_capacity: usize,
+    ) -> FacetCache {
         FacetCache {
             map_reader,
             static_reader,
-            block_cache: HashMap::new(),
-            height_cache: HashMap::new(),
+            width_blocks,
+            height_blocks,
+            land_cache: LruCache::new(block_capacity),
+            record_cache: LruCache::new(block_capacity),
+            prefetcher: None,
+            pending: HashMap::new(),
+            land_overrides: HashMap::new(),
+            statics_overrides: HashMap::new(),
+            patches: Arc::new(Patches::empty()),
+            patched: false,
         }
     }
 
-    fn read_block_cache(&mut self, x: u32, y: u32) -> &(Block, Vec<StaticLocation>) {
-        if (!self.block_cache.contains_key(&(x, y))) {
-            let block = self.map_reader.read_block_from_coordinates(x, y, None);
-            let statics = self.static_reader.read_block_from_coordinates(x, y, None);
-            self.block_cache.insert(
-                (x, y),
-                (block.ok().unwrap(), statics.ok().unwrap_or(vec![])),
-            ); // FIXME: Out of bounds errors
+    // The prefetcher needs its own readers, as it does all of its I/O on another thread.
+    // Patches have to be set first, so it gets a copy of them.
+    pub fn spawn_prefetcher(&mut self, map_reader: MapReader, static_reader: StaticReader<File>) {
+        self.prefetcher = Some(Prefetcher::spawn(
+            map_reader,
+            static_reader,
+            self.width_blocks,
+            self.height_blocks,
+            self.patches.clone(),
+        ));
+    }
+
+    pub fn set_patches(&mut self, patches: Patches) {
+        self.patches = Arc::new(patches);
+        self.record_cache.clear();
+    }
+
+    pub fn patched(&self) -> bool {
+        self.patched
+    }
+
+    // Land is cached unpatched, so only the records built from it need throwing away. Anything
+    // still on its way from the prefetcher will be dropped when it turns up, so it's asked for
+    // again.
+    pub fn set_patched(&mut self, patched: bool) {
+        if patched != self.patched {
+            self.patched = patched;
+            self.record_cache.clear();
+            let dropped = self
+                .pending
+                .drain()
+                .map(|(coords, _)| coords)
+                .collect::<Vec<_>>();
+            for (x, y) in dropped {
+                self.request(x, y);
+            }
         }
-        self.block_cache.get(&(x, y)).unwrap()
     }
 
-    fn read_altitudes(&mut self, x: u32, y: u32) -> &Vec<Altitudes> {
-        if (!self.height_cache.contains_key(&(x, y))) {
-            let (block, _) = self.read_block_cache(x, y).clone(); // FIXME: Do this without clones
-            let (block_x2, _) = self.read_block_cache(x + 1, y).clone();
-            let (block_y2, _) = self.read_block_cache(x, y + 1).clone();
-            let (block_x2y2, _) = self.read_block_cache(x + 1, y + 1).clone();
-            let collector =
-                read_altitudes(&block, Some(&block_x2), Some(&block_y2), Some(&block_x2y2));
-            self.height_cache.insert((x, y), collector);
+    pub fn patches(&self) -> &Patches {
+        &self.patches
+    }
+
+    pub fn patch_indices(&self) -> Vec<u32> {
+        self.patches.indices()
+    }
+
+    pub fn is_patched_block(&self, x: u32, y: u32) -> bool {
+        self.patches.contains(patch_index(x, y, self.height_blocks))
+    }
+
+    pub fn pending_blocks(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_pending(&self, x: u32, y: u32) -> bool {
+        self.pending.contains_key(&(x, y))
+    }
+
+    // Whatever's still on its way is forgotten too, so prefetch asks for it again rather than
+    // waiting on a response that may already have been dropped
+    pub fn flush(&mut self) {
+        self.land_cache.clear();
+        self.record_cache.clear();
+        self.pending.clear();
+    }
+
+    pub fn record_stats(&self) -> CacheStats {
+        self.record_cache.stats()
+    }
+
+    pub fn land_stats(&self) -> CacheStats {
+        self.land_cache.stats()
+    }
+
+    pub fn width_blocks(&self) -> u32 {
+        self.width_blocks
+    }
+
+    pub fn height_blocks(&self) -> u32 {
+        self.height_blocks
+    }
+
+    pub fn in_bounds(&self, x: u32, y: u32) -> bool {
+        x < self.width_blocks && y < self.height_blocks
+    }
+
+    fn patched_land(&self, x: u32, y: u32) -> Option<Block> {
+        if !self.patched {
+            return None;
         }
-        self.height_cache.get(&(x, y)).unwrap()
+        self.patches
+            .land(patch_index(x, y, self.height_blocks))
+            .cloned()
     }
 
-    pub fn read_block(&mut self, x: u32, y: u32) -> ((Block, Vec<StaticLocation>), Vec<Altitudes>) {
-        // FIXME: Sort out mutable borrows
-        let block = self.read_block_cache(x, y).clone();
-        let altitudes = self.read_altitudes(x, y).to_vec();
-        (block, altitudes)
+    pub fn read_land(&mut self, x: u32, y: u32) -> Option<Block> {
+        if self.in_bounds(x, y) && !self.land_overrides.contains_key(&(x, y)) {
+            if let Some(block) = self.patched_land(x, y) {
+                return Some(block);
+            }
+        }
+        self.read_unpatched_land(x, y)
+    }
+
+    // Edits over the map file's own land, whether or not patches are shown. This is what gets
+    // saved, as the client lays the patches over the file itself.
+    pub fn read_unpatched_land(&mut self, x: u32, y: u32) -> Option<Block> {
+        if !self.in_bounds(x, y) {
+            return None;
+        }
+        if let Some(block) = self.land_overrides.get(&(x, y)) {
+            return Some(block.clone());
+        }
+        if self.land_cache.get(&(x, y)).is_none() {
+            let block = load_land(&mut self.map_reader, x, y);
+            self.land_cache.insert((x, y), block);
+        }
+        self.land_cache.peek(&(x, y)).unwrap().clone()
+    }
+
+    fn load_record(&mut self, x: u32, y: u32) -> Option<BlockRecord> {
+        let block = self.read_land(x, y)?;
+        let statics = self.read_statics(x, y);
+        // Edge blocks have no neighbours, so read_altitudes falls back to the block's own heights
+        let block_x2 = self.read_land(x + 1, y);
+        let block_y2 = self.read_land(x, y + 1);
+        let block_x2y2 = self.read_land(x + 1, y + 1);
+        Some(BlockRecord::new(
+            block,
+            statics,
+            block_x2.as_ref(),
+            block_y2.as_ref(),
+            block_x2y2.as_ref(),
+        ))
+    }
+
+    // Straight from the reader, for scans over a whole facet that would only churn the cache
+    pub fn read_land_uncached(&mut self, x: u32, y: u32) -> Option<Block> {
+        if !self.in_bounds(x, y) {
+            return None;
+        }
+        if let Some(block) = self.land_overrides.get(&(x, y)) {
+            return Some(block.clone());
+        }
+        if let Some(block) = self.patched_land(x, y) {
+            return Some(block);
+        }
+        load_land(&mut self.map_reader, x, y)
+    }
+
+    // Replaces a block's land until the facet is dropped, and rebuilds every record that
+    // depends on it straight away so edits show up on the next frame
+    pub fn set_land(&mut self, x: u32, y: u32, block: Block) {
+        if !self.in_bounds(x, y) {
+            return;
+        }
+        self.land_overrides.insert((x, y), block);
+        self.land_cache.remove(&(x, y));
+        for &(dx, dy) in DEPENDENT_RECORDS.iter() {
+            if x >= dx && y >= dy {
+                let coords = (x - dx, y - dy);
+                let record = self.load_record(coords.0, coords.1).map(Rc::new);
+                self.record_cache.insert(coords, record);
+            }
+        }
+    }
+
+    pub fn read_statics(&mut self, x: u32, y: u32) -> Vec<StaticLocation> {
+        if !self.in_bounds(x, y) {
+            return vec![];
+        }
+        if let Some(statics) = self.statics_overrides.get(&(x, y)) {
+            return statics.clone();
+        }
+        if self.patched {
+            if let Some(statics) = self.patches.statics(patch_index(x, y, self.height_blocks)) {
+                return statics.clone();
+            }
+        }
+        load_statics(&mut self.static_reader, x, y)
+    }
+
+    // Like read_unpatched_land, for saving statics
+    pub fn read_unpatched_statics(&mut self, x: u32, y: u32) -> Vec<StaticLocation> {
+        if !self.in_bounds(x, y) {
+            return vec![];
+        }
+        if let Some(statics) = self.statics_overrides.get(&(x, y)) {
+            return statics.clone();
+        }
+        load_statics(&mut self.static_reader, x, y)
+    }
+
+    // Statics only show up in their own block's record, so that's all that needs rebuilding
+    pub fn set_statics(&mut self, x: u32, y: u32, statics: Vec<StaticLocation>) {
+        if !self.in_bounds(x, y) {
+            return;
+        }
+        self.statics_overrides.insert((x, y), statics);
+        let record = self.load_record(x, y).map(Rc::new);
+        self.record_cache.insert((x, y), record);
+    }
+
+    fn depends_on_overrides(&self, x: u32, y: u32) -> bool {
+        self.statics_overrides.contains_key(&(x, y))
+            || DEPENDENT_RECORDS
+                .iter()
+                .any(|&(dx, dy)| self.land_overrides.contains_key(&(x + dx, y + dy)))
+    }
+
+    pub fn read_block(&mut self, x: u32, y: u32) -> Option<Rc<BlockRecord>> {
+        if !self.in_bounds(x, y) {
+            return None;
+        }
+        if let Some(record) = self.record_cache.get(&(x, y)) {
+            return record.clone();
+        }
+        let record = self.load_record(x, y).map(Rc::new);
+        self.record_cache.insert((x, y), record.clone());
+        record
+    }
+
+    fn request(&mut self, x: u32, y: u32) {
+        if !self.in_bounds(x, y) || self.pending.contains_key(&(x, y)) {
+            return;
+        }
+        let sent = match self.prefetcher {
+            Some(ref prefetcher) => prefetcher.request(x, y, self.patched),
+            None => false,
+        };
+        if sent {
+            self.pending.insert((x, y), self.patched);
+        }
+    }
+
+    // Like read_block, but never touches the disk. Blocks we don't have yet are queued up on
+    // the prefetcher, and turn up through receive_prefetched on a later frame.
+    pub fn try_read_block(&mut self, x: u32, y: u32) -> Option<Rc<BlockRecord>> {
+        if self.prefetcher.is_none() {
+            return self.read_block(x, y);
+        }
+        if !self.in_bounds(x, y) {
+            return None;
+        }
+        match self.record_cache.get(&(x, y)) {
+            Some(record) => record.clone(),
+            None => {
+                self.request(x, y);
+                None
+            }
+        }
+    }
+
+    // Queues everything around the viewport, nearest first, reaching further ahead in the
+    // direction we're moving
+    pub fn prefetch(&mut self, x: u32, y: u32, width: u32, height: u32, direction: (i32, i32)) {
+        if self.prefetcher.is_none() {
+            return;
+        }
+        let (dx, dy) = direction;
+        let ahead_x = width / 2;
+        let ahead_y = height / 2;
+        let left = x.saturating_sub(PREFETCH_MARGIN + if dx < 0 { ahead_x } else { 0 });
+        let top = y.saturating_sub(PREFETCH_MARGIN + if dy < 0 { ahead_y } else { 0 });
+        let right = min(
+            self.width_blocks,
+            x + width + PREFETCH_MARGIN + if dx > 0 { ahead_x } else { 0 },
+        );
+        let bottom = min(
+            self.height_blocks,
+            y + height + PREFETCH_MARGIN + if dy > 0 { ahead_y } else { 0 },
+        );
+
+        let centre_x = (x + width / 2) as i64;
+        let centre_y = (y + height / 2) as i64;
+        let mut wanted = vec![];
+        for block_y in top..bottom {
+            for block_x in left..right {
+                let cached = self.record_cache.peek(&(block_x, block_y)).is_some();
+                if !cached && !self.pending.contains_key(&(block_x, block_y)) {
+                    wanted.push((block_x, block_y));
+                }
+            }
+        }
+        wanted.sort_by_key(|&(block_x, block_y)| {
+            let distance_x = block_x as i64 - centre_x;
+            let distance_y = block_y as i64 - centre_y;
+            distance_x * distance_x + distance_y * distance_y
+        });
+        // Asking for more than we can hold would just evict what's on screen
+        wanted.truncate(self.record_cache.stats().capacity / 2);
+        for (block_x, block_y) in wanted {
+            self.request(block_x, block_y);
+        }
+    }
+
+    pub fn receive_prefetched(&mut self) -> Vec<(u32, u32)> {
+        let received = match self.prefetcher {
+            Some(ref prefetcher) => prefetcher.receive(),
+            None => return vec![],
+        };
+        let mut arrived = vec![];
+        for prefetched in received {
+            let coords = (prefetched.x, prefetched.y);
+            // Asked for before patches were switched on or off, and asked for again since
+            if prefetched.patched != self.patched {
+                continue;
+            }
+            self.pending.remove(&coords);
+            // The prefetcher only knows what's on disk, so anything near an edit is rebuilt here
+            let record = if self.depends_on_overrides(coords.0, coords.1) {
+                self.load_record(coords.0, coords.1)
+            } else {
+                prefetched.record
+            };
+            self.record_cache.insert(coords, record.map(Rc::new));
+            arrived.push(coords);
+        }
+        arrived
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FacetCache;
+    use map::writer::{block_cells, write_map, write_statics};
+    use std::time::Instant;
+    use std::{env, fs, process};
+    use uorustlibs::map::{Block, Cell, MapReader, StaticLocation, StaticReader};
+
+    fn block(altitude: i8) -> Block {
+        Block {
+            header: 0,
+            cells: [Cell {
+                graphic: 3,
+                altitude,
+            }; 64],
+        }
+    }
+
+    // Reading a screen's worth of blocks through a facet cache that already holds them all,
+    // against copying each block and its altitudes out the way read_block used to. Run with
+    // --ignored --nocapture.
+    #[test]
+    #[ignore]
+    fn benchmark_cached_reads() {
+        let (width, height, iterations) = (12, 12, 1000);
+        let directory = env::temp_dir().join(format!("facet_cache_bench_{}", process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let (map, index, statics) = (
+            directory.join("map.mul"),
+            directory.join("staidx.mul"),
+            directory.join("statics.mul"),
+        );
+        write_map(&map, width, height, |x, y| {
+            block_cells(&block((x + y) as i8))
+        })
+        .unwrap();
+        write_statics(&index, &statics, width, height, |_, _| {
+            (0..32)
+                .map(|index| StaticLocation {
+                    object_id: index,
+                    x: (index % 8) as u8,
+                    y: (index / 8) as u8,
+                    altitude: 0,
+                    unknown: 0,
+                })
+                .collect()
+        })
+        .unwrap();
+        let mut cache = FacetCache::new(
+            MapReader::new(&map, width, height).unwrap(),
+            StaticReader::new(&index, &statics, width, height).unwrap(),
+            width,
+            height,
+        );
+        for y in 0..height {
+            for x in 0..width {
+                assert!(cache.read_block(x, y).is_some());
+            }
+        }
+
+        let start = Instant::now();
+        for _ in 0..iterations {
+            for y in 0..height {
+                for x in 0..width {
+                    drop(cache.read_block(x, y));
+                }
+            }
+        }
+        let shared = start.elapsed();
+
+        let start = Instant::now();
+        for _ in 0..iterations {
+            for y in 0..height {
+                for x in 0..width {
+                    let copied = cache.read_block(x, y).map(|record| {
+                        (
+                            record.block,
+                            record.statics.clone(),
+                            record.altitudes.to_vec(),
+                        )
+                    });
+                    drop(copied);
+                }
+            }
+        }
+        let copied = start.elapsed();
+        println!(
+            "{} frames of {} cached reads: {:?} shared, {:?} with copies",
+            iterations,
+            width * height,
+            shared,
+            copied
+        );
+        fs::remove_dir_all(&directory).unwrap();
     }
 }
@@ -0,0 +1,88 @@
+use caches::lru_cache::LruCache;
+use image::RgbaImage;
+use std::fs::File;
+use std::path::Path;
+use std::rc::Rc;
+use uorustlibs::art::{Art, ArtReader};
+use uorustlibs::texmaps::TexMapsReader;
+use uorustlibs::tiledata::{MapTileData, StaticTileData, TileDataReader};
+
+// Entry counts like the art cache's, so large statics take more memory than small ones
+pub const DEFAULT_IMAGE_TILE_CAPACITY: usize = 2048;
+pub const DEFAULT_IMAGE_STATIC_CAPACITY: usize = 4096;
+pub const DEFAULT_IMAGE_TEXMAP_CAPACITY: usize = 1024;
+
+// Like the art and texmap caches, but keeps plain images in memory, so it works without a
+// graphics context
+pub struct ImageArtCache {
+    tile_cache: LruCache<u32, Option<(Rc<RgbaImage>, MapTileData)>>,
+    static_cache: LruCache<u32, Option<(Rc<RgbaImage>, StaticTileData)>>,
+    texmap_cache: LruCache<u32, Option<Rc<RgbaImage>>>,
+    reader: ArtReader<File>,
+    tiledata_reader: TileDataReader<File>,
+    texmaps_reader: TexMapsReader<File>,
+}
+
+impl ImageArtCache {
+    pub fn new() -> ImageArtCache {
+        let reader = ArtReader::new(
+            &Path::new("./assets/artidx.mul"),
+            &Path::new("./assets/art.mul"),
+        )
+        .expect("Could not load art");
+        let tiledata_reader = TileDataReader::new(&Path::new("./assets/tiledata.mul"))
+            .expect("Could not load tiledata");
+        let texmaps_reader = TexMapsReader::new(
+            &Path::new("./assets/texidx.mul"),
+            &Path::new("./assets/texmaps.mul"),
+        )
+        .expect("Could not load texmaps");
+        ImageArtCache {
+            tile_cache: LruCache::new(DEFAULT_IMAGE_TILE_CAPACITY),
+            static_cache: LruCache::new(DEFAULT_IMAGE_STATIC_CAPACITY),
+            texmap_cache: LruCache::new(DEFAULT_IMAGE_TEXMAP_CAPACITY),
+            reader,
+            tiledata_reader,
+            texmaps_reader,
+        }
+    }
+
+    pub fn read_tile(&mut self, id: u32) -> Option<(Rc<RgbaImage>, MapTileData)> {
+        if self.tile_cache.get(&id).is_none() {
+            let entry = match (
+                self.reader.read_tile(id),
+                self.tiledata_reader.read_map_tile_data(id),
+            ) {
+                (Ok(tile), Ok(tiledata)) => Some((Rc::new(tile.to_image()), tiledata)),
+                _ => None,
+            };
+            self.tile_cache.insert(id, entry);
+        }
+        self.tile_cache.peek(&id).unwrap().clone()
+    }
+
+    pub fn read_static(&mut self, id: u32) -> Option<(Rc<RgbaImage>, StaticTileData)> {
+        if self.static_cache.get(&id).is_none() {
+            let entry = match (
+                self.reader.read_static(id),
+                self.tiledata_reader.read_static_tile_data(id),
+            ) {
+                (Ok(tile), Ok(tiledata)) => Some((Rc::new(tile.to_image()), tiledata)),
+                _ => None,
+            };
+            self.static_cache.insert(id, entry);
+        }
+        self.static_cache.peek(&id).unwrap().clone()
+    }
+
+    pub fn read_texmap(&mut self, id: u32) -> Option<Rc<RgbaImage>> {
+        if self.texmap_cache.get(&id).is_none() {
+            let entry = match self.texmaps_reader.read(id) {
+                Ok(texmap) => Some(Rc::new(texmap.to_image())),
+                Err(_) => None,
+            };
+            self.texmap_cache.insert(id, entry);
+        }
+        self.texmap_cache.peek(&id).unwrap().clone()
+    }
+}
@@ -0,0 +1,47 @@
+use caches::facet_cache::BlockRecord;
+use caches::lru_cache::{CacheStats, LruCache};
+use map::render::LandMeshes;
+use std::rc::Rc;
+
+// Blocks, not bytes, though every block's meshes are about the same size
+pub const DEFAULT_LAND_MESH_CAPACITY: usize = 128;
+
+// Built land geometry by block, so stretched land isn't rebuilt every frame. Each entry keeps
+// the record it was built from, and anything that changes a block's land or altitudes hands
+// out a new record, so a mismatch means the entry is stale.
+pub struct LandMeshCache {
+    cache: LruCache<(u32, u32), (Rc<BlockRecord>, Rc<LandMeshes>)>,
+}
+
+impl LandMeshCache {
+    pub fn new() -> LandMeshCache {
+        LandMeshCache::with_capacity(DEFAULT_LAND_MESH_CAPACITY)
+    }
+
+    pub fn with_capacity(capacity: usize) -> LandMeshCache {
+        LandMeshCache {
+            cache: LruCache::new(capacity),
+        }
+    }
+
+    pub fn get(&mut self, block: (u32, u32), record: &Rc<BlockRecord>) -> Option<Rc<LandMeshes>> {
+        match self.cache.get(&block) {
+            Some(&(ref built_from, ref meshes)) if Rc::ptr_eq(built_from, record) => {
+                Some(meshes.clone())
+            }
+            _ => None,
+        }
+    }
+
+    pub fn insert(&mut self, block: (u32, u32), record: Rc<BlockRecord>, meshes: Rc<LandMeshes>) {
+        self.cache.insert(block, (record, meshes));
+    }
+
+    pub fn flush(&mut self) {
+        self.cache.clear();
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.cache.stats()
+    }
+}
@@ -0,0 +1,189 @@
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::hash::Hash;
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+    pub len: usize,
+    pub capacity: usize,
+}
+
+impl fmt::Display for CacheStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}/{} hits {} misses {} evictions {}",
+            self.len, self.capacity, self.hits, self.misses, self.evictions
+        )
+    }
+}
+
+// Entries remember the tick they were last touched on, and the stalest one is evicted
+// once we're at capacity. The ticks are kept in order alongside, so finding it is cheap.
+pub struct LruCache<K, V> {
+    entries: HashMap<K, (V, u64)>,
+    order: BTreeMap<u64, K>,
+    capacity: usize,
+    tick: u64,
+    hits: u64,
+    misses: u64,
+    evictions: u64,
+}
+
+impl<K: Hash + Eq + Clone, V> LruCache<K, V> {
+    pub fn new(capacity: usize) -> LruCache<K, V> {
+        LruCache {
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+            capacity: capacity.max(1),
+            tick: 0,
+            hits: 0,
+            misses: 0,
+            evictions: 0,
+        }
+    }
+
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        self.tick += 1;
+        let tick = self.tick;
+        match self.entries.get_mut(key) {
+            Some(entry) => {
+                self.hits += 1;
+                let key = self
+                    .order
+                    .remove(&entry.1)
+                    .expect("Entry missing from order");
+                self.order.insert(tick, key);
+                entry.1 = tick;
+                Some(&entry.0)
+            }
+            None => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    // Looks up without touching the recency or the statistics
+    pub fn peek(&self, key: &K) -> Option<&V> {
+        self.entries.get(key).map(|(value, _)| value)
+    }
+
+    pub fn insert(&mut self, key: K, value: V) -> &V {
+        self.tick += 1;
+        if !self.entries.contains_key(&key) {
+            while self.entries.len() >= self.capacity {
+                self.evict_oldest();
+            }
+        }
+        let tick = self.tick;
+        self.order.insert(tick, key.clone());
+        match self.entries.entry(key) {
+            Entry::Occupied(mut entry) => {
+                let (_, touched) = entry.insert((value, tick));
+                self.order.remove(&touched);
+                &entry.into_mut().0
+            }
+            Entry::Vacant(entry) => &entry.insert((value, tick)).0,
+        }
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let (value, touched) = self.entries.remove(key)?;
+        self.order.remove(&touched);
+        Some(value)
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits,
+            misses: self.misses,
+            evictions: self.evictions,
+            len: self.entries.len(),
+            capacity: self.capacity,
+        }
+    }
+
+    fn evict_oldest(&mut self) {
+        let oldest = self.order.keys().next().cloned();
+        if let Some(tick) = oldest {
+            let key = self.order.remove(&tick).unwrap();
+            self.entries.remove(&key);
+            self.evictions += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LruCache;
+
+    #[test]
+    fn counts_hits_and_misses() {
+        let mut cache = LruCache::new(2);
+        cache.insert(1, "one");
+        assert_eq!(cache.get(&1), Some(&"one"));
+        assert_eq!(cache.get(&2), None);
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.len), (1, 1, 1));
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let mut cache = LruCache::new(2);
+        cache.insert(1, "one");
+        cache.insert(2, "two");
+        cache.get(&1);
+        cache.insert(3, "three");
+        assert_eq!(cache.peek(&2), None);
+        assert_eq!(cache.peek(&1), Some(&"one"));
+        assert_eq!(cache.peek(&3), Some(&"three"));
+        cache.insert(4, "four");
+        assert_eq!(cache.peek(&1), None);
+        assert_eq!(cache.stats().evictions, 2);
+    }
+
+    #[test]
+    fn reinserting_refreshes_without_evicting() {
+        let mut cache = LruCache::new(2);
+        cache.insert(1, "one");
+        cache.insert(2, "two");
+        cache.insert(1, "uno");
+        cache.insert(3, "three");
+        assert_eq!(cache.peek(&1), Some(&"uno"));
+        assert_eq!(cache.peek(&2), None);
+        assert_eq!(cache.stats().evictions, 1);
+    }
+
+    #[test]
+    fn peek_leaves_recency_alone() {
+        let mut cache = LruCache::new(2);
+        cache.insert(1, "one");
+        cache.insert(2, "two");
+        assert_eq!(cache.peek(&1), Some(&"one"));
+        cache.insert(3, "three");
+        assert_eq!(cache.peek(&1), None);
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses), (0, 0));
+    }
+
+    #[test]
+    fn removed_entries_are_not_evicted_later() {
+        let mut cache = LruCache::new(2);
+        cache.insert(1, "one");
+        cache.insert(2, "two");
+        assert_eq!(cache.remove(&1), Some("one"));
+        cache.insert(3, "three");
+        assert_eq!(cache.peek(&2), Some(&"two"));
+        assert_eq!(cache.stats().evictions, 0);
+    }
+}
@@ -1,3 +1,10 @@
 pub mod art_cache;
+pub mod art_mask_cache;
 pub mod facet_cache;
+pub mod image_art_cache;
+pub mod land_mesh_cache;
+pub mod lru_cache;
+pub mod prefetcher;
 pub mod texmap_cache;
+pub mod tiledata_cache;
+pub mod walk_mesh_cache;
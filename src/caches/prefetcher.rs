@@ -0,0 +1,126 @@
+use caches::facet_cache::{load_land, load_statics, BlockRecord};
+use caches::lru_cache::LruCache;
+use map::patches::{patch_index, Patches};
+use std::fs::File;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
+use std::thread;
+use uorustlibs::map::{Block, MapReader, StaticLocation, StaticReader};
+
+// Enough to hold the neighbours of a few rows of requests, which is all we need for altitudes
+const WORKER_CAPACITY: usize = 1024;
+
+pub struct PrefetchedBlock {
+    pub x: u32,
+    pub y: u32,
+    pub patched: bool,
+    pub record: Option<BlockRecord>,
+}
+
+pub struct Prefetcher {
+    requests: Sender<(u32, u32, bool)>,
+    responses: Receiver<PrefetchedBlock>,
+}
+
+struct Worker {
+    map_reader: MapReader,
+    static_reader: StaticReader<File>,
+    width_blocks: u32,
+    height_blocks: u32,
+    blocks: LruCache<(u32, u32), Option<Block>>,
+    patches: Arc<Patches>,
+}
+
+impl Worker {
+    // Only base blocks are cached, patches go over the top as they're read
+    fn read_land(&mut self, x: u32, y: u32, patched: bool) -> Option<Block> {
+        if x >= self.width_blocks || y >= self.height_blocks {
+            return None;
+        }
+        if patched {
+            if let Some(block) = self.patches.land(patch_index(x, y, self.height_blocks)) {
+                return Some(block.clone());
+            }
+        }
+        if self.blocks.get(&(x, y)).is_none() {
+            let block = load_land(&mut self.map_reader, x, y);
+            self.blocks.insert((x, y), block);
+        }
+        self.blocks.peek(&(x, y)).unwrap().clone()
+    }
+
+    fn read_statics(&mut self, x: u32, y: u32, patched: bool) -> Vec<StaticLocation> {
+        if patched {
+            if let Some(statics) = self.patches.statics(patch_index(x, y, self.height_blocks)) {
+                return statics.clone();
+            }
+        }
+        load_statics(&mut self.static_reader, x, y)
+    }
+
+    fn process(&mut self, x: u32, y: u32, patched: bool) -> PrefetchedBlock {
+        let record = match self.read_land(x, y, patched) {
+            Some(block) => {
+                let statics = self.read_statics(x, y, patched);
+                let block_x2 = self.read_land(x + 1, y, patched);
+                let block_y2 = self.read_land(x, y + 1, patched);
+                let block_x2y2 = self.read_land(x + 1, y + 1, patched);
+                Some(BlockRecord::new(
+                    block,
+                    statics,
+                    block_x2.as_ref(),
+                    block_y2.as_ref(),
+                    block_x2y2.as_ref(),
+                ))
+            }
+            None => None,
+        };
+        PrefetchedBlock {
+            x,
+            y,
+            patched,
+            record,
+        }
+    }
+}
+
+impl Prefetcher {
+    pub fn spawn(
+        map_reader: MapReader,
+        static_reader: StaticReader<File>,
+        width_blocks: u32,
+        height_blocks: u32,
+        patches: Arc<Patches>,
+    ) -> Prefetcher {
+        let (request_sender, request_receiver) = channel::<(u32, u32, bool)>();
+        let (response_sender, response_receiver) = channel();
+        let mut worker = Worker {
+            map_reader,
+            static_reader,
+            width_blocks,
+            height_blocks,
+            blocks: LruCache::new(WORKER_CAPACITY),
+            patches,
+        };
+        thread::spawn(move || {
+            // Finishes once the cache drops its end of either channel
+            for (x, y, patched) in request_receiver.iter() {
+                if response_sender.send(worker.process(x, y, patched)).is_err() {
+                    break;
+                }
+            }
+        });
+        Prefetcher {
+            requests: request_sender,
+            responses: response_receiver,
+        }
+    }
+
+    pub fn request(&self, x: u32, y: u32, patched: bool) -> bool {
+        self.requests.send((x, y, patched)).is_ok()
+    }
+
+    pub fn receive(&self) -> Vec<PrefetchedBlock> {
+        self.responses.try_iter().collect()
+    }
+}
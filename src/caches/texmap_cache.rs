@@ -1,44 +1,55 @@
+use caches::lru_cache::{CacheStats, LruCache};
 use ggez::graphics::Image;
 use ggez::Context;
 use image_convert::image_to_surface;
-use std::collections::HashMap;
 use std::fs::File;
 use std::path::Path;
 use uorustlibs::texmaps::TexMapsReader;
 
+// Texmaps are 64x64 or 128x128, so a count is close enough to a memory bound here
+pub const DEFAULT_TEXMAP_CAPACITY: usize = 1024;
+
 pub struct TexMapCache {
-    tex_map_cache: HashMap<u32, Option<Image>>,
+    tex_map_cache: LruCache<u32, Option<Image>>,
     reader: TexMapsReader<File>,
 }
 
 impl TexMapCache {
     pub fn new() -> TexMapCache {
+        TexMapCache::with_capacity(DEFAULT_TEXMAP_CAPACITY)
+    }
+
+    pub fn with_capacity(capacity: usize) -> TexMapCache {
         let reader = TexMapsReader::new(
             &Path::new("./assets/texidx.mul"),
             &Path::new("./assets/texmaps.mul"),
         )
         .expect("Could not load texmaps");
         TexMapCache {
-            tex_map_cache: HashMap::new(),
+            tex_map_cache: LruCache::new(capacity),
             reader,
         }
     }
 
     pub fn read_texmap(&mut self, ctx: &mut Context, id: u32) -> &Option<Image> {
-        if self.tex_map_cache.contains_key(&id) {
-            self.tex_map_cache.get(&id).unwrap()
-        } else {
-            match self.reader.read(id) {
+        if self.tex_map_cache.get(&id).is_none() {
+            let entry = match self.reader.read(id) {
                 Ok(tile) => {
                     let image = tile.to_image();
-                    let tile_image = image_to_surface(ctx, &image);
-                    self.tex_map_cache.insert(id, Some(tile_image));
-                }
-                Err(_) => {
-                    self.tex_map_cache.insert(id, None);
+                    Some(image_to_surface(ctx, &image))
                 }
-            }
-            self.tex_map_cache.get(&id).unwrap()
+                Err(_) => None,
+            };
+            self.tex_map_cache.insert(id, entry);
         }
+        self.tex_map_cache.peek(&id).unwrap()
+    }
+
+    pub fn flush(&mut self) {
+        self.tex_map_cache.clear();
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.tex_map_cache.stats()
     }
 }
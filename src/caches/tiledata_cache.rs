@@ -0,0 +1,53 @@
+use caches::lru_cache::LruCache;
+use std::fs::File;
+use std::path::Path;
+use uorustlibs::tiledata::{MapTileData, StaticTileData, TileDataReader};
+
+// Entries are a few dozen bytes each, so the count is what matters
+pub const DEFAULT_TILEDATA_CAPACITY: usize = 8192;
+
+// Tiledata without the art, for places that only need flags and heights
+pub struct TileDataCache {
+    land_cache: LruCache<u32, Option<MapTileData>>,
+    static_cache: LruCache<u32, Option<StaticTileData>>,
+    reader: TileDataReader<File>,
+}
+
+impl TileDataCache {
+    pub fn new() -> TileDataCache {
+        let reader = TileDataReader::new(&Path::new("./assets/tiledata.mul"))
+            .expect("Could not load tiledata");
+        TileDataCache {
+            land_cache: LruCache::new(DEFAULT_TILEDATA_CAPACITY),
+            static_cache: LruCache::new(DEFAULT_TILEDATA_CAPACITY),
+            reader,
+        }
+    }
+
+    pub fn read_land(&mut self, id: u32) -> Option<&MapTileData> {
+        if self.land_cache.get(&id).is_none() {
+            let entry = self.reader.read_map_tile_data(id).ok();
+            self.land_cache.insert(id, entry);
+        }
+        self.land_cache.peek(&id).unwrap().as_ref()
+    }
+
+    pub fn read_static(&mut self, id: u32) -> Option<&StaticTileData> {
+        if self.static_cache.get(&id).is_none() {
+            let entry = self.reader.read_static_tile_data(id).ok();
+            self.static_cache.insert(id, entry);
+        }
+        self.static_cache.peek(&id).unwrap().as_ref()
+    }
+
+    pub fn land_flags(&mut self, id: u32) -> u32 {
+        self.read_land(id).map(|data| data.flags).unwrap_or(0)
+    }
+
+    // Flags and height are all most callers want, and copying them out avoids holding a borrow
+    pub fn static_flags_and_height(&mut self, id: u32) -> (u32, u8) {
+        self.read_static(id)
+            .map(|data| (data.flags, data.height))
+            .unwrap_or((0, 0))
+    }
+}
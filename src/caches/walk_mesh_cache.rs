@@ -0,0 +1,53 @@
+use caches::facet_cache::BlockRecord;
+use caches::lru_cache::{CacheStats, LruCache};
+use ggez::graphics::Mesh;
+use std::rc::Rc;
+
+type Records = Vec<Option<Rc<BlockRecord>>>;
+
+// Walkability overlays by block. Cells on a block's edges depend on the blocks around it, so
+// each entry keeps every record it was worked out from, and goes stale as soon as any of them
+// is replaced or turns up.
+pub struct WalkMeshCache {
+    cache: LruCache<(u32, u32), (Records, Rc<Mesh>)>,
+}
+
+fn same_records(a: &[Option<Rc<BlockRecord>>], b: &[Option<Rc<BlockRecord>>]) -> bool {
+    a.len() == b.len()
+        && a.iter().zip(b.iter()).all(|pair| match pair {
+            (Some(a), Some(b)) => Rc::ptr_eq(a, b),
+            (None, None) => true,
+            _ => false,
+        })
+}
+
+impl WalkMeshCache {
+    pub fn with_capacity(capacity: usize) -> WalkMeshCache {
+        WalkMeshCache {
+            cache: LruCache::new(capacity),
+        }
+    }
+
+    pub fn get(
+        &mut self,
+        block: (u32, u32),
+        records: &[Option<Rc<BlockRecord>>],
+    ) -> Option<Rc<Mesh>> {
+        match self.cache.get(&block) {
+            Some((built_from, mesh)) if same_records(built_from, records) => Some(mesh.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn insert(&mut self, block: (u32, u32), records: Records, mesh: Rc<Mesh>) {
+        self.cache.insert(block, (records, mesh));
+    }
+
+    pub fn flush(&mut self) {
+        self.cache.clear();
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.cache.stats()
+    }
+}
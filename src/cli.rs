@@ -0,0 +1,205 @@
+use engine::EngineData;
+use image::Rgba;
+use map::compare::compare_report;
+use map::export::EXPORT_DIRECTORY;
+use map::import::{import_facet, TerrainTable};
+use map::merge::{merge_patches, MERGE_DIRECTORY};
+use map::poster::Poster;
+use map::render::{Ceiling, RenderOptions};
+use map::software_render::export_neighbourhood;
+use map::{map_id_to_facet, IMPORTED_MAP, MAP_DETAILS, MAP_SIZES};
+use std::io::{self, Result};
+use std::path::{Path, PathBuf};
+
+const SIZE_NAMES: [&'static str; 5] = ["sosaria", "ilshenar", "malas", "tokuno", "termur"];
+
+fn usage(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message.to_string())
+}
+
+fn import(args: &[String]) -> Result<()> {
+    if args.len() < 3 {
+        return Err(usage(
+            "Usage: import <heightmap.png> <terrain.png> <sosaria|ilshenar|malas|tokuno|termur> [terrain table]",
+        ));
+    }
+    let size = SIZE_NAMES
+        .iter()
+        .position(|&name| name == args[2].to_lowercase())
+        .map(|index| MAP_SIZES[index])
+        .ok_or_else(|| usage("Unknown facet size"))?;
+    let mut terrain = match args.get(3) {
+        Some(path) => TerrainTable::load(Path::new(path))?,
+        None => TerrainTable::default(),
+    };
+    let (map, idx, statics) = IMPORTED_MAP;
+    import_facet(
+        Path::new(&args[0]),
+        Path::new(&args[1]),
+        &mut terrain,
+        size,
+        (Path::new(map), Path::new(idx), Path::new(statics)),
+    )?;
+    println!("Wrote {}, Tab past the last facet to view it", map);
+    Ok(())
+}
+
+fn merge(args: &[String]) -> Result<()> {
+    let map_id = args
+        .get(0)
+        .and_then(|id| id.parse::<u8>().ok())
+        .filter(|&id| (id as usize) < MAP_DETAILS.len())
+        .ok_or_else(|| usage("Usage: merge <facet id> [output directory]"))?;
+    let directory = args
+        .get(1)
+        .map_or(MERGE_DIRECTORY, |directory| directory.as_str());
+    let report = merge_patches(map_id, Path::new(directory))?;
+    println!(
+        "Wrote merged facet to {}, report in {}",
+        directory,
+        report.display()
+    );
+    Ok(())
+}
+
+fn compare(args: &[String]) -> Result<()> {
+    let message = "Usage: compare <old data directory> <new data directory> [facet id]";
+    if args.len() < 2 {
+        return Err(usage(message));
+    }
+    let map_id = match args.get(2) {
+        Some(id) => id
+            .parse::<u8>()
+            .ok()
+            .filter(|&id| (id as usize) < MAP_DETAILS.len())
+            .ok_or_else(|| usage(message))?,
+        None => 0,
+    };
+    let stem = compare_report(
+        Path::new(&args[0]),
+        Path::new(&args[1]),
+        map_id,
+        Path::new(EXPORT_DIRECTORY),
+    )?;
+    println!("Wrote {}.txt and {}.json", stem.display(), stem.display());
+    Ok(())
+}
+
+// Draws a block and its neighbours without opening a window
+fn render(args: &[String]) -> Result<()> {
+    let message = "Usage: render <facet id> <block x> <block y>";
+    let numbers = args
+        .iter()
+        .take(3)
+        .map(|arg| arg.parse::<u32>().ok())
+        .collect::<Option<Vec<u32>>>()
+        .filter(|numbers| numbers.len() == 3)
+        .ok_or_else(|| usage(message))?;
+    let map_id = numbers[0] as u8;
+    if numbers[0] as usize >= MAP_DETAILS.len() {
+        return Err(usage(message));
+    }
+    let mut facet = map_id_to_facet(map_id);
+    if numbers[1] >= facet.width_blocks() || numbers[2] >= facet.height_blocks() {
+        return Err(usage("Block is outside the facet"));
+    }
+    let path = export_neighbourhood(
+        &mut facet,
+        map_id,
+        numbers[1],
+        numbers[2],
+        Path::new(EXPORT_DIRECTORY),
+    )?;
+    println!("Wrote {}", path.display());
+    Ok(())
+}
+
+// Takes RRGGBB or RRGGBBAA
+fn parse_colour(hex: &str) -> Option<Rgba<u8>> {
+    if (hex.len() != 6 && hex.len() != 8) || !hex.is_ascii() {
+        return None;
+    }
+    let channel = |index: usize| {
+        hex.get(index * 2..index * 2 + 2)
+            .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+    };
+    let alpha = if hex.len() == 8 { channel(3)? } else { 255 };
+    Some(Rgba([channel(0)?, channel(1)?, channel(2)?, alpha]))
+}
+
+fn poster(args: &[String]) -> Result<()> {
+    let message = "Usage: poster <facet id> <x> <y> <width> <height> [--ceiling <z>] [--no-statics] [--background <rrggbb[aa]>]";
+    let numbers = args
+        .iter()
+        .take(5)
+        .map(|arg| arg.parse::<u32>().ok())
+        .collect::<Option<Vec<u32>>>()
+        .filter(|numbers| numbers.len() == 5)
+        .ok_or_else(|| usage(message))?;
+    if numbers[0] as usize >= MAP_DETAILS.len() {
+        return Err(usage(message));
+    }
+    let map_id = numbers[0] as u8;
+    let mut poster = Poster {
+        area: (numbers[1], numbers[2], numbers[3], numbers[4]),
+        options: RenderOptions::default(),
+        background: Rgba([0, 0, 0, 255]),
+    };
+    let mut options = args[5..].iter();
+    while let Some(option) = options.next() {
+        match option.as_str() {
+            "--ceiling" => {
+                let z = options
+                    .next()
+                    .and_then(|z| z.parse::<i8>().ok())
+                    .ok_or_else(|| usage(message))?;
+                poster.options.ceiling = Some(Ceiling {
+                    z,
+                    hide_roofs: true,
+                    translucent: false,
+                });
+            }
+            "--no-statics" => poster.options.statics = false,
+            "--background" => {
+                poster.background = options
+                    .next()
+                    .and_then(|hex| parse_colour(hex))
+                    .ok_or_else(|| usage(message))?;
+            }
+            _ => return Err(usage(message)),
+        }
+    }
+    let mut facet = map_id_to_facet(map_id);
+    let path = poster.export(&mut facet, map_id, Path::new(EXPORT_DIRECTORY))?;
+    println!("Wrote {}", path.display());
+    Ok(())
+}
+
+// Options for the viewer itself, rather than commands
+pub fn engine_data(args: &[String]) -> EngineData {
+    let mut engine_data = EngineData::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--compare" {
+            engine_data.compare_directory = args.next().map(PathBuf::from);
+        }
+    }
+    engine_data
+}
+
+// Returns None when there's no command, and the viewer should start as normal
+pub fn run(args: &[String]) -> Option<Result<()>> {
+    let (command, rest) = args.split_first()?;
+    if command.starts_with("--") {
+        return None;
+    }
+    let result = match command.as_str() {
+        "import" => import(rest),
+        "merge" => merge(rest),
+        "compare" => compare(rest),
+        "render" => render(rest),
+        "poster" => poster(rest),
+        _ => Err(usage(&format!("Unknown command {}", command))),
+    };
+    Some(result)
+}
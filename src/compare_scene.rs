@@ -0,0 +1,171 @@
+use caches::art_cache::ArtCache;
+use caches::land_mesh_cache::LandMeshCache;
+use caches::texmap_cache::TexMapCache;
+use cgmath::Point2;
+use engine::EngineData;
+use ggez::event::{KeyCode, KeyMods};
+use ggez::graphics::{self, Text};
+use ggez::{Context, GameResult};
+use map::compare::{compare_block, BlockChange};
+use map::render::{draw_neighbourhood, NEIGHBOURHOOD};
+use map::{facet_in, map_id_to_facet, Facet};
+use scene::{BoxedScene, Scene, SceneChangeEvent, SceneName};
+use std::path::PathBuf;
+
+pub struct CompareScene {
+    art_cache: ArtCache,
+    texmap_cache: TexMapCache,
+    land_meshes: LandMeshCache,
+    old: Option<Facet>,
+    new: Facet,
+    directory: PathBuf,
+    showing_old: bool,
+    change: BlockChange,
+    map_id: u8,
+    x: u32,
+    y: u32,
+    exiting: bool,
+}
+
+impl<'a> CompareScene {
+    pub fn new(
+        _ctx: &mut Context,
+        engine_data: &EngineData,
+    ) -> BoxedScene<'a, SceneName, EngineData> {
+        let (map_id, x, y) = engine_data.compare_block.unwrap_or((0, 0, 0));
+        let directory = engine_data
+            .compare_directory
+            .clone()
+            .unwrap_or_else(|| PathBuf::from("."));
+        let mut scene = Box::new(CompareScene {
+            art_cache: ArtCache::new(),
+            texmap_cache: TexMapCache::new(),
+            land_meshes: LandMeshCache::new(),
+            old: facet_in(&directory, map_id),
+            new: map_id_to_facet(map_id),
+            directory,
+            showing_old: true,
+            change: BlockChange::default(),
+            map_id,
+            x,
+            y,
+            exiting: false,
+        });
+        scene.compare();
+        scene
+    }
+
+    fn compare(&mut self) {
+        let (x, y) = (self.x, self.y);
+        self.change = match self.old {
+            Some(ref mut old) => {
+                let old_land = old.read_land_uncached(x, y);
+                let new_land = self.new.read_land_uncached(x, y);
+                compare_block(
+                    x,
+                    y,
+                    old_land.as_ref(),
+                    new_land.as_ref(),
+                    &old.read_statics(x, y),
+                    &self.new.read_statics(x, y),
+                )
+            }
+            None => BlockChange::default(),
+        };
+    }
+
+    fn move_block(&mut self, dx: i32, dy: i32) {
+        let x = self.x as i32 + dx;
+        let y = self.y as i32 + dy;
+        if x >= 0
+            && y >= 0
+            && (x as u32) < self.new.width_blocks()
+            && (y as u32) < self.new.height_blocks()
+        {
+            self.x = x as u32;
+            self.y = y as u32;
+            self.compare();
+        }
+    }
+}
+
+impl Scene<SceneName, EngineData> for CompareScene {
+    fn draw(&mut self, ctx: &mut Context, _engine_data: &mut EngineData) -> GameResult<()> {
+        graphics::clear(ctx, graphics::BLACK);
+        let label = match self.old {
+            Some(ref mut old) => {
+                let screen = graphics::screen_coordinates(ctx);
+                graphics::set_screen_coordinates(ctx, NEIGHBOURHOOD)?;
+                let facet = if self.showing_old { old } else { &mut self.new };
+                draw_neighbourhood(
+                    ctx,
+                    &mut self.art_cache,
+                    &mut self.texmap_cache,
+                    &mut self.land_meshes,
+                    facet,
+                    self.x,
+                    self.y,
+                )?;
+                graphics::set_screen_coordinates(ctx, screen)?;
+                format!(
+                    "Map {} block {},{} ({},{})\n{} - Space to flip\n{} land cells changed, {} statics added, {} removed",
+                    self.map_id,
+                    self.x,
+                    self.y,
+                    self.x * 8,
+                    self.y * 8,
+                    if self.showing_old {
+                        format!("Old, from {}", self.directory.display())
+                    } else {
+                        "New".to_string()
+                    },
+                    self.change.land_cells,
+                    self.change.statics_added,
+                    self.change.statics_removed
+                )
+            }
+            None => format!(
+                "No facet {} to compare in {}",
+                self.map_id,
+                self.directory.display()
+            ),
+        };
+        graphics::draw(
+            ctx,
+            &Text::new(label),
+            (Point2::new(0.0, 0.0), graphics::WHITE),
+        )?;
+        Ok(())
+    }
+
+    fn key_down_event(
+        &mut self,
+        _ctx: &mut Context,
+        keycode: KeyCode,
+        _keymods: KeyMods,
+        _repeat: bool,
+        _engine_data: &mut EngineData,
+    ) {
+        match keycode {
+            KeyCode::Escape => self.exiting = true,
+            KeyCode::Space => self.showing_old = !self.showing_old,
+            KeyCode::Left => self.move_block(-1, 0),
+            KeyCode::Right => self.move_block(1, 0),
+            KeyCode::Up => self.move_block(0, -1),
+            KeyCode::Down => self.move_block(0, 1),
+            _ => (),
+        }
+    }
+
+    fn update(
+        &mut self,
+        _ctx: &mut Context,
+        _engine_data: &mut EngineData,
+    ) -> GameResult<Option<SceneChangeEvent<SceneName>>> {
+        if self.exiting {
+            Ok(Some(SceneChangeEvent::PopScene))
+        } else {
+            Ok(None)
+        }
+    }
+}
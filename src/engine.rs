@@ -1,4 +1,5 @@
 use anim_scene;
+use compare_scene;
 use font_scene;
 use ggez::event::{quit, EventHandler, KeyCode, KeyMods, MouseButton};
 use ggez::{graphics, timer, Context, GameError, GameResult};
@@ -9,21 +10,36 @@ use map_scene;
 use scene::{BoxedScene, SceneChangeEvent, SceneName, SceneStack};
 use skills_scene;
 use statics_scene;
+use stats_scene;
+use std::path::PathBuf;
 use texmaps_scene;
 use tile_scene;
 use title_scene;
 use world_scene;
 
+// Shared by every scene, so one scene can pick something for another to use
+#[derive(Default)]
+pub struct EngineData {
+    pub selected_tile: Option<u16>,
+    pub selected_static: Option<u16>,
+    // Another client's data directory, given with --compare, and the facet and block picked
+    // in it to look at
+    pub compare_directory: Option<PathBuf>,
+    pub compare_block: Option<(u8, u32, u32)>,
+}
+
 pub struct Engine<'a> {
-    scene_stack: Option<SceneStack<'a, SceneName, ()>>,
+    scene_stack: Option<SceneStack<'a, SceneName, EngineData>>,
+    engine_data: EngineData,
 }
 
 impl<'a> Engine<'a> {
-    pub fn new(_ctx: &mut Context) -> Engine<'a> {
+    pub fn new(_ctx: &mut Context, engine_data: EngineData) -> Engine<'a> {
         let mut scene_stack = SceneStack::new();
         scene_stack.push(title_scene::TitleScene::new());
         Engine {
             scene_stack: Some(scene_stack),
+            engine_data,
         }
     }
 
@@ -31,7 +47,7 @@ impl<'a> Engine<'a> {
         &mut self,
         ctx: &mut Context,
         scene_name: SceneName,
-    ) -> BoxedScene<'a, SceneName, ()> {
+    ) -> BoxedScene<'a, SceneName, EngineData> {
         match scene_name {
             SceneName::TitleScene => title_scene::TitleScene::new(),
             SceneName::SkillsScene => skills_scene::SkillsScene::new(),
@@ -45,6 +61,8 @@ impl<'a> Engine<'a> {
             SceneName::WorldScene => world_scene::WorldScene::new(),
             SceneName::FontScene => font_scene::FontScene::new(ctx),
             SceneName::MapDiffScene => map_diff_scene::MapDiffScene::new(ctx),
+            SceneName::StatsScene => stats_scene::StatsScene::new(ctx),
+            SceneName::CompareScene => compare_scene::CompareScene::new(ctx, &self.engine_data),
         }
     }
 }
@@ -59,7 +77,7 @@ impl<'a> EventHandler for Engine<'a> {
         if scene_stack.is_empty() {
             quit(ctx);
         } else {
-            let scene_event = scene_stack.update(ctx, &mut ())?;
+            let scene_event = scene_stack.update(ctx, &mut self.engine_data)?;
             match scene_event {
                 Some(SceneChangeEvent::PopScene) => {
                     scene_stack.pop();
@@ -82,7 +100,7 @@ impl<'a> EventHandler for Engine<'a> {
             .scene_stack
             .take()
             .ok_or_else(|| GameError::EventLoopError("Empty scene stack".to_owned()))?;
-        scene_stack.draw(ctx, &mut ())?;
+        scene_stack.draw(ctx, &mut self.engine_data)?;
         self.scene_stack = Some(scene_stack);
         graphics::present(ctx)?;
         timer::yield_now();
@@ -97,13 +115,41 @@ impl<'a> EventHandler for Engine<'a> {
         repeat: bool,
     ) {
         let mut scene_stack = self.scene_stack.take().expect("Empty scene stack");
-        scene_stack.key_down_event(ctx, keycode, keymods, repeat, &mut ());
+        scene_stack.key_down_event(ctx, keycode, keymods, repeat, &mut self.engine_data);
         self.scene_stack = Some(scene_stack);
     }
 
     fn mouse_button_down_event(&mut self, ctx: &mut Context, button: MouseButton, x: f32, y: f32) {
         let mut scene_stack = self.scene_stack.take().expect("Empty scene stack");
-        scene_stack.mouse_button_down_event(ctx, button, x, y, &mut ());
+        scene_stack.mouse_button_down_event(ctx, button, x, y, &mut self.engine_data);
         self.scene_stack = Some(scene_stack);
     }
+
+    fn mouse_button_up_event(&mut self, ctx: &mut Context, button: MouseButton, x: f32, y: f32) {
+        let mut scene_stack = self.scene_stack.take().expect("Empty scene stack");
+        scene_stack.mouse_button_up_event(ctx, button, x, y, &mut self.engine_data);
+        self.scene_stack = Some(scene_stack);
+    }
+
+    fn mouse_motion_event(&mut self, ctx: &mut Context, x: f32, y: f32, dx: f32, dy: f32) {
+        let mut scene_stack = self.scene_stack.take().expect("Empty scene stack");
+        scene_stack.mouse_motion_event(ctx, x, y, dx, dy, &mut self.engine_data);
+        self.scene_stack = Some(scene_stack);
+    }
+
+    fn mouse_wheel_event(&mut self, ctx: &mut Context, x: f32, y: f32) {
+        let mut scene_stack = self.scene_stack.take().expect("Empty scene stack");
+        scene_stack.mouse_wheel_event(ctx, x, y, &mut self.engine_data);
+        self.scene_stack = Some(scene_stack);
+    }
+
+    // Keeps one unit to a pixel, so scenes see more of themselves in a bigger window rather
+    // than a stretched one
+    fn resize_event(&mut self, ctx: &mut Context, width: f32, height: f32) {
+        if let Err(error) =
+            graphics::set_screen_coordinates(ctx, graphics::Rect::new(0.0, 0.0, width, height))
+        {
+            println!("Could not resize: {}", error);
+        }
+    }
 }
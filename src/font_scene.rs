@@ -1,4 +1,5 @@
 use cgmath::Point2;
+use engine::EngineData;
 use ggez::event::{KeyCode, KeyMods};
 use ggez::graphics::{self, Canvas, Color, DrawParam, Text};
 use ggez::{Context, GameResult};
@@ -20,7 +21,7 @@ pub struct FontScene {
 }
 
 impl<'a> FontScene {
-    pub fn new(ctx: &mut Context) -> BoxedScene<'a, SceneName, ()> {
+    pub fn new(ctx: &mut Context) -> BoxedScene<'a, SceneName, EngineData> {
         let mut scene = Box::new(FontScene {
             reader: FontReader::new(&Path::new("./assets/fonts.mul")),
             texture: None,
@@ -80,8 +81,8 @@ impl<'a> FontScene {
     }
 }
 
-impl Scene<SceneName, ()> for FontScene {
-    fn draw(&mut self, ctx: &mut Context, _engine_data: &mut ()) -> GameResult<()> {
+impl Scene<SceneName, EngineData> for FontScene {
+    fn draw(&mut self, ctx: &mut Context, _engine_data: &mut EngineData) -> GameResult<()> {
         match self.texture {
             Some(ref texture) => {
                 graphics::draw(ctx, texture, DrawParam::default())?;
@@ -94,7 +95,7 @@ impl Scene<SceneName, ()> for FontScene {
     fn update(
         &mut self,
         _ctx: &mut Context,
-        _engine_data: &mut (),
+        _engine_data: &mut EngineData,
     ) -> GameResult<Option<SceneChangeEvent<SceneName>>> {
         if self.exiting {
             Ok(Some(SceneChangeEvent::PopScene))
@@ -109,7 +110,7 @@ impl Scene<SceneName, ()> for FontScene {
         keycode: KeyCode,
         _keymods: KeyMods,
         _repeat: bool,
-        _engine_data: &mut (),
+        _engine_data: &mut EngineData,
     ) {
         match keycode {
             KeyCode::Escape => self.exiting = true,
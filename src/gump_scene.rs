@@ -1,4 +1,5 @@
 use cgmath::Point2;
+use engine::EngineData;
 use ggez::event::{KeyCode, KeyMods};
 use ggez::graphics::{self, Canvas, DrawParam, Text};
 use ggez::{Context, GameResult};
@@ -17,7 +18,7 @@ pub struct GumpScene {
 }
 
 impl<'a> GumpScene {
-    pub fn new(ctx: &mut Context) -> BoxedScene<'a, SceneName, ()> {
+    pub fn new(ctx: &mut Context) -> BoxedScene<'a, SceneName, EngineData> {
         let reader = GumpReader::new(
             &Path::new("./assets/gumpidx.mul"),
             &Path::new("./assets/gumpart.mul"),
@@ -100,8 +101,8 @@ impl<'a> GumpScene {
     }
 }
 
-impl Scene<SceneName, ()> for GumpScene {
-    fn draw(&mut self, ctx: &mut Context, _engine_data: &mut ()) -> GameResult<()> {
+impl Scene<SceneName, EngineData> for GumpScene {
+    fn draw(&mut self, ctx: &mut Context, _engine_data: &mut EngineData) -> GameResult<()> {
         match self.texture {
             Some(ref texture) => {
                 graphics::draw(ctx, texture, DrawParam::default())?;
@@ -114,7 +115,7 @@ impl Scene<SceneName, ()> for GumpScene {
     fn update(
         &mut self,
         _ctx: &mut Context,
-        _engine_data: &mut (),
+        _engine_data: &mut EngineData,
     ) -> GameResult<Option<SceneChangeEvent<SceneName>>> {
         if self.exiting {
             Ok(Some(SceneChangeEvent::PopScene))
@@ -129,7 +130,7 @@ impl Scene<SceneName, ()> for GumpScene {
         keycode: KeyCode,
         _keymods: KeyMods,
         _repeat: bool,
-        _engine_data: &mut (),
+        _engine_data: &mut EngineData,
     ) {
         match keycode {
             KeyCode::Escape => self.exiting = true,
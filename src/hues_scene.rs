@@ -1,4 +1,5 @@
 use cgmath::Point2;
+use engine::EngineData;
 use ggez::event::{KeyCode, KeyMods};
 use ggez::graphics::{self, Canvas, Color, DrawParam, Text};
 use ggez::{Context, GameResult};
@@ -20,7 +21,7 @@ pub struct HuesScene {
 }
 
 impl<'a> HuesScene {
-    pub fn new(ctx: &mut Context) -> BoxedScene<'a, SceneName, ()> {
+    pub fn new(ctx: &mut Context) -> BoxedScene<'a, SceneName, EngineData> {
         let mut scene = Box::new(HuesScene {
             reader: HueReader::new(&Path::new("./assets/hues.mul")),
             texture: None,
@@ -108,8 +109,8 @@ impl<'a> HuesScene {
     }
 }
 
-impl Scene<SceneName, ()> for HuesScene {
-    fn draw(&mut self, ctx: &mut Context, _engine_data: &mut ()) -> GameResult<()> {
+impl Scene<SceneName, EngineData> for HuesScene {
+    fn draw(&mut self, ctx: &mut Context, _engine_data: &mut EngineData) -> GameResult<()> {
         match self.texture {
             Some(ref texture) => {
                 graphics::draw(ctx, texture, DrawParam::default())?;
@@ -122,7 +123,7 @@ impl Scene<SceneName, ()> for HuesScene {
     fn update(
         &mut self,
         _ctx: &mut Context,
-        _engine_data: &mut (),
+        _engine_data: &mut EngineData,
     ) -> GameResult<Option<SceneChangeEvent<SceneName>>> {
         if self.exiting {
             Ok(Some(SceneChangeEvent::PopScene))
@@ -137,7 +138,7 @@ impl Scene<SceneName, ()> for HuesScene {
         keycode: KeyCode,
         _keymods: KeyMods,
         _repeat: bool,
-        _engine_data: &mut (),
+        _engine_data: &mut EngineData,
     ) {
         match keycode {
             KeyCode::Escape => self.exiting = true,
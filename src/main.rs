@@ -1,10 +1,13 @@
 extern crate cgmath;
 extern crate ggez;
 extern crate image;
+extern crate png;
 extern crate uorustlibs;
 
 mod anim_scene;
 mod caches;
+mod cli;
+mod compare_scene;
 mod engine;
 mod font_scene;
 mod gump_scene;
@@ -16,26 +19,39 @@ mod map_scene;
 mod scene;
 mod skills_scene;
 mod statics_scene;
+mod stats_scene;
 mod texmaps_scene;
 mod tile_scene;
 mod title_scene;
 mod world_scene;
 
-use ggez::conf::WindowSetup;
+use ggez::conf::{WindowMode, WindowSetup};
 use ggez::event;
 use ggez::ContextBuilder;
+use std::env;
+use std::process;
 
 fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if let Some(result) = cli::run(&args) {
+        if let Err(error) = result {
+            println!("{}", error);
+            process::exit(1);
+        }
+        return;
+    }
+
     // Make a Context.
     let (mut ctx, mut event_loop) = ContextBuilder::new("UO Data Viewer", "Angry Lawyer")
         .window_setup(WindowSetup::default().title("UO Data Viewer"))
+        .window_mode(WindowMode::default().resizable(true))
         .build()
         .expect("Could not create context");
 
     // Create an instance of your event handler.
     // Usually, you should provide it with the Context object to
     // use when setting your game up.
-    let mut my_game = engine::Engine::new(&mut ctx);
+    let mut my_game = engine::Engine::new(&mut ctx, cli::engine_data(&args));
 
     // Run!
     event::run(ctx, event_loop, my_game);
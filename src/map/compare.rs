@@ -0,0 +1,253 @@
+use map::{facet_in, Facet};
+use std::cmp::Ordering;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Result, Write};
+use std::path::{Path, PathBuf};
+use uorustlibs::map::{Block, StaticLocation};
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BlockChange {
+    pub x: u32,
+    pub y: u32,
+    // One bit per cell, set where its land or any of its statics changed
+    pub cells: u64,
+    pub land_cells: usize,
+    pub statics_added: usize,
+    pub statics_removed: usize,
+}
+
+impl BlockChange {
+    pub fn score(&self) -> usize {
+        self.land_cells + self.statics_added + self.statics_removed
+    }
+
+    pub fn cell_changed(&self, index: usize) -> bool {
+        self.cells & (1 << index) != 0
+    }
+}
+
+fn static_key(location: &StaticLocation) -> (u8, u8, i8, u16, u16) {
+    (
+        location.y,
+        location.x,
+        location.altitude,
+        location.object_id,
+        location.unknown,
+    )
+}
+
+fn cell_bit(key: &(u8, u8, i8, u16, u16)) -> u64 {
+    1 << ((key.0 as u64 % 8) * 8 + key.1 as u64 % 8)
+}
+
+pub fn compare_block(
+    x: u32,
+    y: u32,
+    old_land: Option<&Block>,
+    new_land: Option<&Block>,
+    old_statics: &[StaticLocation],
+    new_statics: &[StaticLocation],
+) -> BlockChange {
+    let mut change = BlockChange {
+        x,
+        y,
+        ..BlockChange::default()
+    };
+    for index in 0..64 {
+        let differs = match (old_land, new_land) {
+            (Some(old), Some(new)) => {
+                old.cells[index].graphic != new.cells[index].graphic
+                    || old.cells[index].altitude != new.cells[index].altitude
+            }
+            (None, None) => false,
+            _ => true,
+        };
+        if differs {
+            change.land_cells += 1;
+            change.cells |= 1 << index;
+        }
+    }
+
+    // Statics have no order worth keeping, so sort both sides and walk them together.
+    // Anything only on one side was added or removed.
+    let mut old_keys = old_statics.iter().map(static_key).collect::<Vec<_>>();
+    let mut new_keys = new_statics.iter().map(static_key).collect::<Vec<_>>();
+    old_keys.sort();
+    new_keys.sort();
+    let (mut old_index, mut new_index) = (0, 0);
+    while old_index < old_keys.len() || new_index < new_keys.len() {
+        let order = match (old_keys.get(old_index), new_keys.get(new_index)) {
+            (Some(old), Some(new)) => old.cmp(new),
+            (Some(_), None) => Ordering::Less,
+            _ => Ordering::Greater,
+        };
+        match order {
+            Ordering::Less => {
+                change.statics_removed += 1;
+                change.cells |= cell_bit(&old_keys[old_index]);
+                old_index += 1;
+            }
+            Ordering::Greater => {
+                change.statics_added += 1;
+                change.cells |= cell_bit(&new_keys[new_index]);
+                new_index += 1;
+            }
+            Ordering::Equal => {
+                old_index += 1;
+                new_index += 1;
+            }
+        }
+    }
+    change
+}
+
+// Reads both facets straight from disk, without their patches, and keeps only what changed
+pub fn compare_facets(old: &mut Facet, new: &mut Facet) -> Vec<BlockChange> {
+    let mut changes = vec![];
+    for x in 0..new.width_blocks() {
+        for y in 0..new.height_blocks() {
+            let old_land = old.read_land_uncached(x, y);
+            let new_land = new.read_land_uncached(x, y);
+            let old_statics = old.read_statics(x, y);
+            let new_statics = new.read_statics(x, y);
+            let change = compare_block(
+                x,
+                y,
+                old_land.as_ref(),
+                new_land.as_ref(),
+                &old_statics,
+                &new_statics,
+            );
+            if change.score() > 0 {
+                changes.push(change);
+            }
+        }
+    }
+    changes
+}
+
+fn write_text_report(changes: &[BlockChange], heading: &str, path: &Path) -> Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    writeln!(writer, "{}", heading)?;
+    writeln!(writer, "{} changed blocks", changes.len())?;
+    for change in changes {
+        writeln!(
+            writer,
+            "block {},{} (cell {},{}): {} land cells, {} statics added, {} removed",
+            change.x,
+            change.y,
+            change.x * 8,
+            change.y * 8,
+            change.land_cells,
+            change.statics_added,
+            change.statics_removed
+        )?;
+    }
+    writer.flush()
+}
+
+// Quoted and escaped, as Windows paths are full of backslashes
+fn json_string(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+fn write_json_report(
+    changes: &[BlockChange],
+    map_id: u8,
+    old_directory: &Path,
+    new_directory: &Path,
+    path: &Path,
+) -> Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    writeln!(writer, "{{")?;
+    writeln!(writer, "  \"facet\": {},", map_id)?;
+    writeln!(
+        writer,
+        "  \"old\": {},",
+        json_string(&old_directory.display().to_string())
+    )?;
+    writeln!(
+        writer,
+        "  \"new\": {},",
+        json_string(&new_directory.display().to_string())
+    )?;
+    writeln!(writer, "  \"changed_blocks\": [")?;
+    for (index, change) in changes.iter().enumerate() {
+        writeln!(
+            writer,
+            "    {{\"x\": {}, \"y\": {}, \"land_cells\": {}, \"statics_added\": {}, \"statics_removed\": {}}}{}",
+            change.x,
+            change.y,
+            change.land_cells,
+            change.statics_added,
+            change.statics_removed,
+            if index + 1 < changes.len() { "," } else { "" }
+        )?;
+    }
+    writeln!(writer, "  ]")?;
+    writeln!(writer, "}}")?;
+    writer.flush()
+}
+
+// Compares a facet across two data directories and writes text and JSON reports of every
+// changed block, returning the stem they were written under
+pub fn compare_report(
+    old_directory: &Path,
+    new_directory: &Path,
+    map_id: u8,
+    directory: &Path,
+) -> Result<PathBuf> {
+    let missing = |path: &Path| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("No facet {} in {}", map_id, path.display()),
+        )
+    };
+    let mut old = facet_in(old_directory, map_id).ok_or_else(|| missing(old_directory))?;
+    let mut new = facet_in(new_directory, map_id).ok_or_else(|| missing(new_directory))?;
+    fs::create_dir_all(directory)?;
+    let changes = compare_facets(&mut old, &mut new);
+    let stem = directory.join(format!("compare{}", map_id));
+    let heading = format!(
+        "Facet {}: {} against {}",
+        map_id,
+        new_directory.display(),
+        old_directory.display()
+    );
+    write_text_report(&changes, &heading, &stem.with_extension("txt"))?;
+    write_json_report(
+        &changes,
+        map_id,
+        old_directory,
+        new_directory,
+        &stem.with_extension("json"),
+    )?;
+    println!("{} changed blocks", changes.len());
+    Ok(stem)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::json_string;
+
+    #[test]
+    fn json_strings_are_escaped() {
+        assert_eq!(json_string("plain"), "\"plain\"");
+        assert_eq!(json_string("C:\\UO \"old\""), "\"C:\\\\UO \\\"old\\\"\"");
+        assert_eq!(json_string("a\nb\tc\u{1}"), "\"a\\nb\\tc\\u0001\"");
+    }
+}
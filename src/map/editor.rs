@@ -0,0 +1,370 @@
+use map::Facet;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Result;
+use uorustlibs::map::{Block, StaticLocation};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LandTool {
+    Paint,
+    Raise,
+    Lower,
+}
+
+struct BlockChange {
+    x: u32,
+    y: u32,
+    before: Block,
+    after: Block,
+}
+
+// Each stroke of the brush is one undo step, however many blocks it reached into
+pub struct LandEditor {
+    undo_stack: Vec<Vec<BlockChange>>,
+    redo_stack: Vec<Vec<BlockChange>>,
+    unsaved: BTreeSet<(u32, u32)>,
+}
+
+impl LandEditor {
+    pub fn new() -> LandEditor {
+        LandEditor {
+            undo_stack: vec![],
+            redo_stack: vec![],
+            unsaved: BTreeSet::new(),
+        }
+    }
+
+    pub fn unsaved_blocks(&self) -> usize {
+        self.unsaved.len()
+    }
+
+    // Applies the tool to every cell within radius of the given cell. Returns false if
+    // nothing was changed.
+    pub fn apply(
+        &mut self,
+        facet: &mut Facet,
+        cell_x: u32,
+        cell_y: u32,
+        radius: u32,
+        tool: LandTool,
+        graphic: u16,
+    ) -> bool {
+        let radius = radius as i64;
+        let mut edited: BTreeMap<(u32, u32), (Block, Block)> = BTreeMap::new();
+        for dy in -radius..=radius {
+            for dx in -radius..=radius {
+                if dx * dx + dy * dy > radius * radius + radius {
+                    continue;
+                }
+                let x = cell_x as i64 + dx;
+                let y = cell_y as i64 + dy;
+                if x < 0 || y < 0 {
+                    continue;
+                }
+                let (x, y) = (x as u32, y as u32);
+                let coords = (x / 8, y / 8);
+                if !edited.contains_key(&coords) {
+                    match facet.read_land(coords.0, coords.1) {
+                        Some(block) => {
+                            edited.insert(coords, (block.clone(), block));
+                        }
+                        None => continue,
+                    }
+                }
+                let after = &mut edited.get_mut(&coords).unwrap().1;
+                let cell = &mut after.cells[((y % 8) * 8 + x % 8) as usize];
+                match tool {
+                    LandTool::Paint => cell.graphic = graphic,
+                    LandTool::Raise => cell.altitude = cell.altitude.saturating_add(1),
+                    LandTool::Lower => cell.altitude = cell.altitude.saturating_sub(1),
+                }
+            }
+        }
+        if edited.is_empty() {
+            return false;
+        }
+        let changes = edited
+            .into_iter()
+            .map(|((x, y), (before, after))| BlockChange {
+                x,
+                y,
+                before,
+                after,
+            })
+            .collect::<Vec<_>>();
+        for change in changes.iter() {
+            facet.set_land(change.x, change.y, change.after.clone());
+            self.unsaved.insert((change.x, change.y));
+        }
+        self.undo_stack.push(changes);
+        self.redo_stack.clear();
+        true
+    }
+
+    pub fn undo(&mut self, facet: &mut Facet) -> bool {
+        match self.undo_stack.pop() {
+            Some(changes) => {
+                for change in changes.iter().rev() {
+                    facet.set_land(change.x, change.y, change.before.clone());
+                    self.unsaved.insert((change.x, change.y));
+                }
+                self.redo_stack.push(changes);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn redo(&mut self, facet: &mut Facet) -> bool {
+        match self.redo_stack.pop() {
+            Some(changes) => {
+                for change in changes.iter() {
+                    facet.set_land(change.x, change.y, change.after.clone());
+                    self.unsaved.insert((change.x, change.y));
+                }
+                self.undo_stack.push(changes);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Writes every block touched since the last save, returning how many there were
+    pub fn save(&mut self, facet: &mut Facet) -> Result<usize> {
+        let blocks = self.unsaved.iter().cloned().collect::<Vec<_>>();
+        facet.save_land(&blocks)?;
+        self.unsaved.clear();
+        Ok(blocks.len())
+    }
+}
+
+// A static is picked out by its block and its place in that block's list
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StaticHandle {
+    pub block_x: u32,
+    pub block_y: u32,
+    pub index: usize,
+}
+
+pub struct StaticEditor {
+    unsaved: BTreeSet<(u32, u32)>,
+}
+
+impl StaticEditor {
+    pub fn new() -> StaticEditor {
+        StaticEditor {
+            unsaved: BTreeSet::new(),
+        }
+    }
+
+    pub fn unsaved_blocks(&self) -> usize {
+        self.unsaved.len()
+    }
+
+    // Edits start from the block's cached record, which already has any earlier edits in it
+    fn statics(facet: &mut Facet, x: u32, y: u32) -> Vec<StaticLocation> {
+        facet
+            .read_block(x, y)
+            .map(|record| record.statics.clone())
+            .unwrap_or(vec![])
+    }
+
+    fn replace(&mut self, facet: &mut Facet, x: u32, y: u32, statics: Vec<StaticLocation>) {
+        facet.set_statics(x, y, statics);
+        self.unsaved.insert((x, y));
+    }
+
+    pub fn get(&self, facet: &mut Facet, handle: StaticHandle) -> Option<StaticLocation> {
+        facet
+            .read_block(handle.block_x, handle.block_y)
+            .and_then(|record| record.statics.get(handle.index).cloned())
+    }
+
+    pub fn place(
+        &mut self,
+        facet: &mut Facet,
+        cell_x: u32,
+        cell_y: u32,
+        altitude: i8,
+        object_id: u16,
+    ) -> StaticHandle {
+        let (block_x, block_y) = (cell_x / 8, cell_y / 8);
+        let mut statics = StaticEditor::statics(facet, block_x, block_y);
+        statics.push(StaticLocation {
+            object_id,
+            x: (cell_x % 8) as u8,
+            y: (cell_y % 8) as u8,
+            altitude,
+            unknown: 0,
+        });
+        let index = statics.len() - 1;
+        self.replace(facet, block_x, block_y, statics);
+        StaticHandle {
+            block_x,
+            block_y,
+            index,
+        }
+    }
+
+    pub fn delete(&mut self, facet: &mut Facet, handle: StaticHandle) -> bool {
+        let mut statics = StaticEditor::statics(facet, handle.block_x, handle.block_y);
+        if handle.index >= statics.len() {
+            return false;
+        }
+        statics.remove(handle.index);
+        self.replace(facet, handle.block_x, handle.block_y, statics);
+        true
+    }
+
+    // Moves a static by whole cells and z units. Moving off the edge of its block hands it over
+    // to the neighbouring one, so the handle it comes back with may not be the one passed in.
+    pub fn nudge(
+        &mut self,
+        facet: &mut Facet,
+        handle: StaticHandle,
+        dx: i32,
+        dy: i32,
+        dz: i32,
+    ) -> Option<StaticHandle> {
+        let mut statics = StaticEditor::statics(facet, handle.block_x, handle.block_y);
+        let mut location = statics.get(handle.index)?.clone();
+        let x = (handle.block_x * 8 + location.x as u32) as i64 + dx as i64;
+        let y = (handle.block_y * 8 + location.y as u32) as i64 + dy as i64;
+        if x < 0
+            || y < 0
+            || x >= facet.width_blocks() as i64 * 8
+            || y >= facet.height_blocks() as i64 * 8
+        {
+            return Some(handle);
+        }
+        let (x, y) = (x as u32, y as u32);
+        location.x = (x % 8) as u8;
+        location.y = (y % 8) as u8;
+        location.altitude = (location.altitude as i32 + dz).max(-128).min(127) as i8;
+
+        let (block_x, block_y) = (x / 8, y / 8);
+        if (block_x, block_y) == (handle.block_x, handle.block_y) {
+            statics[handle.index] = location;
+            self.replace(facet, block_x, block_y, statics);
+            return Some(handle);
+        }
+        statics.remove(handle.index);
+        self.replace(facet, handle.block_x, handle.block_y, statics);
+        let mut neighbour = StaticEditor::statics(facet, block_x, block_y);
+        neighbour.push(location);
+        let index = neighbour.len() - 1;
+        self.replace(facet, block_x, block_y, neighbour);
+        Some(StaticHandle {
+            block_x,
+            block_y,
+            index,
+        })
+    }
+
+    pub fn set_hue(&mut self, facet: &mut Facet, handle: StaticHandle, hue: u16) -> bool {
+        let mut statics = StaticEditor::statics(facet, handle.block_x, handle.block_y);
+        match statics.get_mut(handle.index) {
+            Some(location) => location.unknown = hue,
+            None => return false,
+        }
+        self.replace(facet, handle.block_x, handle.block_y, statics);
+        true
+    }
+
+    pub fn save(&mut self, facet: &mut Facet) -> Result<usize> {
+        let blocks = self.unsaved.iter().cloned().collect::<Vec<_>>();
+        facet.save_statics(&blocks)?;
+        self.unsaved.clear();
+        Ok(blocks.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LandEditor, LandTool};
+    use map::patches::Patches;
+    use map::writer::{block_cells, write_empty_statics, write_map};
+    use map::Facet;
+    use std::collections::BTreeSet;
+    use std::path::PathBuf;
+    use std::{env, fs, process};
+
+    // A flat 3x3 block facet at z 0, in its own directory so tests can run side by side
+    fn flat_facet(name: &str) -> (PathBuf, Facet) {
+        let directory = env::temp_dir().join(format!("editor_{}_{}", name, process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let (map, index, statics) = (
+            directory.join("map.mul"),
+            directory.join("staidx.mul"),
+            directory.join("statics.mul"),
+        );
+        write_map(&map, 3, 3, |_, _| vec![(3, 0); 64]).unwrap();
+        write_empty_statics(&index, &statics, 3, 3).unwrap();
+        let facet = Facet::new(&map, &index, &statics, 3, 3, Patches::empty());
+        (directory, facet)
+    }
+
+    fn altitude(facet: &mut Facet, x: u32, y: u32) -> i8 {
+        facet.read_land(x / 8, y / 8).unwrap().cells[((y % 8) * 8 + x % 8) as usize].altitude
+    }
+
+    fn snapshot(facet: &mut Facet) -> Vec<Vec<(u16, i8)>> {
+        let mut blocks = vec![];
+        for y in 0..3 {
+            for x in 0..3 {
+                blocks.push(block_cells(&facet.read_land(x, y).unwrap()));
+            }
+        }
+        blocks
+    }
+
+    #[test]
+    fn brushes_reach_over_block_edges() {
+        let (directory, mut facet) = flat_facet("edges");
+        let mut editor = LandEditor::new();
+        // The corner cell of block (1, 1), so a radius of 1 reaches into three neighbours
+        assert!(editor.apply(&mut facet, 8, 8, 1, LandTool::Raise, 0));
+        for &(x, y) in [(7, 7), (8, 7), (7, 8), (8, 8), (9, 9)].iter() {
+            assert_eq!(altitude(&mut facet, x, y), 1, "cell {}, {}", x, y);
+        }
+        assert_eq!(altitude(&mut facet, 10, 8), 0);
+        assert_eq!(altitude(&mut facet, 6, 8), 0);
+        let expected = [(0, 0), (0, 1), (1, 0), (1, 1)]
+            .iter()
+            .cloned()
+            .collect::<BTreeSet<_>>();
+        assert_eq!(editor.unsaved, expected);
+
+        // At the facet's edge, the cells that would fall off it are skipped
+        assert!(editor.apply(&mut facet, 0, 0, 1, LandTool::Raise, 0));
+        assert_eq!(altitude(&mut facet, 0, 0), 1);
+        assert_eq!(altitude(&mut facet, 1, 1), 1);
+        assert_eq!(editor.unsaved, expected);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn undo_and_redo_restore_whole_strokes() {
+        let (directory, mut facet) = flat_facet("undo");
+        let mut editor = LandEditor::new();
+        let original = snapshot(&mut facet);
+        editor.apply(&mut facet, 15, 12, 2, LandTool::Raise, 0);
+        editor.apply(&mut facet, 16, 12, 2, LandTool::Paint, 0x16);
+        let edited = snapshot(&mut facet);
+        assert_ne!(edited, original);
+
+        assert!(editor.undo(&mut facet));
+        assert!(editor.undo(&mut facet));
+        assert!(!editor.undo(&mut facet));
+        assert_eq!(snapshot(&mut facet), original);
+
+        assert!(editor.redo(&mut facet));
+        assert!(editor.redo(&mut facet));
+        assert!(!editor.redo(&mut facet));
+        assert_eq!(snapshot(&mut facet), edited);
+
+        // Undone blocks differ from the file until saved, so they stay on the list
+        let expected = [(1, 1), (2, 1)].iter().cloned().collect::<BTreeSet<_>>();
+        assert_eq!(editor.unsaved, expected);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
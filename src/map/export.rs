@@ -0,0 +1,156 @@
+use image::{ImageBuffer, Luma};
+use map::Facet;
+use std::cmp::min;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Result, Write};
+use std::path::{Path, PathBuf};
+
+// Altitudes run from -128 to 127, so they're shifted up to start at zero and then stretched
+// across the whole 16 bit range for the PNG
+pub const Z_OFFSET: i32 = 128;
+pub const EXPORT_DIRECTORY: &'static str = "./exports";
+pub const PNG_Z_SCALE: i32 = 257;
+
+pub struct AltitudeGrid {
+    pub origin_x: u32,
+    pub origin_y: u32,
+    pub width: u32,
+    pub height: u32,
+    pub altitudes: Vec<i8>,
+}
+
+// A rectangle of blocks to export
+#[derive(Clone, Copy)]
+pub struct Region {
+    pub x_block: u32,
+    pub y_block: u32,
+    pub width_blocks: u32,
+    pub height_blocks: u32,
+}
+
+impl AltitudeGrid {
+    // An empty grid for a rectangle of blocks, clipped to the facet
+    pub fn new(facet: &Facet, region: Region) -> AltitudeGrid {
+        let width_blocks = min(
+            region.width_blocks,
+            facet.width_blocks().saturating_sub(region.x_block),
+        );
+        let height_blocks = min(
+            region.height_blocks,
+            facet.height_blocks().saturating_sub(region.y_block),
+        );
+        let width = width_blocks * 8;
+        let height = height_blocks * 8;
+        AltitudeGrid {
+            origin_x: region.x_block * 8,
+            origin_y: region.y_block * 8,
+            width,
+            height,
+            altitudes: vec![0; (width * height) as usize],
+        }
+    }
+
+    pub fn rows(&self) -> u32 {
+        self.height / 8
+    }
+
+    // Fills in one row of blocks. Blocks that fail to read are left at 0.
+    pub fn read_row(&mut self, facet: &mut Facet, row: u32) {
+        let (x_block, y_block) = (self.origin_x / 8, self.origin_y / 8 + row);
+        for block_x in 0..(self.width / 8) {
+            let block = match facet.read_land_uncached(x_block + block_x, y_block) {
+                Some(block) => block,
+                None => continue,
+            };
+            for (index, cell) in block.cells.iter().enumerate() {
+                let x = block_x * 8 + (index % 8) as u32;
+                let y = row * 8 + (index / 8) as u32;
+                self.altitudes[(y * self.width + x) as usize] = cell.altitude;
+            }
+        }
+    }
+
+    pub fn write_png16(&self, path: &Path) -> Result<()> {
+        let pixels = self
+            .altitudes
+            .iter()
+            .map(|&altitude| ((altitude as i32 + Z_OFFSET) * PNG_Z_SCALE) as u16)
+            .collect::<Vec<u16>>();
+        let image: ImageBuffer<Luma<u16>, Vec<u16>> =
+            ImageBuffer::from_raw(self.width, self.height, pixels)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "Bad heightmap size"))?;
+        image
+            .save(path)
+            .map_err(|error| io::Error::new(io::ErrorKind::Other, error.to_string()))
+    }
+
+    pub fn write_raw_i8(&self, path: &Path) -> Result<()> {
+        let bytes = self
+            .altitudes
+            .iter()
+            .map(|&altitude| altitude as u8)
+            .collect::<Vec<u8>>();
+        File::create(path)?.write_all(&bytes)
+    }
+
+    pub fn write_raw_i16(&self, path: &Path) -> Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        for &altitude in self.altitudes.iter() {
+            writer.write_all(&(altitude as i16).to_le_bytes())?;
+        }
+        writer.flush()
+    }
+
+    pub fn write_csv(&self, path: &Path) -> Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        for row in self.altitudes.chunks(self.width as usize) {
+            let line = row
+                .iter()
+                .map(|altitude| altitude.to_string())
+                .collect::<Vec<String>>()
+                .join(",");
+            writeln!(writer, "{}", line)?;
+        }
+        writer.flush()
+    }
+
+    pub fn write_metadata(&self, path: &Path, map_id: u8, map_path: &str) -> Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "{{")?;
+        writeln!(writer, "  \"facet\": {},", map_id)?;
+        writeln!(writer, "  \"map\": \"{}\",", map_path)?;
+        writeln!(writer, "  \"origin_x\": {},", self.origin_x)?;
+        writeln!(writer, "  \"origin_y\": {},", self.origin_y)?;
+        writeln!(writer, "  \"width\": {},", self.width)?;
+        writeln!(writer, "  \"height\": {},", self.height)?;
+        writeln!(writer, "  \"z_offset\": {},", Z_OFFSET)?;
+        writeln!(writer, "  \"png_z_scale\": {},", PNG_Z_SCALE)?;
+        writeln!(writer, "  \"raw_i8\": \"signed z, row major\",")?;
+        writeln!(
+            writer,
+            "  \"raw_i16\": \"signed z, little endian, row major\","
+        )?;
+        writeln!(
+            writer,
+            "  \"png16\": \"(z + z_offset) * png_z_scale, unsigned\""
+        )?;
+        writeln!(writer, "}}")?;
+        writer.flush()
+    }
+
+    // Writes every format side by side, named after the facet and origin, and returns the
+    // shared stem they were written under
+    pub fn write_all(&self, map_id: u8, map_path: &str, directory: &Path) -> Result<PathBuf> {
+        fs::create_dir_all(directory)?;
+        let stem = directory.join(format!(
+            "heightmap{}_{}_{}_{}x{}",
+            map_id, self.origin_x, self.origin_y, self.width, self.height
+        ));
+        self.write_png16(&stem.with_extension("png"))?;
+        self.write_raw_i8(&stem.with_extension("i8.raw"))?;
+        self.write_raw_i16(&stem.with_extension("i16.raw"))?;
+        self.write_csv(&stem.with_extension("csv"))?;
+        self.write_metadata(&stem.with_extension("json"), map_id, map_path)?;
+        Ok(stem)
+    }
+}
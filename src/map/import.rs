@@ -0,0 +1,297 @@
+use image::{self, GenericImageView, ImageBuffer, Luma, Rgb};
+use map::export::{PNG_Z_SCALE, Z_OFFSET};
+use map::writer::{write_empty_statics, write_map};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Result};
+use std::path::Path;
+
+// Used when no terrain table is given. Each colour picks the first tile of its kind.
+const DEFAULT_TERRAIN: [((u8, u8, u8), u16); 8] = [
+    ((0, 0, 255), 0x00A8),     // water
+    ((0, 128, 0), 0x0003),     // grass
+    ((0, 64, 0), 0x00C4),      // forest
+    ((255, 224, 128), 0x0016), // sand
+    ((128, 64, 0), 0x0071),    // dirt
+    ((128, 128, 128), 0x00DC), // mountain
+    ((255, 255, 255), 0x011A), // snow
+    ((255, 0, 0), 0x01F4),     // lava
+];
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn parse_tile_id(text: &str) -> Option<u16> {
+    if text.starts_with("0x") || text.starts_with("0X") {
+        u16::from_str_radix(&text[2..], 16).ok()
+    } else {
+        text.parse().ok()
+    }
+}
+
+fn parse_color(text: &str) -> Option<(u8, u8, u8)> {
+    let text = text.trim_start_matches('#');
+    if text.len() != 6 || !text.is_ascii() {
+        return None;
+    }
+    let channel = |start: usize| {
+        text.get(start..start + 2)
+            .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+    };
+    Some((channel(0)?, channel(2)?, channel(4)?))
+}
+
+pub struct TerrainTable {
+    entries: Vec<((u8, u8, u8), u16)>,
+    // Painted terrain is rarely exact once it's been anti-aliased, so remember which entry
+    // each stray colour snapped to
+    matches: HashMap<(u8, u8, u8), u16>,
+}
+
+impl Default for TerrainTable {
+    fn default() -> TerrainTable {
+        TerrainTable::new(DEFAULT_TERRAIN.to_vec())
+    }
+}
+
+impl TerrainTable {
+    pub fn new(entries: Vec<((u8, u8, u8), u16)>) -> TerrainTable {
+        TerrainTable {
+            entries,
+            matches: HashMap::new(),
+        }
+    }
+
+    // One entry per line, as a hex colour and a tile id, e.g. "#00FF00 0x0003".
+    // Anything after a ';' is a comment.
+    pub fn load(path: &Path) -> Result<TerrainTable> {
+        let reader = BufReader::new(File::open(path)?);
+        let mut entries = vec![];
+        for (number, line) in reader.lines().enumerate() {
+            let line = line?;
+            let content = line.split(';').next().unwrap_or("").trim();
+            if content.is_empty() {
+                continue;
+            }
+            let mut fields = content.split_whitespace();
+            let color = fields.next().and_then(parse_color);
+            let tile_id = fields.next().and_then(parse_tile_id);
+            match (color, tile_id) {
+                (Some(color), Some(tile_id)) => entries.push((color, tile_id)),
+                _ => {
+                    return Err(invalid(format!(
+                        "{}:{}: expected a colour and a tile id",
+                        path.display(),
+                        number + 1
+                    )))
+                }
+            }
+        }
+        if entries.is_empty() {
+            return Err(invalid(format!(
+                "{} has no terrain entries",
+                path.display()
+            )));
+        }
+        Ok(TerrainTable::new(entries))
+    }
+
+    pub fn tile_for(&mut self, color: (u8, u8, u8)) -> u16 {
+        if let Some(&tile_id) = self.matches.get(&color) {
+            return tile_id;
+        }
+        let distance = |&(other, _): &((u8, u8, u8), u16)| {
+            let dr = color.0 as i32 - other.0 as i32;
+            let dg = color.1 as i32 - other.1 as i32;
+            let db = color.2 as i32 - other.2 as i32;
+            dr * dr + dg * dg + db * db
+        };
+        let tile_id = self
+            .entries
+            .iter()
+            .min_by_key(|entry| distance(entry))
+            .map(|&(_, tile_id)| tile_id)
+            .unwrap_or(0);
+        self.matches.insert(color, tile_id);
+        tile_id
+    }
+}
+
+fn open_image(path: &Path) -> Result<image::DynamicImage> {
+    image::open(path).map_err(|error| invalid(format!("{}: {}", path.display(), error)))
+}
+
+// Images that aren't the size of the facet get stretched over it, nearest neighbour
+fn sample<P: image::Pixel + 'static>(
+    image: &ImageBuffer<P, Vec<P::Subpixel>>,
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+) -> P {
+    let source_x = (x as u64 * image.width() as u64 / width as u64) as u32;
+    let source_y = (y as u64 * image.height() as u64 / height as u64) as u32;
+    *image.get_pixel(source_x, source_y)
+}
+
+// Reads the heightmap the same way export writes it, so an exported facet imports unchanged.
+// 8 bit images are widened first, so 128 grey is z 0 either way.
+fn height_to_altitude(Luma([value]): Luma<u16>) -> i8 {
+    let shifted = (value as i32 + PNG_Z_SCALE / 2) / PNG_Z_SCALE;
+    (shifted - Z_OFFSET).max(-128).min(127) as i8
+}
+
+// Writes a land only facet of the given size in cells, along with empty statics so it can be
+// opened like any other
+pub fn import_facet(
+    heightmap_path: &Path,
+    terrain_path: &Path,
+    terrain: &mut TerrainTable,
+    (width, height): (u32, u32),
+    (map_path, index_path, statics_path): (&Path, &Path, &Path),
+) -> Result<()> {
+    let heightmap = open_image(heightmap_path)?;
+    let terrain_map = open_image(terrain_path)?;
+    println!(
+        "Importing {}x{} heightmap and {}x{} terrain as a {}x{} facet",
+        heightmap.width(),
+        heightmap.height(),
+        terrain_map.width(),
+        terrain_map.height(),
+        width,
+        height
+    );
+    let heightmap: ImageBuffer<Luma<u16>, Vec<u16>> = heightmap.to_luma16();
+    let terrain_map: ImageBuffer<Rgb<u8>, Vec<u8>> = terrain_map.to_rgb8();
+
+    let width_blocks = width / 8;
+    let height_blocks = height / 8;
+    write_map(map_path, width_blocks, height_blocks, |block_x, block_y| {
+        let mut cells = Vec::with_capacity(64);
+        for y in 0..8 {
+            for x in 0..8 {
+                let cell_x = block_x * 8 + x;
+                let cell_y = block_y * 8 + y;
+                let Rgb([r, g, b]) = sample(&terrain_map, cell_x, cell_y, width, height);
+                let altitude =
+                    height_to_altitude(sample(&heightmap, cell_x, cell_y, width, height));
+                cells.push((terrain.tile_for((r, g, b)), altitude));
+            }
+        }
+        cells
+    })?;
+    write_empty_statics(index_path, statics_path, width_blocks, height_blocks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{import_facet, parse_color, TerrainTable};
+    use image::{Rgb, RgbImage};
+    use map::export::{AltitudeGrid, Region};
+    use map::patches::Patches;
+    use map::writer::{block_cells, write_empty_statics, write_map};
+    use map::Facet;
+    use std::{env, fs, process};
+    use uorustlibs::map::{Block, Cell, MapReader};
+
+    const GRASS: u16 = 0x0003;
+    const SAND: u16 = 0x0016;
+
+    #[test]
+    fn colours_must_be_six_hex_digits() {
+        assert_eq!(parse_color("#00FF80"), Some((0, 255, 128)));
+        assert_eq!(parse_color("00ff80"), Some((0, 255, 128)));
+        assert_eq!(parse_color("#00FF8"), None);
+        assert_eq!(parse_color("#00FFZZ"), None);
+        // Six bytes, but not six characters
+        assert_eq!(parse_color("é00FF"), None);
+        assert_eq!(parse_color("0é0FF"), None);
+    }
+
+    // Every altitude from -128 to 127, one per cell of a 2x2 block facet
+    fn block(x: u32, y: u32) -> Block {
+        let mut block = Block {
+            header: 0,
+            cells: [Cell {
+                graphic: GRASS,
+                altitude: 0,
+            }; 64],
+        };
+        for (index, cell) in block.cells.iter_mut().enumerate() {
+            cell.altitude = ((y * 2 + x) * 64 + index as u32) as u8 as i8;
+        }
+        block
+    }
+
+    #[test]
+    fn exported_heightmaps_import_unchanged() {
+        let directory = env::temp_dir().join(format!("import_round_trip_{}", process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let original = (
+            directory.join("map.mul"),
+            directory.join("staidx.mul"),
+            directory.join("statics.mul"),
+        );
+        write_map(&original.0, 2, 2, |x, y| block_cells(&block(x, y))).unwrap();
+        write_empty_statics(&original.1, &original.2, 2, 2).unwrap();
+
+        let mut facet = Facet::new(
+            &original.0,
+            &original.1,
+            &original.2,
+            2,
+            2,
+            Patches::empty(),
+        );
+        let region = Region {
+            x_block: 0,
+            y_block: 0,
+            width_blocks: 2,
+            height_blocks: 2,
+        };
+        let mut grid = AltitudeGrid::new(&facet, region);
+        for row in 0..grid.rows() {
+            grid.read_row(&mut facet, row);
+        }
+        let stem = grid.write_all(0, "map.mul", &directory).unwrap();
+
+        // Sand down the west half, grass down the east
+        let terrain = RgbImage::from_fn(16, 16, |x, _| {
+            if x < 8 {
+                Rgb([255, 224, 128])
+            } else {
+                Rgb([0, 128, 0])
+            }
+        });
+        let terrain_path = directory.join("terrain.png");
+        terrain.save(&terrain_path).unwrap();
+
+        let imported = (
+            directory.join("imported.mul"),
+            directory.join("imported_staidx.mul"),
+            directory.join("imported_statics.mul"),
+        );
+        import_facet(
+            &stem.with_extension("png"),
+            &terrain_path,
+            &mut TerrainTable::default(),
+            (16, 16),
+            (&imported.0, &imported.1, &imported.2),
+        )
+        .unwrap();
+
+        let mut reader = MapReader::new(&imported.0, 2, 2).unwrap();
+        for y in 0..2 {
+            for x in 0..2 {
+                let read = reader.read_block_from_coordinates(x, y, None).unwrap();
+                let expected = block(x, y);
+                let tile_id = if x == 0 { SAND } else { GRASS };
+                for (cell, expected) in read.cells.iter().zip(expected.cells.iter()) {
+                    assert_eq!((cell.graphic, cell.altitude), (tile_id, expected.altitude));
+                }
+            }
+        }
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,258 @@
+use caches::facet_cache::BlockRecord;
+use caches::tiledata_cache::TileDataCache;
+use map::walkability::land_heights;
+use map::Facet;
+use std::rc::Rc;
+use uorustlibs::tiledata::Flags;
+
+// Mobiles see from, and are seen at, this far above where they stand
+pub const EYE_HEIGHT: i32 = 14;
+
+#[derive(Clone, Copy, Debug)]
+pub enum Blocker {
+    Land(u16),
+    Static {
+        block_x: u32,
+        block_y: u32,
+        index: usize,
+        object_id: u16,
+    },
+}
+
+pub struct SightLine {
+    // Every point the ray passes through, from the eye to the target
+    pub path: Vec<(i32, i32, i32)>,
+    // Which point of the path was blocked, and by what
+    pub blocked: Option<(usize, Blocker)>,
+}
+
+// Land that's never drawn, like the black void, doesn't get in the way
+fn is_ignored_land(graphic: u16) -> bool {
+    graphic == 2 || graphic == 0x244 || (0x1DB..=0x1E0).contains(&graphic)
+}
+
+fn between(value: f64, first: i32, second: i32) -> bool {
+    let (low, high) = if first > second {
+        (second as f64, first as f64)
+    } else {
+        (first as f64, second as f64)
+    };
+    value > low - 0.5 && value < high + 0.5
+}
+
+// Steps along the line in unit lengths, rounding to the nearest point each time
+fn trace(from: (i32, i32, i32), to: (i32, i32, i32)) -> Vec<(i32, i32, i32)> {
+    let (dx, dy, dz) = (
+        (to.0 - from.0) as f64,
+        (to.1 - from.1) as f64,
+        (to.2 - from.2) as f64,
+    );
+    let length = (dx * dx + dy * dy + dz * dz).sqrt();
+    let mut path: Vec<(i32, i32, i32)> = vec![];
+    if length == 0.0 {
+        return path;
+    }
+    let (step_x, step_y, step_z) = (dx / length, dy / length, dz / length);
+    let (mut x, mut y, mut z) = (from.0 as f64, from.1 as f64, from.2 as f64);
+    while between(x, from.0, to.0) && between(y, from.1, to.1) && between(z, from.2, to.2) {
+        let point = (x.round() as i32, y.round() as i32, z.round() as i32);
+        if path.last() != Some(&point) {
+            path.push(point);
+        }
+        x += step_x;
+        y += step_y;
+        z += step_z;
+    }
+    if path.last() != Some(&to) {
+        path.push(to);
+    }
+    path
+}
+
+// Follows the server's line of sight check. The ray is blocked by any land it passes through,
+// and by statics flagged as windows or no-shoot, except for whatever is at the target itself.
+pub fn line_of_sight(
+    facet: &mut Facet,
+    tiledata: &mut TileDataCache,
+    from: (i32, i32, i32),
+    to: (i32, i32, i32),
+) -> SightLine {
+    sight_line(
+        from,
+        to,
+        |x, y| facet.read_block(x, y),
+        |object_id| tiledata.static_flags_and_height(object_id as u32),
+    )
+}
+
+fn sight_line<F, G>(
+    from: (i32, i32, i32),
+    to: (i32, i32, i32),
+    mut read_block: F,
+    mut static_data: G,
+) -> SightLine
+where
+    F: FnMut(u32, u32) -> Option<Rc<BlockRecord>>,
+    G: FnMut(u16) -> (u32, u8),
+{
+    let path = trace(from, to);
+    let target_top = to.2 + 1;
+    let at_target = |x: i32, y: i32, bottom: i32, top: i32| {
+        x == to.0 && y == to.1 && bottom <= target_top && top >= to.2
+    };
+    for (step, &(x, y, z)) in path.iter().enumerate() {
+        if x < 0 || y < 0 {
+            continue;
+        }
+        let (block_x, block_y) = (x as u32 / 8, y as u32 / 8);
+        let record = match read_block(block_x, block_y) {
+            Some(record) => record,
+            None => continue,
+        };
+        let (cell_x, cell_y) = (x as u8 % 8, y as u8 % 8);
+        let index = cell_y as usize * 8 + cell_x as usize;
+        let point_top = z + 1;
+
+        let graphic = record.block.cells[index].graphic;
+        let (low, _, high) = land_heights(&record.altitudes[index]);
+        if low <= point_top && high >= z && !at_target(x, y, low, high) && !is_ignored_land(graphic)
+        {
+            return SightLine {
+                blocked: Some((step, Blocker::Land(graphic))),
+                path,
+            };
+        }
+
+        for (static_index, location) in record.statics.iter().enumerate() {
+            if location.x != cell_x || location.y != cell_y {
+                continue;
+            }
+            let (flags, height) = static_data(location.object_id);
+            let (bottom, top) = (
+                location.altitude as i32,
+                location.altitude as i32 + height as i32,
+            );
+            let blocks_sight = flags & (Flags::WindowFlag as u32 | Flags::NoShootFlag as u32) != 0;
+            if blocks_sight && bottom <= point_top && top >= z && !at_target(x, y, bottom, top) {
+                return SightLine {
+                    blocked: Some((
+                        step,
+                        Blocker::Static {
+                            block_x,
+                            block_y,
+                            index: static_index,
+                            object_id: location.object_id,
+                        },
+                    )),
+                    path,
+                };
+            }
+        }
+    }
+    SightLine {
+        path,
+        blocked: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{sight_line, trace, Blocker, SightLine};
+    use caches::facet_cache::BlockRecord;
+    use std::rc::Rc;
+    use uorustlibs::map::{Block, Cell, StaticLocation};
+    use uorustlibs::tiledata::Flags;
+
+    const WINDOW: u16 = 1;
+    const CHAIR: u16 = 2;
+
+    fn static_data(object_id: u16) -> (u32, u8) {
+        match object_id {
+            WINDOW => (Flags::WindowFlag as u32, 20),
+            _ => (0, 20),
+        }
+    }
+
+    fn item(object_id: u16, x: u8) -> StaticLocation {
+        StaticLocation {
+            object_id,
+            x,
+            y: 0,
+            altitude: 0,
+            unknown: 0,
+        }
+    }
+
+    // Looks along the top row of a single flat block, from eye height at one end to the other
+    fn look(hill: Option<usize>, statics: Vec<StaticLocation>) -> SightLine {
+        let mut block = Block {
+            header: 0,
+            cells: [Cell {
+                graphic: 3,
+                altitude: 0,
+            }; 64],
+        };
+        if let Some(x) = hill {
+            block.cells[x].altitude = 30;
+        }
+        let record = Rc::new(BlockRecord::new(block, statics, None, None, None));
+        sight_line(
+            (0, 0, 14),
+            (5, 0, 14),
+            |x, y| {
+                if (x, y) == (0, 0) {
+                    Some(record.clone())
+                } else {
+                    None
+                }
+            },
+            static_data,
+        )
+    }
+
+    #[test]
+    fn traces_include_both_ends() {
+        assert_eq!(
+            trace((0, 0, 0), (3, 0, 0)),
+            vec![(0, 0, 0), (1, 0, 0), (2, 0, 0), (3, 0, 0)]
+        );
+        assert_eq!(
+            trace((0, 0, 0), (3, 3, 0)),
+            vec![(0, 0, 0), (1, 1, 0), (2, 2, 0), (3, 3, 0)]
+        );
+        assert_eq!(trace((2, 2, 2), (2, 2, 2)), vec![]);
+    }
+
+    #[test]
+    fn clear_lines_are_not_blocked() {
+        let sight = look(None, vec![item(CHAIR, 2)]);
+        assert_eq!(sight.path.len(), 6);
+        assert!(sight.blocked.is_none());
+    }
+
+    #[test]
+    fn land_in_the_way_blocks() {
+        let sight = look(Some(3), vec![]);
+        match sight.blocked {
+            Some((step, Blocker::Land(3))) => assert!(sight.path[step].0 < 5),
+            other => panic!("Expected land to block, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn windows_block() {
+        let sight = look(None, vec![item(CHAIR, 1), item(WINDOW, 2)]);
+        match sight.blocked {
+            Some((step, Blocker::Static { index: 1, .. })) => {
+                assert_eq!(sight.path[step], (2, 0, 14))
+            }
+            other => panic!("Expected the window to block, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn blockers_at_the_target_are_ignored() {
+        let sight = look(None, vec![item(WINDOW, 5)]);
+        assert!(sight.blocked.is_none());
+    }
+}
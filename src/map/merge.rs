@@ -0,0 +1,75 @@
+use map::patches::patch_coordinates;
+use map::writer::{block_cells, write_map, write_statics};
+use map::{map_id_to_facet, Facet};
+use std::fs::{self, File};
+use std::io::{BufWriter, Result, Write};
+use std::path::{Path, PathBuf};
+
+pub const MERGE_DIRECTORY: &'static str = "./exports/merged";
+
+// One line per patched block, saying which of its files the patch replaced
+fn write_report(facet: &Facet, map_id: u8, path: &Path) -> Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    let patches = facet.patches();
+    let (mut land, mut statics) = (0, 0);
+    writeln!(writer, "index,block_x,block_y,x,y,land,statics")?;
+    for index in facet.patch_indices() {
+        let (block_x, block_y) = patch_coordinates(index, facet.height_blocks());
+        let patched_land = patches.land(index).is_some();
+        let patched_statics = patches.statics(index).map(|statics| statics.len());
+        if patched_land {
+            land += 1;
+        }
+        if patched_statics.is_some() {
+            statics += 1;
+        }
+        writeln!(
+            writer,
+            "{},{},{},{},{},{},{}",
+            index,
+            block_x,
+            block_y,
+            block_x * 8,
+            block_y * 8,
+            if patched_land { "yes" } else { "no" },
+            patched_statics.map_or("no".to_string(), |count| format!("{} statics", count))
+        )?;
+    }
+    writer.flush()?;
+    println!(
+        "Facet {}: {} land patches, {} statics patches",
+        map_id, land, statics
+    );
+    Ok(())
+}
+
+// Writes map, staidx and statics files for a facet with every patch applied, so tools that
+// don't read the diff files see what the client does. Returns the path of the report.
+pub fn merge_patches(map_id: u8, directory: &Path) -> Result<PathBuf> {
+    fs::create_dir_all(directory)?;
+    let mut facet = map_id_to_facet(map_id);
+    facet.set_patched(true);
+    let (width_blocks, height_blocks) = (facet.width_blocks(), facet.height_blocks());
+
+    // Blocks that can't be read are written out flat, rather than leaving a hole in the file
+    write_map(
+        &directory.join(format!("map{}.mul", map_id)),
+        width_blocks,
+        height_blocks,
+        |x, y| match facet.read_land_uncached(x, y) {
+            Some(block) => block_cells(&block),
+            None => vec![(0, 0); 64],
+        },
+    )?;
+    write_statics(
+        &directory.join(format!("staidx{}.mul", map_id)),
+        &directory.join(format!("statics{}.mul", map_id)),
+        width_blocks,
+        height_blocks,
+        |x, y| facet.read_statics(x, y),
+    )?;
+
+    let report = directory.join(format!("merge{}.csv", map_id));
+    write_report(&facet, map_id, &report)?;
+    Ok(report)
+}
@@ -1,30 +1,70 @@
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::Result;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
+pub mod compare;
+pub mod editor;
+pub mod export;
+pub mod import;
+pub mod line_of_sight;
+pub mod merge;
+pub mod patches;
+pub mod pathfinding;
+pub mod picking;
+pub mod poster;
+pub mod radar;
 pub mod render;
+pub mod shading;
+pub mod software_render;
+pub mod stats;
+pub mod walkability;
+pub mod writer;
 
+use std::rc::Rc;
 use uorustlibs::map::{Block, MapReader, StaticLocation, StaticReader};
 
-use crate::caches::facet_cache::{Altitudes, FacetCache};
+use self::patches::Patches;
+use crate::caches::facet_cache::{BlockRecord, FacetCache};
+use crate::caches::lru_cache::CacheStats;
 use uorustlibs::map::map_size::{ILSHENAR, MALAS, SOSARIA, TER_MUR, TOKUNO};
 
 pub fn map_id_to_facet(id: u8) -> Facet {
-    let corrected_id = if id as usize >= MAP_DETAILS.len() {
-        0
+    let (map, idx, statics, (width, height)) = facet_details(id);
+    // Only the client's own facets come with patches
+    let patches = if (id as usize) < MAP_DETAILS.len() {
+        Patches::load(id)
     } else {
-        id as usize
+        Patches::empty()
     };
-    let (map, idx, statics, (width, height)) = MAP_DETAILS[corrected_id];
     Facet::new(
         &Path::new(map),
         &Path::new(idx),
         &Path::new(statics),
         width / 8,
         height / 8,
+        patches,
     )
 }
 
+// The same facet's files under another data directory, such as an older client's, along with
+// that directory's patches, which start off hidden. None if any of the files are missing.
+pub fn facet_in(directory: &Path, id: u8) -> Option<Facet> {
+    let (map, idx, statics, (width, height)) = facet_details(id);
+    let in_directory = |path: &str| directory.join(Path::new(path).file_name().unwrap());
+    let (map, idx, statics) = (in_directory(map), in_directory(idx), in_directory(statics));
+    if !(map.exists() && idx.exists() && statics.exists()) {
+        return None;
+    }
+    Some(Facet::new(
+        &map,
+        &idx,
+        &statics,
+        width / 8,
+        height / 8,
+        Patches::load_from(directory, id),
+    ))
+}
+
 pub const MAP_DETAILS: [(&'static str, &'static str, &'static str, (u32, u32)); 5] = [
     (
         "./assets/map0.mul",
@@ -58,8 +98,51 @@ pub const MAP_DETAILS: [(&'static str, &'static str, &'static str, (u32, u32));
     ),
 ];
 
+// Written by the importer. It comes after the real facets, whenever it exists.
+pub const IMPORTED_MAP: (&'static str, &'static str, &'static str) = (
+    "./assets/import/map.mul",
+    "./assets/import/staidx.mul",
+    "./assets/import/statics.mul",
+);
+
+pub const MAP_SIZES: [(u32, u32); 5] = [SOSARIA, ILSHENAR, MALAS, TOKUNO, TER_MUR];
+
+// The imported facet can be any of the standard sizes, so work it out from the file length
+fn imported_size() -> Option<(u32, u32)> {
+    let length = fs::metadata(IMPORTED_MAP.0).ok()?.len();
+    MAP_SIZES.iter().cloned().find(|&(width, height)| {
+        (width / 8) as u64 * (height / 8) as u64 * writer::BLOCK_SIZE == length
+    })
+}
+
+pub fn facet_count() -> u8 {
+    if imported_size().is_some() {
+        MAP_DETAILS.len() as u8 + 1
+    } else {
+        MAP_DETAILS.len() as u8
+    }
+}
+
+pub fn facet_details(id: u8) -> (&'static str, &'static str, &'static str, (u32, u32)) {
+    if id as usize == MAP_DETAILS.len() {
+        if let Some(size) = imported_size() {
+            let (map, idx, statics) = IMPORTED_MAP;
+            return (map, idx, statics, size);
+        }
+    }
+    let corrected_id = if id as usize >= MAP_DETAILS.len() {
+        0
+    } else {
+        id as usize
+    };
+    MAP_DETAILS[corrected_id]
+}
+
 pub struct Facet {
     facet_cache: FacetCache,
+    map_path: PathBuf,
+    static_index: PathBuf,
+    static_path: PathBuf,
 }
 
 impl Facet {
@@ -69,15 +152,138 @@ impl Facet {
         static_path: &Path,
         width_blocks: u32,
         height_blocks: u32,
+        patches: Patches,
     ) -> Facet {
-        let facet_cache = FacetCache::new(
+        let mut facet_cache = FacetCache::new(
             MapReader::new(map_path, width_blocks, height_blocks).unwrap(),
             StaticReader::new(static_index, static_path, width_blocks, height_blocks).unwrap(),
+            width_blocks,
+            height_blocks,
         );
-        Facet { facet_cache }
+        facet_cache.set_patches(patches);
+        match (
+            MapReader::new(map_path, width_blocks, height_blocks),
+            StaticReader::new(static_index, static_path, width_blocks, height_blocks),
+        ) {
+            (Ok(map_reader), Ok(static_reader)) => {
+                facet_cache.spawn_prefetcher(map_reader, static_reader)
+            }
+            _ => (),
+        }
+        Facet {
+            facet_cache,
+            map_path: map_path.to_path_buf(),
+            static_index: static_index.to_path_buf(),
+            static_path: static_path.to_path_buf(),
+        }
+    }
+
+    pub fn width_blocks(&self) -> u32 {
+        self.facet_cache.width_blocks()
+    }
+
+    pub fn height_blocks(&self) -> u32 {
+        self.facet_cache.height_blocks()
+    }
+
+    pub fn flush(&mut self) {
+        self.facet_cache.flush();
+    }
+
+    pub fn record_stats(&self) -> CacheStats {
+        self.facet_cache.record_stats()
+    }
+
+    pub fn land_stats(&self) -> CacheStats {
+        self.facet_cache.land_stats()
     }
 
-    pub fn read_block(&mut self, x: u32, y: u32) -> ((Block, Vec<StaticLocation>), Vec<Altitudes>) {
+    pub fn patched(&self) -> bool {
+        self.facet_cache.patched()
+    }
+
+    pub fn set_patched(&mut self, patched: bool) {
+        self.facet_cache.set_patched(patched);
+    }
+
+    pub fn patches(&self) -> &Patches {
+        self.facet_cache.patches()
+    }
+
+    pub fn patch_indices(&self) -> Vec<u32> {
+        self.facet_cache.patch_indices()
+    }
+
+    pub fn is_patched_block(&self, x: u32, y: u32) -> bool {
+        self.facet_cache.is_patched_block(x, y)
+    }
+
+    pub fn pending_blocks(&self) -> usize {
+        self.facet_cache.pending_blocks()
+    }
+
+    pub fn is_pending(&self, x: u32, y: u32) -> bool {
+        self.facet_cache.is_pending(x, y)
+    }
+
+    pub fn read_block(&mut self, x: u32, y: u32) -> Option<Rc<BlockRecord>> {
         self.facet_cache.read_block(x, y)
     }
+
+    pub fn read_land_uncached(&mut self, x: u32, y: u32) -> Option<Block> {
+        self.facet_cache.read_land_uncached(x, y)
+    }
+
+    pub fn read_land(&mut self, x: u32, y: u32) -> Option<Block> {
+        self.facet_cache.read_land(x, y)
+    }
+
+    pub fn set_land(&mut self, x: u32, y: u32, block: Block) {
+        self.facet_cache.set_land(x, y, block);
+    }
+
+    // Writes the edited land of each block back over the facet's map file. Patches never go
+    // in, even while they're shown.
+    pub fn save_land(&mut self, blocks: &[(u32, u32)]) -> Result<()> {
+        let mut writer = writer::MapWriter::open(&self.map_path, self.height_blocks())?;
+        for &(x, y) in blocks {
+            if let Some(block) = self.facet_cache.read_unpatched_land(x, y) {
+                writer.write_block(x, y, &block)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn read_statics(&mut self, x: u32, y: u32) -> Vec<StaticLocation> {
+        self.facet_cache.read_statics(x, y)
+    }
+
+    pub fn set_statics(&mut self, x: u32, y: u32, statics: Vec<StaticLocation>) {
+        self.facet_cache.set_statics(x, y, statics);
+    }
+
+    pub fn save_statics(&mut self, blocks: &[(u32, u32)]) -> Result<()> {
+        let mut writer = writer::StaticWriter::open(
+            &self.static_index,
+            &self.static_path,
+            self.height_blocks(),
+        )?;
+        for &(x, y) in blocks {
+            let statics = self.facet_cache.read_unpatched_statics(x, y);
+            writer.write_block(x, y, &statics)?;
+        }
+        Ok(())
+    }
+
+    pub fn try_read_block(&mut self, x: u32, y: u32) -> Option<Rc<BlockRecord>> {
+        self.facet_cache.try_read_block(x, y)
+    }
+
+    pub fn prefetch(&mut self, x: u32, y: u32, width: u32, height: u32, direction: (i32, i32)) {
+        self.facet_cache.prefetch(x, y, width, height, direction);
+    }
+
+    pub fn receive_prefetched(&mut self) -> Vec<(u32, u32)> {
+        self.facet_cache.receive_prefetched()
+    }
 }
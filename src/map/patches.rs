@@ -0,0 +1,79 @@
+use std::collections::HashMap;
+use std::path::Path;
+use uorustlibs::map::{Block, MapDiffReader, StaticDiffReader, StaticLocation};
+
+// The client's mapdif and stadif overrides for a facet, keyed by block index the same way the
+// map file is laid out
+pub struct Patches {
+    land: HashMap<u32, Block>,
+    statics: HashMap<u32, Vec<StaticLocation>>,
+}
+
+pub fn patch_index(x: u32, y: u32, height_blocks: u32) -> u32 {
+    x * height_blocks + y
+}
+
+pub fn patch_coordinates(index: u32, height_blocks: u32) -> (u32, u32) {
+    (index / height_blocks, index % height_blocks)
+}
+
+impl Patches {
+    pub fn empty() -> Patches {
+        Patches {
+            land: HashMap::new(),
+            statics: HashMap::new(),
+        }
+    }
+
+    pub fn load(map_id: u8) -> Patches {
+        Patches::load_from(&Path::new("./assets"), map_id)
+    }
+
+    // Facets without patch files just come out empty, as do patches that fail to read
+    pub fn load_from(directory: &Path, map_id: u8) -> Patches {
+        let file = |name: &str| directory.join(format!("{}{}.mul", name, map_id));
+        let land = match MapDiffReader::new(&file("mapdifl"), &file("mapdif")) {
+            Ok(mut reader) => reader
+                .read_all()
+                .into_iter()
+                .filter_map(|(index, block)| block.ok().map(|block| (index, block)))
+                .collect(),
+            Err(_) => HashMap::new(),
+        };
+        let statics =
+            match StaticDiffReader::new(&file("stadifl"), &file("stadifi"), &file("stadif")) {
+                Ok(mut reader) => reader
+                    .read_all()
+                    .into_iter()
+                    .filter_map(|(index, statics)| statics.ok().map(|statics| (index, statics)))
+                    .collect(),
+                Err(_) => HashMap::new(),
+            };
+        Patches { land, statics }
+    }
+
+    pub fn land(&self, index: u32) -> Option<&Block> {
+        self.land.get(&index)
+    }
+
+    pub fn statics(&self, index: u32) -> Option<&Vec<StaticLocation>> {
+        self.statics.get(&index)
+    }
+
+    pub fn contains(&self, index: u32) -> bool {
+        self.land.contains_key(&index) || self.statics.contains_key(&index)
+    }
+
+    // Every patched block, land or statics, in file order
+    pub fn indices(&self) -> Vec<u32> {
+        let mut indices = self
+            .land
+            .keys()
+            .chain(self.statics.keys())
+            .cloned()
+            .collect::<Vec<u32>>();
+        indices.sort();
+        indices.dedup();
+        indices
+    }
+}
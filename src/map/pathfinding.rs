@@ -0,0 +1,317 @@
+use map::walkability::CellSurfaces;
+use std::cmp::{max, Ordering};
+use std::collections::{BinaryHeap, HashMap};
+
+// Keeps a search between two unreachable points from reading half the facet
+pub const MAX_EXPLORED: usize = 250_000;
+
+const DIRECTIONS: [(i32, i32); 8] = [
+    (0, -1),
+    (1, -1),
+    (1, 0),
+    (1, 1),
+    (0, 1),
+    (-1, 1),
+    (-1, 0),
+    (-1, -1),
+];
+
+type Node = (i32, i32, i32);
+
+// A* over the walkability model, moving in the 8 directions a mobile can. Diagonal steps need
+// both cells they cut past to be passable as well, so paths don't squeeze between corners.
+// The search is advanced a budget at a time, so it can be spread over several frames.
+pub struct PathSearch {
+    // Cell coordinates and the z stood at, from start to end
+    pub path: Option<Vec<Node>>,
+    // Every cell the search reached, which shows where it got stuck when there's no path
+    pub explored: Vec<(i32, i32)>,
+    pub gave_up: bool,
+    // Taken once the start has been looked up
+    start: Option<(i32, i32)>,
+    end: (i32, i32),
+    finished: bool,
+    cells: HashMap<(i32, i32), Option<CellSurfaces>>,
+    came_from: HashMap<Node, Node>,
+    steps_to: HashMap<Node, usize>,
+    open: BinaryHeap<Open>,
+}
+
+#[derive(PartialEq, Eq)]
+struct Open {
+    estimate: usize,
+    steps: usize,
+    node: Node,
+}
+
+// Reversed so the heap hands out the lowest estimate first
+impl Ord for Open {
+    fn cmp(&self, other: &Open) -> Ordering {
+        other
+            .estimate
+            .cmp(&self.estimate)
+            .then(self.steps.cmp(&other.steps))
+            .then(self.node.cmp(&other.node))
+    }
+}
+
+impl PartialOrd for Open {
+    fn partial_cmp(&self, other: &Open) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+fn distance((x1, y1): (i32, i32), (x2, y2): (i32, i32)) -> usize {
+    max((x1 - x2).abs(), (y1 - y2).abs()) as usize
+}
+
+impl PathSearch {
+    pub fn new(start: (i32, i32), end: (i32, i32)) -> PathSearch {
+        PathSearch {
+            path: None,
+            explored: vec![],
+            gave_up: false,
+            start: Some(start),
+            end,
+            finished: false,
+            cells: HashMap::new(),
+            came_from: HashMap::new(),
+            steps_to: HashMap::new(),
+            open: BinaryHeap::new(),
+        }
+    }
+
+    pub fn finished(&self) -> bool {
+        self.finished
+    }
+
+    // Steps taken, where diagonal steps take as long as straight ones
+    pub fn length(&self) -> Option<usize> {
+        self.path.as_ref().map(|path| path.len().saturating_sub(1))
+    }
+
+    // Each cell is only read once, however many times the search looks at it
+    fn cell<F>(&mut self, x: i32, y: i32, surfaces_at: &mut F) -> Option<&CellSurfaces>
+    where
+        F: FnMut(i32, i32) -> Option<CellSurfaces>,
+    {
+        self.cells
+            .entry((x, y))
+            .or_insert_with(|| surfaces_at(x, y))
+            .as_ref()
+    }
+
+    fn step<F>(&mut self, x: i32, y: i32, z: i32, surfaces_at: &mut F) -> Option<i32>
+    where
+        F: FnMut(i32, i32) -> Option<CellSurfaces>,
+    {
+        self.cell(x, y, surfaces_at)
+            .and_then(|surfaces| surfaces.step_from(z))
+    }
+
+    // Explores up to budget more cells, reading any it hasn't seen through surfaces_at, and
+    // returns whether the search is over
+    pub fn advance<F>(&mut self, budget: usize, mut surfaces_at: F) -> bool
+    where
+        F: FnMut(i32, i32) -> Option<CellSurfaces>,
+    {
+        if let Some(start) = self.start.take() {
+            match self
+                .cell(start.0, start.1, &mut surfaces_at)
+                .and_then(|cell| cell.highest())
+            {
+                Some(z) => {
+                    let start_node = (start.0, start.1, z);
+                    self.steps_to.insert(start_node, 0);
+                    self.open.push(Open {
+                        estimate: distance(start, self.end),
+                        steps: 0,
+                        node: start_node,
+                    });
+                }
+                None => self.finished = true,
+            }
+        }
+        let mut explored = 0;
+        while !self.finished && explored < budget {
+            let (steps, node) = match self.open.pop() {
+                Some(Open { steps, node, .. }) => (steps, node),
+                None => {
+                    self.finished = true;
+                    break;
+                }
+            };
+            if steps > self.steps_to[&node] {
+                continue;
+            }
+            let (x, y, z) = node;
+            self.explored.push((x, y));
+            explored += 1;
+            if (x, y) == self.end {
+                let mut path = vec![node];
+                let mut current = node;
+                while let Some(&previous) = self.came_from.get(&current) {
+                    path.push(previous);
+                    current = previous;
+                }
+                path.reverse();
+                self.path = Some(path);
+                self.finished = true;
+                break;
+            }
+            if self.explored.len() >= MAX_EXPLORED {
+                self.gave_up = true;
+                self.finished = true;
+                break;
+            }
+            for &(dx, dy) in DIRECTIONS.iter() {
+                let new_z = match self.step(x + dx, y + dy, z, &mut surfaces_at) {
+                    Some(new_z) => new_z,
+                    None => continue,
+                };
+                if dx != 0
+                    && dy != 0
+                    && (self.step(x + dx, y, z, &mut surfaces_at).is_none()
+                        || self.step(x, y + dy, z, &mut surfaces_at).is_none())
+                {
+                    continue;
+                }
+                let next = (x + dx, y + dy, new_z);
+                let next_steps = steps + 1;
+                if self
+                    .steps_to
+                    .get(&next)
+                    .map_or(true, |&known| next_steps < known)
+                {
+                    self.steps_to.insert(next, next_steps);
+                    self.came_from.insert(next, node);
+                    self.open.push(Open {
+                        estimate: next_steps + distance((next.0, next.1), self.end),
+                        steps: next_steps,
+                        node: next,
+                    });
+                }
+            }
+        }
+        if self.finished {
+            // Only the result is wanted from here on
+            self.cells = HashMap::new();
+            self.came_from = HashMap::new();
+            self.steps_to = HashMap::new();
+            self.open = BinaryHeap::new();
+        }
+        self.finished
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PathSearch, MAX_EXPLORED};
+    use caches::facet_cache::Altitudes;
+    use map::walkability::{cell_surfaces, CellSurfaces};
+    use uorustlibs::map::StaticLocation;
+    use uorustlibs::tiledata::Flags;
+
+    const FLAT: Altitudes = Altitudes {
+        x1y1: 0,
+        x2y1: 0,
+        x1y2: 0,
+        x2y2: 0,
+    };
+
+    fn open_cell() -> CellSurfaces {
+        cell_surfaces(0, &FLAT, &[], |_| (0, 0))
+    }
+
+    fn wall_cell() -> CellSurfaces {
+        let wall = StaticLocation {
+            object_id: 1,
+            x: 0,
+            y: 0,
+            altitude: 0,
+            unknown: 0,
+        };
+        cell_surfaces(0, &FLAT, &[&wall], |_| (Flags::ImpassableFlag as u32, 20))
+    }
+
+    // '.' is open land and '#' a wall, with nothing at all off the edges
+    fn grid(rows: &'static [&'static str]) -> impl FnMut(i32, i32) -> Option<CellSurfaces> {
+        move |x, y| {
+            if x < 0 || y < 0 {
+                return None;
+            }
+            match rows
+                .get(y as usize)
+                .and_then(|row| row.as_bytes().get(x as usize))
+            {
+                Some(b'.') => Some(open_cell()),
+                Some(b'#') => Some(wall_cell()),
+                _ => None,
+            }
+        }
+    }
+
+    fn run(rows: &'static [&'static str], start: (i32, i32), end: (i32, i32)) -> PathSearch {
+        let mut search = PathSearch::new(start, end);
+        assert!(search.advance(usize::max_value(), grid(rows)));
+        search
+    }
+
+    #[test]
+    fn corridors_are_followed_in_a_straight_line() {
+        let search = run(&["######", "......", "######"], (0, 1), (5, 1));
+        let cells = search
+            .path
+            .unwrap()
+            .iter()
+            .map(|&(x, y, _)| (x, y))
+            .collect::<Vec<_>>();
+        assert_eq!(cells, (0..6).map(|x| (x, 1)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn diagonals_do_not_cut_past_corners() {
+        let search = run(&[".#", ".."], (0, 0), (1, 1));
+        assert_eq!(search.length(), Some(2));
+        assert_eq!(search.path.unwrap()[1], (0, 1, 0));
+        let search = run(&[".#", "#."], (0, 0), (1, 1));
+        assert_eq!(search.path, None);
+        assert!(!search.gave_up);
+    }
+
+    #[test]
+    fn walled_off_targets_are_unreachable() {
+        let rows = &[".....", ".###.", ".#.#.", ".###.", "....."];
+        let search = run(rows, (0, 0), (2, 2));
+        assert_eq!(search.path, None);
+        assert!(!search.gave_up);
+        assert_eq!(search.explored.len(), 16);
+    }
+
+    #[test]
+    fn searches_give_up_after_max_explored() {
+        let mut search = PathSearch::new((0, 0), (-10, -10));
+        let open = |x, y| {
+            if x >= 0 && y >= 0 && x < 1000 && y < 1000 {
+                Some(open_cell())
+            } else {
+                None
+            }
+        };
+        assert!(search.advance(usize::max_value(), open));
+        assert_eq!(search.path, None);
+        assert!(search.gave_up);
+        assert_eq!(search.explored.len(), MAX_EXPLORED);
+    }
+
+    #[test]
+    fn advancing_stops_at_the_budget() {
+        let rows = &["......", "......", "......"];
+        let mut search = PathSearch::new((0, 1), (5, 1));
+        let mut surfaces = grid(rows);
+        assert!(!search.advance(2, &mut surfaces));
+        assert_eq!(search.explored.len(), 2);
+        assert!(search.advance(usize::max_value(), &mut surfaces));
+        assert_eq!(search.length(), Some(5));
+    }
+}
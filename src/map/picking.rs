@@ -0,0 +1,196 @@
+use caches::art_cache::ArtCache;
+use caches::art_mask_cache::ArtMaskCache;
+use cgmath::Point2;
+use ggez::Context;
+use map::render::{
+    collect_items, skew_points, Ceiling, RenderOptions, ViewBlock, ViewPiece, TILE_SIZE,
+};
+use uorustlibs::tiledata::Flags;
+
+const FLAG_NAMES: [(u32, &'static str); 26] = [
+    (Flags::BackgroundFlag as u32, "background"),
+    (Flags::WeaponFlag as u32, "weapon"),
+    (Flags::TransparentFlag as u32, "transparent"),
+    (Flags::TranslucentFlag as u32, "translucent"),
+    (Flags::WallFlag as u32, "wall"),
+    (Flags::DamagingFlag as u32, "damaging"),
+    (Flags::ImpassableFlag as u32, "impassable"),
+    (Flags::WetFlag as u32, "wet"),
+    (Flags::SurfaceFlag as u32, "surface"),
+    (Flags::BridgeFlag as u32, "bridge"),
+    (Flags::StackableFlag as u32, "stackable"),
+    (Flags::WindowFlag as u32, "window"),
+    (Flags::NoShootFlag as u32, "no shoot"),
+    (Flags::FoliageFlag as u32, "foliage"),
+    (Flags::PartialHueFlag as u32, "partial hue"),
+    (Flags::MapFlag as u32, "map"),
+    (Flags::ContainerFlag as u32, "container"),
+    (Flags::WearableFlag as u32, "wearable"),
+    (Flags::LightSourceFlag as u32, "light source"),
+    (Flags::AnimatedFlag as u32, "animated"),
+    (Flags::NoDiagonalFlag as u32, "no diagonal"),
+    (Flags::ArmorFlag as u32, "armor"),
+    (Flags::RoofFlag as u32, "roof"),
+    (Flags::DoorFlag as u32, "door"),
+    (Flags::StairBackFlag as u32, "stair back"),
+    (Flags::StairRightFlag as u32, "stair right"),
+];
+
+pub fn flag_names(flags: u32) -> Vec<&'static str> {
+    FLAG_NAMES
+        .iter()
+        .filter(|&&(flag, _)| flags & flag != 0)
+        .map(|&(_, name)| name)
+        .collect()
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum PickedItem {
+    Land(u16),
+    Static {
+        index: usize,
+        object_id: u16,
+        hue: u16,
+    },
+}
+
+#[derive(Clone, Debug)]
+pub struct Pick {
+    pub item: PickedItem,
+    pub x: u32,
+    pub y: u32,
+    pub z: i32,
+    pub block_x: u32,
+    pub block_y: u32,
+    pub name: String,
+    pub flags: u32,
+}
+
+fn edge(a: [f32; 2], b: [f32; 2], c: [f32; 2]) -> f32 {
+    (b[0] - a[0]) * (c[1] - a[1]) - (b[1] - a[1]) * (c[0] - a[0])
+}
+
+fn in_triangle(points: [[f32; 2]; 3], point: [f32; 2]) -> bool {
+    let sides = [
+        edge(points[0], points[1], point),
+        edge(points[1], points[2], point),
+        edge(points[2], points[0], point),
+    ];
+    sides.iter().all(|&side| side >= 0.0) || sides.iter().all(|&side| side <= 0.0)
+}
+
+// Finds whatever is drawn on top at a point, in the same coordinates as the blocks'
+// transforms. Everything is placed and sorted by collect_items, the way the renderers do it,
+// and tried from the top down. Art is only tested against its transparency once the point is
+// inside it, so clicks go through the gaps in trees and fences.
+pub fn pick(
+    ctx: &mut Context,
+    art_cache: &mut ArtCache,
+    masks: &mut ArtMaskCache,
+    blocks: &[ViewBlock],
+    point: Point2<f32>,
+    ceiling: Option<Ceiling>,
+) -> Option<Pick> {
+    // Anything over the ceiling is out of the way, even when it's drawn faded
+    let options = RenderOptions {
+        ceiling: ceiling.map(|ceiling| Ceiling {
+            translucent: false,
+            ..ceiling
+        }),
+        ..RenderOptions::default()
+    };
+    let items = collect_items(blocks, &options, |object_id| {
+        art_cache
+            .read_static(ctx, object_id as u32)
+            .as_ref()
+            .map(|(art, tiledata)| (tiledata.flags, tiledata.height, art.height() as u32))
+    });
+    for item in items.iter().rev() {
+        let relative = [point.x - item.dest.x, point.y - item.dest.y];
+        match item.piece {
+            ViewPiece::Land {
+                block, cell, flat, ..
+            } => {
+                let (block_x, block_y) = blocks[block].position;
+                let record = &blocks[block].record;
+                let land = record.block.cells[cell];
+                let corners = skew_points(&record.altitudes[cell]);
+                let lowest = corners.iter().map(|p| p[1]).fold(f32::MAX, f32::min);
+                let highest = corners.iter().map(|p| p[1]).fold(f32::MIN, f32::max);
+                if relative[0] < 0.0
+                    || relative[0] > TILE_SIZE
+                    || relative[1] < lowest
+                    || relative[1] > highest
+                {
+                    continue;
+                }
+                let hit = if flat {
+                    masks
+                        .read_tile(land.graphic as u32)
+                        .map_or(false, |mask| mask.opaque_at(relative[0], relative[1]))
+                } else {
+                    in_triangle([corners[0], corners[1], corners[2]], relative)
+                        || in_triangle([corners[0], corners[2], corners[3]], relative)
+                };
+                if !hit {
+                    continue;
+                }
+                if let Some((_, ref tiledata)) = *art_cache.read_tile(ctx, land.graphic as u32) {
+                    return Some(Pick {
+                        item: PickedItem::Land(land.graphic),
+                        x: block_x * 8 + (cell % 8) as u32,
+                        y: block_y * 8 + (cell / 8) as u32,
+                        z: land.altitude as i32,
+                        block_x,
+                        block_y,
+                        name: tiledata.name.clone(),
+                        flags: tiledata.flags,
+                    });
+                }
+            }
+            ViewPiece::Static {
+                block,
+                index,
+                object_id,
+                ..
+            } => {
+                let (width, height) = match *art_cache.read_static(ctx, object_id as u32) {
+                    Some((ref art, _)) => (art.width() as f32, art.height() as f32),
+                    None => continue,
+                };
+                if relative[0] < 0.0
+                    || relative[1] < 0.0
+                    || relative[0] >= width
+                    || relative[1] >= height
+                {
+                    continue;
+                }
+                let hit = masks
+                    .read_static(object_id as u32)
+                    .map_or(false, |mask| mask.opaque_at(relative[0], relative[1]));
+                if !hit {
+                    continue;
+                }
+                let (block_x, block_y) = blocks[block].position;
+                let location = &blocks[block].record.statics[index];
+                if let Some((_, ref tiledata)) = *art_cache.read_static(ctx, object_id as u32) {
+                    return Some(Pick {
+                        item: PickedItem::Static {
+                            index,
+                            object_id,
+                            hue: location.unknown,
+                        },
+                        x: block_x * 8 + location.x as u32,
+                        y: block_y * 8 + location.y as u32,
+                        z: location.altitude as i32,
+                        block_x,
+                        block_y,
+                        name: tiledata.name.clone(),
+                        flags: tiledata.flags,
+                    });
+                }
+            }
+        }
+    }
+    None
+}
@@ -0,0 +1,211 @@
+use caches::image_art_cache::ImageArtCache;
+use cgmath::Point2;
+use image::{imageops, Rgba, RgbaImage};
+use map::render::{RenderOptions, ViewBlock};
+use map::software_render::render_blocks;
+use map::Facet;
+use std::cmp::{max, min};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Result, Write};
+use std::path::{Path, PathBuf};
+
+// Posters are drawn a piece at a time, so only the blocks touching a piece are held at once
+pub const POSTER_TILE_WIDTH: u32 = 2048;
+// Rows of pieces are kept short, as each is held whole until it's written out. A strip across
+// a whole facet is still a couple of hundred megabytes.
+const POSTER_STRIP_HEIGHT: u32 = 256;
+// Room over the highest point for whatever's stood on it, and either side for art wider
+// than a tile
+const STATIC_HEADROOM: i64 = 256;
+const STATIC_OVERHANG: i64 = 128;
+const BLOCK_STEP: i64 = 22 * 8;
+
+// Where a block's first cell is drawn at z 0, in pixels from the top left of the facet
+fn block_origin(block_x: i64, block_y: i64) -> (i64, i64) {
+    (
+        BLOCK_STEP * (block_x - block_y),
+        BLOCK_STEP * (block_x + block_y),
+    )
+}
+
+// The lowest and highest anything in the blocks sits
+fn altitude_range(facet: &mut Facet, blocks: &[(u32, u32)]) -> (i64, i64) {
+    let (mut lowest, mut highest) = (0, 0);
+    for &(x, y) in blocks {
+        if let Some(record) = facet.read_block(x, y) {
+            let land = record.block.cells.iter().map(|cell| cell.altitude as i64);
+            let statics = record
+                .statics
+                .iter()
+                .map(|location| location.altitude as i64);
+            for z in land.chain(statics) {
+                lowest = min(lowest, z);
+                highest = max(highest, z);
+            }
+        }
+    }
+    (lowest, highest)
+}
+
+pub struct Poster {
+    // The cells drawn, as x, y, width and height
+    pub area: (u32, u32, u32, u32),
+    pub options: RenderOptions,
+    pub background: Rgba<u8>,
+}
+
+// Where the poster sits and what goes in it, worked out before anything is drawn
+struct Layout {
+    // The blocks that reach across each column of pieces
+    columns: Vec<Vec<(u32, u32)>>,
+    lowest: i64,
+    highest: i64,
+    // The poster's edges, in the same pixels as block_origin
+    left: i64,
+    top: i64,
+    width: u32,
+    height: u32,
+    options: RenderOptions,
+}
+
+impl Poster {
+    fn blocks(&self) -> Vec<(u32, u32)> {
+        let (x, y, width, height) = self.area;
+        let mut blocks = vec![];
+        for block_y in (y / 8)..=((y + height - 1) / 8) {
+            for block_x in (x / 8)..=((x + width - 1) / 8) {
+                blocks.push((block_x, block_y));
+            }
+        }
+        blocks
+    }
+
+    fn layout(&self, facet: &mut Facet) -> Result<Layout> {
+        let (x, y, width, height) = self.area;
+        let outside = |start: u32, length: u32, limit: u32| {
+            start.checked_add(length).map_or(true, |end| end > limit)
+        };
+        if width == 0
+            || height == 0
+            || outside(x, width, facet.width_blocks() * 8)
+            || outside(y, height, facet.height_blocks() * 8)
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Area is outside the facet",
+            ));
+        }
+        let blocks = self.blocks();
+        let (lowest, highest) = altitude_range(facet, &blocks);
+        let (x, y) = (x as i64, y as i64);
+        let (right, bottom) = (x + width as i64 - 1, y + height as i64 - 1);
+        let left = 22 * (x - bottom) + 22 * 7;
+        let top = 22 * (x + y) - highest * 4 - STATIC_HEADROOM;
+        let layout_width = (22 * (right - y) + 22 * 7 + 44 - left) as u32;
+
+        // Blocks only move across the poster by column, so each piece only looks at its own
+        let mut columns =
+            vec![vec![]; ((layout_width + POSTER_TILE_WIDTH - 1) / POSTER_TILE_WIDTH) as usize];
+        for &(block_x, block_y) in &blocks {
+            let (origin_x, _) = block_origin(block_x as i64, block_y as i64);
+            let (block_left, block_right) = (
+                origin_x - STATIC_OVERHANG - left,
+                origin_x + 22 * 16 + STATIC_OVERHANG - left,
+            );
+            let first = max(block_left, 0) / POSTER_TILE_WIDTH as i64;
+            let last =
+                min(block_right - 1, layout_width as i64 - 1).div_euclid(POSTER_TILE_WIDTH as i64);
+            for column in first..=last {
+                columns[column as usize].push((block_x, block_y));
+            }
+        }
+        Ok(Layout {
+            columns,
+            lowest,
+            highest,
+            left,
+            top,
+            width: layout_width,
+            height: (22 * (right + bottom) + 44 - lowest * 4 - top) as u32,
+            options: RenderOptions {
+                cells: Some((x as i32, y as i32, right as i32 + 1, bottom as i32 + 1)),
+                ..self.options
+            },
+        })
+    }
+
+    // Renders a row of pieces and stitches them into one strip, the full width of the poster
+    fn render_row(
+        &self,
+        facet: &mut Facet,
+        art: &mut ImageArtCache,
+        layout: &Layout,
+        row: u32,
+    ) -> RgbaImage {
+        let (lowest, highest) = (layout.lowest, layout.highest);
+        let tile_top = layout.top + (row * POSTER_STRIP_HEIGHT) as i64;
+        let tile_height = min(
+            POSTER_STRIP_HEIGHT,
+            layout.height - row * POSTER_STRIP_HEIGHT,
+        );
+        let mut strip = RgbaImage::from_pixel(layout.width, tile_height, self.background);
+        for (column, blocks) in layout.columns.iter().enumerate() {
+            let column = column as u32;
+            let tile_left = layout.left + (column * POSTER_TILE_WIDTH) as i64;
+            let tile_width = min(POSTER_TILE_WIDTH, layout.width - column * POSTER_TILE_WIDTH);
+            let mut view = vec![];
+            for &(block_x, block_y) in blocks {
+                let (origin_x, origin_y) = block_origin(block_x as i64, block_y as i64);
+                let touches = origin_y - highest * 4 - STATIC_HEADROOM
+                    < tile_top + tile_height as i64
+                    && origin_y + 22 * 16 - lowest * 4 > tile_top;
+                if !touches {
+                    continue;
+                }
+                if let Some(record) = facet.read_block(block_x, block_y) {
+                    view.push(ViewBlock {
+                        record,
+                        position: (block_x, block_y),
+                        transform: Point2::new(
+                            (origin_x - tile_left) as f32,
+                            (origin_y - tile_top) as f32,
+                        ),
+                        origin: (block_x as i32 * 8, block_y as i32 * 8),
+                    });
+                }
+            }
+            let mut tile = RgbaImage::from_pixel(tile_width, tile_height, self.background);
+            render_blocks(&mut tile, art, &view, &layout.options);
+            imageops::replace(&mut strip, &tile, column * POSTER_TILE_WIDTH, 0);
+        }
+        strip
+    }
+
+    // Posters can be far bigger than memory, so each row of pieces is written out as soon as
+    // it's drawn
+    pub fn export(&self, facet: &mut Facet, map_id: u8, directory: &Path) -> Result<PathBuf> {
+        let layout = self.layout(facet)?;
+        fs::create_dir_all(directory)?;
+        let (x, y, width, height) = self.area;
+        let path = directory.join(format!(
+            "poster{}_{}_{}_{}x{}.png",
+            map_id, x, y, width, height
+        ));
+        let file = BufWriter::new(File::create(&path)?);
+        let mut encoder = png::Encoder::new(file, layout.width, layout.height);
+        encoder.set_color(png::ColorType::RGBA);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        let mut stream = writer.stream_writer();
+
+        let mut art = ImageArtCache::new();
+        let rows = (layout.height + POSTER_STRIP_HEIGHT - 1) / POSTER_STRIP_HEIGHT;
+        for row in 0..rows {
+            let strip = self.render_row(facet, &mut art, &layout, row);
+            stream.write_all(&strip)?;
+            println!("Rendered row {} of {}", row + 1, rows);
+        }
+        stream.finish()?;
+        Ok(path)
+    }
+}
@@ -0,0 +1,132 @@
+use std::io::Result;
+use uorustlibs::color::{Color as ColorTrait, Color16};
+use uorustlibs::map::StaticLocation;
+use uorustlibs::tiledata::Flags;
+
+// radarcol.mul holds the land colours first, with the statics following on at this offset
+pub const STATIC_COLOR_OFFSET: usize = 0x4000;
+
+// How an item ranks against others whose tops are at the same altitude. Land loses to
+// anything standing on it, apart from flat background pieces like rugs and dirt patches.
+// Surfaces such as floors and tables lose to whatever's been put on them.
+const RANK_FLAT_BACKGROUND: u8 = 0;
+const RANK_LAND: u8 = 1;
+const RANK_SURFACE: u8 = 2;
+const RANK_STATIC: u8 = 3;
+
+pub fn radar_color(radar_cols: &Result<Vec<Color16>>, index: usize) -> Option<(u8, u8, u8)> {
+    match radar_cols {
+        &Ok(ref colors) => colors.get(index).map(|color| {
+            let (r, g, b, _) = color.to_rgba();
+            (r, g, b)
+        }),
+        _ => None,
+    }
+}
+
+pub fn land_color(radar_cols: &Result<Vec<Color16>>, graphic: u16) -> (u8, u8, u8) {
+    radar_color(radar_cols, graphic as usize).unwrap_or_else(|| {
+        let (r, g, b, _) = graphic.to_rgba();
+        (r, g, b)
+    })
+}
+
+pub fn static_color(radar_cols: &Result<Vec<Color16>>, object_id: u16) -> (u8, u8, u8) {
+    radar_color(radar_cols, STATIC_COLOR_OFFSET + object_id as usize).unwrap_or((0, 0, 0))
+}
+
+// Picks the static the client's radar would show for a cell. Statics are compared by the
+// top of the item (z plus its tiledata height) rather than where they start, and anything
+// that finishes below the land is buried and never shown. Later statics win ties, as the
+// client draws them over earlier ones. Foliage gets no special treatment: the client only
+// fades treetops in the game view when they hide the player, and the radar shows them whole.
+pub fn topmost_static<'a, F>(
+    cell_statics: &[&'a StaticLocation],
+    land_altitude: Option<i8>,
+    mut static_data: F,
+    hide_roofs: bool,
+) -> Option<&'a StaticLocation>
+where
+    F: FnMut(u16) -> (u32, u8),
+{
+    let mut best: Option<(&StaticLocation, (i32, u8))> = None;
+    let land = land_altitude.map(|altitude| (altitude as i32, RANK_LAND));
+    for &stat in cell_statics {
+        let (flags, height) = static_data(stat.object_id);
+        if hide_roofs && flags & Flags::RoofFlag as u32 != 0 {
+            continue;
+        }
+        let top = stat.altitude as i32 + height as i32;
+        let rank = if flags & Flags::BackgroundFlag as u32 != 0 && height == 0 {
+            RANK_FLAT_BACKGROUND
+        } else if flags & Flags::SurfaceFlag as u32 != 0 {
+            RANK_SURFACE
+        } else {
+            RANK_STATIC
+        };
+        match land {
+            Some(land_key) if (top, rank) <= land_key => continue,
+            _ => (),
+        }
+        match best {
+            Some((_, best_key)) if (top, rank) < best_key => (),
+            _ => best = Some((stat, (top, rank))),
+        }
+    }
+    best.map(|(stat, _)| stat)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::topmost_static;
+    use uorustlibs::map::StaticLocation;
+    use uorustlibs::tiledata::Flags;
+
+    const FLOOR: u16 = 1;
+    const TABLE: u16 = 2;
+    const RUG: u16 = 3;
+    const VASE: u16 = 4;
+
+    fn static_data(object_id: u16) -> (u32, u8) {
+        match object_id {
+            FLOOR => (Flags::SurfaceFlag as u32, 0),
+            TABLE => (Flags::SurfaceFlag as u32, 6),
+            RUG => (Flags::BackgroundFlag as u32, 0),
+            _ => (0, 0),
+        }
+    }
+
+    fn item(object_id: u16, altitude: i8) -> StaticLocation {
+        StaticLocation {
+            object_id,
+            x: 0,
+            y: 0,
+            altitude,
+            unknown: 0,
+        }
+    }
+
+    fn topmost(statics: &[StaticLocation], land_altitude: Option<i8>) -> Option<u16> {
+        let statics = statics.iter().collect::<Vec<_>>();
+        topmost_static(&statics, land_altitude, static_data, false).map(|stat| stat.object_id)
+    }
+
+    #[test]
+    fn items_beat_the_surfaces_they_stand_on() {
+        // Listed after the vase, so the table would win a plain tie
+        assert_eq!(topmost(&[item(VASE, 6), item(TABLE, 0)], None), Some(VASE));
+        assert_eq!(
+            topmost(&[item(VASE, 0), item(FLOOR, 0)], Some(0)),
+            Some(VASE)
+        );
+    }
+
+    #[test]
+    fn flat_backgrounds_lose_to_the_land_under_them() {
+        assert_eq!(topmost(&[item(RUG, 0)], Some(0)), None);
+        // Raised off the land, they show
+        assert_eq!(topmost(&[item(RUG, 1)], Some(0)), Some(RUG));
+        // Surfaces level with the land still cover it
+        assert_eq!(topmost(&[item(FLOOR, 0)], Some(0)), Some(FLOOR));
+    }
+}
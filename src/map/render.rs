@@ -1,26 +1,87 @@
 use caches::art_cache::ArtCache;
-use caches::facet_cache::Altitudes;
+use caches::facet_cache::{Altitudes, BlockRecord};
+use caches::land_mesh_cache::LandMeshCache;
 use caches::texmap_cache::TexMapCache;
 use cgmath::Point2;
-use ggez::graphics::{self, DrawParam, Image, Mesh, MeshBuilder, Vertex};
+use ggez::graphics::{self, DrawMode, DrawParam, Image, Mesh, MeshBuilder, Rect, Vertex};
 use ggez::{Context, GameResult};
-use std::cmp::Ordering;
-use uorustlibs::map::{Block, StaticLocation};
-use uorustlibs::tiledata::{Flags, MapTileData, StaticTileData};
+use map::walkability::land_heights;
+use map::Facet;
+use std::cmp::{max, min};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::rc::Rc;
+use uorustlibs::tiledata::Flags;
 
 pub const TILE_SIZE: f32 = 44.0;
 
-enum DrawableItem {
-    Static(Image, StaticTileData),
-    Tile(Image, MapTileData),
-    Skewable(Mesh, MapTileData),
+// Blocks are 352 pixels across, so a block and the ring of blocks around it are drawn
+// zoomed out, with that block in the middle
+pub const NEIGHBOURHOOD: Rect = Rect {
+    x: -624.0,
+    y: -376.0,
+    w: 1600.0,
+    h: 1200.0,
+};
+const BLOCK_STEP: f32 = 176.0;
+
+// Takes away what's above someone standing inside, the way the client lifts off roofs, so
+// interiors and dungeons can be seen
+#[derive(Clone, Copy, Debug)]
+pub struct Ceiling {
+    pub z: i8,
+    pub hide_roofs: bool,
+    // Fade what's above rather than leaving it out
+    pub translucent: bool,
+}
+
+impl Ceiling {
+    pub fn covers(&self, altitude: i8, flags: u32) -> bool {
+        altitude >= self.z || (self.hide_roofs && flags & Flags::RoofFlag as u32 != 0)
+    }
+
+    pub fn hides(&self, altitude: i8, flags: u32) -> bool {
+        !self.translucent && self.covers(altitude, flags)
+    }
+}
+
+enum LandDrawable {
+    Tile(Image),
+    Skewable(Mesh),
+}
+
+// Which parts of a view are drawn
+#[derive(Clone, Copy)]
+pub struct RenderOptions {
+    pub ceiling: Option<Ceiling>,
+    pub statics: bool,
+    // Only cells in this rectangle are drawn, as left, top, right and bottom, in the same
+    // coordinates as the blocks' origins. The right and bottom edges are left out.
+    pub cells: Option<(i32, i32, i32, i32)>,
+}
+
+impl Default for RenderOptions {
+    fn default() -> RenderOptions {
+        RenderOptions {
+            ceiling: None,
+            statics: true,
+            cells: None,
+        }
+    }
+}
+
+impl RenderOptions {
+    fn includes(&self, x: i32, y: i32) -> bool {
+        self.cells.map_or(true, |(left, top, right, bottom)| {
+            x >= left && x < right && y >= top && y < bottom
+        })
+    }
 }
 
 fn add(a: Point2<f32>, b: Point2<f32>) -> Point2<f32> {
     Point2::new(a.x + b.x, a.y + b.y)
 }
 
-fn cell_at(x: i32, y: i32) -> Point2<f32> {
+pub fn cell_at(x: i32, y: i32) -> Point2<f32> {
     Point2::new(
         ((22 * 7) + (22 * x) - (y * 22)) as f32,
         ((22 * y) + (x * 22)) as f32,
@@ -52,13 +113,14 @@ pub fn generate_vertices(params: &[[f32; 2]; 4]) -> [Vertex; 4] {
     ]
 }
 
-pub fn skew(ctx: &mut Context, tile: &Image, altitudes: &Altitudes) -> Mesh {
+// The corners of a stretched texmap, relative to where the cell's flat tile would go
+pub fn skew_points(altitudes: &Altitudes) -> [[f32; 2]; 4] {
     let top_right = altitudes.x2y1 - altitudes.x1y1;
     let bottom_right = altitudes.x2y2 - altitudes.x1y1;
     let bottom_left = altitudes.x1y2 - altitudes.x1y1;
 
     let increment = 4.0;
-    let vertices = generate_vertices(&[
+    [
         [0.5 * TILE_SIZE, 0.0],
         [
             1.0 * TILE_SIZE,
@@ -69,7 +131,11 @@ pub fn skew(ctx: &mut Context, tile: &Image, altitudes: &Altitudes) -> Mesh {
             1.0 * TILE_SIZE - (increment * bottom_right as f32),
         ],
         [0.0, 0.5 * TILE_SIZE - (increment * bottom_left as f32)],
-    ]);
+    ]
+}
+
+pub fn skew(ctx: &mut Context, tile: &Image, altitudes: &Altitudes) -> Mesh {
+    let vertices = generate_vertices(&skew_points(altitudes));
     MeshBuilder::new()
         .raw(&vertices, &[0, 1, 2, 0, 2, 3], Some(tile.clone()))
         .expect("Failed to create raw mesh")
@@ -77,115 +143,601 @@ pub fn skew(ctx: &mut Context, tile: &Image, altitudes: &Altitudes) -> Mesh {
         .expect("Failed to generate mesh")
 }
 
-pub fn draw_block(
+// Where an item falls in the client's drawing order. Diagonals further down the screen come
+// later, then within a diagonal items go by z, with land a little under anything stood on it,
+// backgrounds under what's in front of them and anything with height over what's flat.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DepthKey {
+    depth: i32,
+    z: i32,
+    kind: u8,
+    height: u8,
+    x: i32,
+}
+
+impl DepthKey {
+    pub fn land(x: i32, y: i32, z: i32) -> DepthKey {
+        DepthKey {
+            depth: x + y,
+            z: z - 1,
+            kind: 0,
+            height: 0,
+            x,
+        }
+    }
+
+    pub fn item(x: i32, y: i32, z: i32, flags: u32, height: u8) -> DepthKey {
+        let mut priority = z;
+        if flags & Flags::BackgroundFlag as u32 != 0 {
+            priority -= 1;
+        }
+        if height > 0 {
+            priority += 1;
+        }
+        DepthKey {
+            depth: x + y,
+            z: priority,
+            kind: 1,
+            height,
+            x,
+        }
+    }
+}
+
+pub enum ViewPiece {
+    // A cell of the view's block at this index, stretched over its corners unless it's flat
+    Land {
+        block: usize,
+        cell: usize,
+        flat: bool,
+        high: i32,
+    },
+    // The static at this index in a view block's record. Faded statics are above the ceiling.
+    Static {
+        block: usize,
+        index: usize,
+        object_id: u16,
+        faded: bool,
+    },
+}
+
+pub struct ViewItem {
+    pub piece: ViewPiece,
+    pub dest: Point2<f32>,
+    pub key: DepthKey,
+}
+
+// A block's land, built once. Cells sharing art or a texmap go into one mesh, which is drawn
+// under everything else, and each cell is kept on its own as well for the few that have to be
+// sorted in with everything else.
+pub struct LandMeshes {
+    batches: Vec<Mesh>,
+    cells: Vec<Option<LandDrawable>>,
+}
+
+fn is_flat(altitude: i8, altitudes: &Altitudes) -> bool {
+    altitudes.x1y1 == altitudes.x1y2 && altitude == altitudes.x2y1 && altitude == altitudes.x2y2
+}
+
+const QUAD: [[f32; 2]; 4] = [
+    [0.0, 0.0],
+    [TILE_SIZE, 0.0],
+    [TILE_SIZE, TILE_SIZE],
+    [0.0, TILE_SIZE],
+];
+
+pub fn build_land(
     ctx: &mut Context,
     art_cache: &mut ArtCache,
     texmap_cache: &mut TexMapCache,
-    maybe_block: Option<&Block>,
-    statics: &Vec<StaticLocation>,
-    altitudes: &Vec<Altitudes>,
-    transform: Point2<f32>,
-) -> GameResult<()> {
+    record: &BlockRecord,
+) -> GameResult<LandMeshes> {
+    // Flat tiles are keyed by their art and stretched ones by their texmap
+    let mut batches: BTreeMap<(bool, u32), (Image, Vec<Vertex>, Vec<u32>)> = BTreeMap::new();
+    let mut cells = vec![];
     for y in 0..(8 as usize) {
         for x in 0..(8 as usize) {
-            let cell_statics = statics
-                .iter()
-                .filter(|s| s.x == x as u8 && s.y == y as u8)
-                .collect::<Vec<&StaticLocation>>();
-            let mut tiles: Vec<(DrawableItem, Point2<f32>, i8)> = vec![];
-            match maybe_block {
-                Some(block) => {
-                    let cell = block.cells[y * 8 + x];
-                    let altitudes = altitudes[y * 8 + x];
-                    let cell_height = altitudes.x1y1;
-                    let cell_x2y1_height = altitudes.x2y1;
-                    let cell_x1y2_height = altitudes.x1y2;
-                    let cell_x2y2_height = altitudes.x2y2;
-
-                    let data: Option<(Image, MapTileData)> = match art_cache
-                        .read_tile(ctx, cell.graphic as u32)
-                    {
-                        Some((ref tile, ref tiledata)) => Some((tile.clone(), tiledata.clone())),
-                        _ => None,
-                    };
-
-                    data.map(|(tile, tiledata)| {
-                        let new_transform = add(
-                            add(cell_at(x as i32, y as i32), transform),
-                            Point2::new(0.0, -(cell.altitude as f32 * 4.0)),
-                        );
-                        if cell_height == cell_x1y2_height
-                            && cell.altitude == cell_x2y1_height
-                            && cell.altitude == cell_x2y2_height
-                        {
-                            tiles.push((
-                                DrawableItem::Tile(tile.clone(), tiledata),
-                                new_transform,
-                                cell.altitude,
-                            ));
-                        } else {
-                            texmap_cache
-                                .read_texmap(ctx, tiledata.texture_id as u32)
-                                .as_ref()
-                                .map(|tile| {
-                                    let skewed = skew(ctx, tile, &altitudes);
-                                    tiles.push((
-                                        DrawableItem::Skewable(skewed, tiledata),
-                                        new_transform,
-                                        cell.altitude - 1,
-                                    ));
-                                });
-                        }
-                    });
+            let cell = record.block.cells[y * 8 + x];
+            let altitudes = record.altitudes[y * 8 + x];
+            let dest = add(
+                cell_at(x as i32, y as i32),
+                Point2::new(0.0, -(cell.altitude as f32 * 4.0)),
+            );
+            let (tile, tiledata) = match art_cache.read_tile(ctx, cell.graphic as u32) {
+                Some((ref tile, ref tiledata)) => (tile.clone(), tiledata.clone()),
+                None => {
+                    cells.push(None);
+                    continue;
                 }
-                None => (),
             };
-            for s in cell_statics {
-                art_cache.read_static(ctx, s.object_id as u32).as_ref().map(
-                    |(ref art, ref tiledata)| {
-                        let new_transform = add(
-                            add(cell_at(x as i32, y as i32), transform),
-                            Point2::new(
-                                0.0,
-                                -(s.altitude as f32 * 4.0) - art.height() as f32 + TILE_SIZE,
-                            ),
-                        );
-                        tiles.push((
-                            DrawableItem::Static(art.clone(), tiledata.clone()),
-                            new_transform,
-                            s.altitude,
-                        ));
-                    },
-                );
-            }
-            tiles.sort_by(|a, b| match a.2.cmp(&b.2) {
-                Ordering::Equal => match (&a.0, &b.0) {
-                    (&DrawableItem::Static(_, ref tiledata), DrawableItem::Static(_, _)) => {
-                        if tiledata.flags & Flags::BackgroundFlag as u32 != 0 {
-                            Ordering::Less
-                        } else {
-                            Ordering::Greater
-                        }
+            let (key, image, points, land_cell) = if is_flat(cell.altitude, &altitudes) {
+                (
+                    (false, cell.graphic as u32),
+                    tile.clone(),
+                    QUAD,
+                    LandDrawable::Tile(tile),
+                )
+            } else {
+                let texture = match texmap_cache.read_texmap(ctx, tiledata.texture_id as u32) {
+                    Some(ref texture) => texture.clone(),
+                    None => {
+                        cells.push(None);
+                        continue;
                     }
-                    (&DrawableItem::Static(_, _), _) => Ordering::Greater,
-                    _ => Ordering::Less,
+                };
+                (
+                    (true, tiledata.texture_id as u32),
+                    texture.clone(),
+                    skew_points(&altitudes),
+                    LandDrawable::Skewable(skew(ctx, &texture, &altitudes)),
+                )
+            };
+            let batch = batches
+                .entry(key)
+                .or_insert_with(|| (image, vec![], vec![]));
+            let first = batch.1.len() as u32;
+            let placed = [
+                [points[0][0] + dest.x, points[0][1] + dest.y],
+                [points[1][0] + dest.x, points[1][1] + dest.y],
+                [points[2][0] + dest.x, points[2][1] + dest.y],
+                [points[3][0] + dest.x, points[3][1] + dest.y],
+            ];
+            batch.1.extend_from_slice(&generate_vertices(&placed));
+            batch
+                .2
+                .extend([0, 1, 2, 0, 2, 3].iter().map(|index| first + index));
+            cells.push(Some(land_cell));
+        }
+    }
+    let mut meshes = vec![];
+    for (_, (image, vertices, indices)) in batches {
+        meshes.push(
+            MeshBuilder::new()
+                .raw(&vertices, &indices, Some(image))?
+                .build(ctx)?,
+        );
+    }
+    Ok(LandMeshes {
+        batches: meshes,
+        cells,
+    })
+}
+
+fn land_for(
+    ctx: &mut Context,
+    art_cache: &mut ArtCache,
+    texmap_cache: &mut TexMapCache,
+    land_cache: &mut LandMeshCache,
+    block: &ViewBlock,
+) -> GameResult<Rc<LandMeshes>> {
+    if let Some(land) = land_cache.get(block.position, &block.record) {
+        return Ok(land);
+    }
+    let land = Rc::new(build_land(ctx, art_cache, texmap_cache, &block.record)?);
+    land_cache.insert(block.position, block.record.clone(), land.clone());
+    Ok(land)
+}
+
+// Everything in a view, where it goes and in the order the client draws it. Both renderers
+// draw from this, so the screen and exported images agree. Static art comes from static_art
+// as its flags, its height and how tall the art is, which is all it takes to place one. The
+// sort is stable, so statics that tie keep the order they have in the file.
+pub fn collect_items<F>(
+    blocks: &[ViewBlock],
+    options: &RenderOptions,
+    mut static_art: F,
+) -> Vec<ViewItem>
+where
+    F: FnMut(u16) -> Option<(u32, u8, u32)>,
+{
+    let mut items = vec![];
+    for (block_index, block) in blocks.iter().enumerate() {
+        let (record, (left, top)) = (&block.record, block.origin);
+        for index in 0..64 {
+            let (x, y) = ((index % 8) as i32, (index / 8) as i32);
+            if !options.includes(left + x, top + y) {
+                continue;
+            }
+            let cell = record.block.cells[index];
+            let altitudes = record.altitudes[index];
+            let (_, average, high) = land_heights(&altitudes);
+            let flat = is_flat(cell.altitude, &altitudes);
+            items.push(ViewItem {
+                piece: ViewPiece::Land {
+                    block: block_index,
+                    cell: index,
+                    flat,
+                    high,
                 },
-                otherwise => otherwise,
+                dest: add(
+                    add(cell_at(x, y), block.transform),
+                    Point2::new(0.0, -(cell.altitude as f32 * 4.0)),
+                ),
+                key: DepthKey::land(
+                    left + x,
+                    top + y,
+                    if flat { cell.altitude as i32 } else { average },
+                ),
             });
-            for (gfx, point, _) in tiles {
-                match gfx {
-                    DrawableItem::Static(ref img, _) => {
-                        graphics::draw(ctx, img, DrawParam::default().dest(point))
-                    }
-                    DrawableItem::Tile(ref img, _) => {
-                        graphics::draw(ctx, img, DrawParam::default().dest(point))
-                    }
-                    DrawableItem::Skewable(ref img, _) => {
-                        graphics::draw(ctx, img, DrawParam::default().dest(point))
-                    }
-                }?;
+        }
+
+        if !options.statics {
+            continue;
+        }
+        for (index, s) in record.statics.iter().enumerate() {
+            if !options.includes(left + s.x as i32, top + s.y as i32) {
+                continue;
+            }
+            let (flags, height, art_height) = match static_art(s.object_id) {
+                Some(art) => art,
+                None => continue,
+            };
+            let ceiling = options.ceiling;
+            let covered = ceiling.map_or(false, |ceiling| ceiling.covers(s.altitude, flags));
+            if covered && !ceiling.map_or(false, |ceiling| ceiling.translucent) {
+                continue;
+            }
+            items.push(ViewItem {
+                piece: ViewPiece::Static {
+                    block: block_index,
+                    index,
+                    object_id: s.object_id,
+                    faded: covered,
+                },
+                dest: add(
+                    add(cell_at(s.x as i32, s.y as i32), block.transform),
+                    Point2::new(
+                        0.0,
+                        -(s.altitude as f32 * 4.0) - art_height as f32 + TILE_SIZE,
+                    ),
+                ),
+                key: DepthKey::item(
+                    left + s.x as i32,
+                    top + s.y as i32,
+                    s.altitude as i32,
+                    flags,
+                    height,
+                ),
+            });
+        }
+    }
+    items.sort_by_key(|item| item.key);
+    items
+}
+
+// A block in view, with where it's drawn and its first cell relative to the others in view
+pub struct ViewBlock {
+    pub record: Rc<BlockRecord>,
+    // Where the block is in the facet, which the land cache goes by
+    pub position: (u32, u32),
+    pub transform: Point2<f32>,
+    pub origin: (i32, i32),
+}
+
+// The cells whose land is drawn again in amongst everything else, once the batches are down.
+// Batches go in texture order rather than back to front, so this is any land that could
+// cover a static or land that comes before it in collect_items' order.
+pub fn sorted_land(blocks: &[ViewBlock], items: &[ViewItem]) -> HashSet<(i32, i32)> {
+    // Land can only cover a static from the static's own cell or the cells just in front of
+    // it, so each of those cells keeps the lowest base of the statics it's in front of
+    let mut lowest_behind: HashMap<(i32, i32), i32> = HashMap::new();
+    for block in blocks {
+        let (left, top) = block.origin;
+        for location in &block.record.statics {
+            let (x, y) = (left + location.x as i32, top + location.y as i32);
+            for &(dx, dy) in &[(0, 0), (1, 0), (0, 1), (1, 1)] {
+                let base = lowest_behind
+                    .entry((x + dx, y + dy))
+                    .or_insert(i32::max_value());
+                *base = min(*base, location.altitude as i32);
+            }
+        }
+    }
+
+    // Land only reaches over earlier land where it's steep enough to fold back on screen, or
+    // stands on top of such a fold. Corners line up in columns of half a tile, so it's enough
+    // to keep how far down the screen the land so far reaches in each column, and look for
+    // cells whose top edge is above that.
+    let mut reached: HashMap<i32, i32> = HashMap::new();
+    let mut sorted = HashSet::new();
+    for item in items {
+        let (block, cell, high) = match item.piece {
+            ViewPiece::Land {
+                block, cell, high, ..
+            } => (block, cell, high),
+            ViewPiece::Static { .. } => continue,
+        };
+        let (left, top) = blocks[block].origin;
+        let (x, y) = (left + (cell % 8) as i32, top + (cell / 8) as i32);
+        if lowest_behind
+            .get(&(x, y))
+            .map_or(false, |&base| high > base)
+        {
+            sorted.insert((x, y));
+        }
+
+        let altitudes = blocks[block].record.altitudes[cell];
+        let screen_y = |depth: i32, z: i8| depth * 22 - z as i32 * 4;
+        let (top_y, bottom_y) = (
+            screen_y(x + y, altitudes.x1y1),
+            screen_y(x + y + 2, altitudes.x2y2),
+        );
+        let (left_y, right_y) = (
+            screen_y(x + y + 1, altitudes.x1y2),
+            screen_y(x + y + 1, altitudes.x2y1),
+        );
+        let column = x - y;
+        let upper = [
+            (column - 1, left_y),
+            (column, min(top_y, bottom_y)),
+            (column + 1, right_y),
+        ];
+        if upper
+            .iter()
+            .any(|&(column, y)| reached.get(&column).map_or(false, |&lowest| y < lowest))
+        {
+            sorted.insert((x, y));
+        }
+        let lower = [
+            (column - 1, left_y),
+            (column, max(top_y, bottom_y)),
+            (column + 1, right_y),
+        ];
+        for &(column, y) in lower.iter() {
+            let lowest = reached.entry(column).or_insert(y);
+            *lowest = max(*lowest, y);
+        }
+    }
+    sorted
+}
+
+// Draws every block in a view together. The land goes down first, a batch at a time, and
+// then everything is drawn in collect_items' order, skipping land that nothing earlier can
+// get in front of.
+pub fn draw_blocks(
+    ctx: &mut Context,
+    art_cache: &mut ArtCache,
+    texmap_cache: &mut TexMapCache,
+    land_cache: &mut LandMeshCache,
+    blocks: &[ViewBlock],
+    ceiling: Option<Ceiling>,
+) -> GameResult<()> {
+    let mut lands = vec![];
+    for block in blocks {
+        let land = land_for(ctx, art_cache, texmap_cache, land_cache, block)?;
+        for mesh in &land.batches {
+            graphics::draw(ctx, mesh, DrawParam::default().dest(block.transform))?;
+        }
+        lands.push(land);
+    }
+
+    let options = RenderOptions {
+        ceiling,
+        ..RenderOptions::default()
+    };
+    let items = collect_items(blocks, &options, |object_id| {
+        art_cache
+            .read_static(ctx, object_id as u32)
+            .as_ref()
+            .map(|(art, tiledata)| (tiledata.flags, tiledata.height, art.height() as u32))
+    });
+    let sorted = sorted_land(blocks, &items);
+    for item in items {
+        let param = DrawParam::default().dest(item.dest);
+        match item.piece {
+            ViewPiece::Land { block, cell, .. } => {
+                let (left, top) = blocks[block].origin;
+                if !sorted.contains(&(left + (cell % 8) as i32, top + (cell / 8) as i32)) {
+                    continue;
+                }
+                match lands[block].cells[cell] {
+                    Some(LandDrawable::Tile(ref image)) => graphics::draw(ctx, image, param)?,
+                    Some(LandDrawable::Skewable(ref mesh)) => graphics::draw(ctx, mesh, param)?,
+                    None => (),
+                }
+            }
+            ViewPiece::Static {
+                object_id, faded, ..
+            } => {
+                if let Some((ref art, _)) = *art_cache.read_static(ctx, object_id as u32) {
+                    let alpha = if faded { 0.25 } else { 1.0 };
+                    graphics::draw(
+                        ctx,
+                        art,
+                        param.color(graphics::Color::new(1.0, 1.0, 1.0, alpha)),
+                    )?;
+                }
             }
         }
     }
     Ok(())
 }
+
+// A block and the ring of blocks around it, laid out to be drawn under NEIGHBOURHOOD. Read
+// through the facet, so the neighbours are there for altitudes and whatever patches the facet
+// has are applied to them as well.
+pub fn neighbourhood_blocks(facet: &mut Facet, block_x: u32, block_y: u32) -> Vec<ViewBlock> {
+    let mut blocks = vec![];
+    for dy in -1..=1i32 {
+        for dx in -1..=1i32 {
+            let x = block_x as i32 + dx;
+            let y = block_y as i32 + dy;
+            if x < 0 || y < 0 {
+                continue;
+            }
+            match facet.read_block(x as u32, y as u32) {
+                Some(record) => {
+                    let transform =
+                        Point2::new(BLOCK_STEP * (dx - dy) as f32, BLOCK_STEP * (dx + dy) as f32);
+                    blocks.push(ViewBlock {
+                        record,
+                        position: (x as u32, y as u32),
+                        transform,
+                        origin: (dx * 8, dy * 8),
+                    });
+                }
+                None => (),
+            }
+        }
+    }
+    blocks
+}
+
+pub fn draw_neighbourhood(
+    ctx: &mut Context,
+    art_cache: &mut ArtCache,
+    texmap_cache: &mut TexMapCache,
+    land_cache: &mut LandMeshCache,
+    facet: &mut Facet,
+    block_x: u32,
+    block_y: u32,
+) -> GameResult<()> {
+    let blocks = neighbourhood_blocks(facet, block_x, block_y);
+    draw_blocks(ctx, art_cache, texmap_cache, land_cache, &blocks, None)?;
+    let outline = [
+        Point2::new(BLOCK_STEP, 0.0),
+        Point2::new(BLOCK_STEP * 2.0, BLOCK_STEP),
+        Point2::new(BLOCK_STEP, BLOCK_STEP * 2.0),
+        Point2::new(0.0, BLOCK_STEP),
+    ];
+    let mesh = Mesh::new_polygon(
+        ctx,
+        DrawMode::stroke(4.0),
+        &outline,
+        graphics::Color::new(1.0, 0.0, 1.0, 1.0),
+    )?;
+    graphics::draw(ctx, &mesh, DrawParam::default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{collect_items, skew_points, sorted_land, RenderOptions, ViewBlock, ViewPiece};
+    use caches::facet_cache::BlockRecord;
+    use cgmath::Point2;
+    use std::collections::HashMap;
+    use std::rc::Rc;
+    use uorustlibs::map::{Block, Cell};
+
+    // A flat plain that steps up onto a plateau, with a spike standing on the plain. Both
+    // fold back over the land behind them on screen.
+    fn altitude(x: i32, y: i32) -> i8 {
+        if (x, y) == (9, 2) {
+            30
+        } else if y >= 4 {
+            40
+        } else {
+            0
+        }
+    }
+
+    fn block(left: i32) -> Block {
+        let mut block = Block {
+            header: 0,
+            cells: [Cell {
+                graphic: 3,
+                altitude: 0,
+            }; 64],
+        };
+        for (index, cell) in block.cells.iter_mut().enumerate() {
+            cell.altitude = altitude(left + (index % 8) as i32, (index / 8) as i32);
+        }
+        block
+    }
+
+    fn edge(a: [f32; 2], b: [f32; 2], point: [f32; 2]) -> f32 {
+        (b[0] - a[0]) * (point[1] - a[1]) - (b[1] - a[1]) * (point[0] - a[0])
+    }
+
+    fn in_triangle(corners: [[f32; 2]; 3], point: [f32; 2]) -> bool {
+        let sides = [
+            edge(corners[0], corners[1], point),
+            edge(corners[1], corners[2], point),
+            edge(corners[2], corners[0], point),
+        ];
+        sides.iter().all(|&side| side > 0.0) || sides.iter().all(|&side| side < 0.0)
+    }
+
+    // Paints each cell's quad in turn, the way the meshes are split into triangles, and
+    // returns which cell ended up on top at each pixel
+    fn paint(
+        quads: &HashMap<(i32, i32), [[f32; 2]; 4]>,
+        order: &[(i32, i32)],
+    ) -> HashMap<(i32, i32), (i32, i32)> {
+        let mut owners = HashMap::new();
+        for cell in order {
+            let quad = quads[cell];
+            let (mut left, mut top, mut right, mut bottom) = (
+                i32::max_value(),
+                i32::max_value(),
+                i32::min_value(),
+                i32::min_value(),
+            );
+            for corner in quad.iter() {
+                left = left.min(corner[0].floor() as i32);
+                top = top.min(corner[1].floor() as i32);
+                right = right.max(corner[0].ceil() as i32);
+                bottom = bottom.max(corner[1].ceil() as i32);
+            }
+            for py in top..bottom {
+                for px in left..right {
+                    let point = [px as f32 + 0.5, py as f32 + 0.5];
+                    if in_triangle([quad[0], quad[1], quad[2]], point)
+                        || in_triangle([quad[0], quad[2], quad[3]], point)
+                    {
+                        owners.insert((px, py), *cell);
+                    }
+                }
+            }
+        }
+        owners
+    }
+
+    #[test]
+    fn batched_land_ends_up_layered_like_collect_items() {
+        let (west, east) = (block(0), block(8));
+        let blocks = [
+            ViewBlock {
+                record: Rc::new(BlockRecord::new(west, vec![], Some(&east), None, None)),
+                position: (0, 0),
+                transform: Point2::new(0.0, 0.0),
+                origin: (0, 0),
+            },
+            ViewBlock {
+                record: Rc::new(BlockRecord::new(east, vec![], None, None, None)),
+                position: (1, 0),
+                transform: Point2::new(176.0, 176.0),
+                origin: (8, 0),
+            },
+        ];
+        let items = collect_items(&blocks, &RenderOptions::default(), |_| None);
+        let mut quads = HashMap::new();
+        let mut in_order = vec![];
+        for item in items.iter() {
+            if let ViewPiece::Land { block, cell, .. } = item.piece {
+                let (left, top) = blocks[block].origin;
+                let position = (left + (cell % 8) as i32, top + (cell / 8) as i32);
+                let points = skew_points(&blocks[block].record.altitudes[cell]);
+                let mut quad = [[0.0; 2]; 4];
+                for (corner, point) in quad.iter_mut().zip(points.iter()) {
+                    *corner = [point[0] + item.dest.x, point[1] + item.dest.y];
+                }
+                quads.insert(position, quad);
+                in_order.push(position);
+            }
+        }
+        let expected = paint(&quads, &in_order);
+
+        // Batches can come in any order, so take the worst, back to front
+        let sorted = sorted_land(&blocks, &items);
+        let mut drawn = in_order.iter().rev().cloned().collect::<Vec<_>>();
+        assert_ne!(paint(&quads, &drawn), expected);
+        drawn.extend(in_order.iter().filter(|cell| sorted.contains(cell)));
+        assert_eq!(paint(&quads, &drawn), expected);
+
+        // The cliff and the spike are drawn again, the plain well away from them isn't
+        assert!(sorted.contains(&(2, 3)));
+        assert!(sorted.contains(&(9, 1)));
+        assert!(!sorted.contains(&(2, 1)));
+        assert!(!sorted.contains(&(14, 7)));
+    }
+}
@@ -0,0 +1,135 @@
+use caches::facet_cache::Altitudes;
+
+// Most of the sea in UO sits at z -5, so anything at or below it is treated as water
+pub const SEA_LEVEL: i8 = -5;
+
+// Light comes from the north west, as it does on paper maps
+const LIGHT: [f32; 3] = [-0.5, -0.5, 0.70710677];
+const AMBIENT: f32 = 0.35;
+// A z unit is far shorter than a tile is wide, so exaggerate slopes or nothing would show
+const Z_SCALE: f32 = 0.5;
+
+const WATER_RAMP: [(i8, (u8, u8, u8)); 3] = [
+    (-128, (8, 16, 64)),
+    (-40, (24, 64, 140)),
+    (SEA_LEVEL, (96, 160, 210)),
+];
+
+const LAND_RAMP: [(i8, (u8, u8, u8)); 6] = [
+    (SEA_LEVEL, (64, 128, 64)),
+    (10, (112, 160, 80)),
+    (30, (200, 190, 110)),
+    (60, (150, 110, 70)),
+    (90, (130, 130, 130)),
+    (127, (250, 250, 250)),
+];
+
+fn lerp(from: u8, to: u8, amount: f32) -> u8 {
+    (from as f32 + (to as f32 - from as f32) * amount).round() as u8
+}
+
+fn sample_ramp(ramp: &[(i8, (u8, u8, u8))], altitude: i8) -> (u8, u8, u8) {
+    for pair in ramp.windows(2) {
+        let (low, low_color) = pair[0];
+        let (high, high_color) = pair[1];
+        if altitude <= high {
+            let span = (high as f32 - low as f32).max(1.0);
+            let amount = ((altitude as f32 - low as f32) / span).max(0.0).min(1.0);
+            return (
+                lerp(low_color.0, high_color.0, amount),
+                lerp(low_color.1, high_color.1, amount),
+                lerp(low_color.2, high_color.2, amount),
+            );
+        }
+    }
+    ramp[ramp.len() - 1].1
+}
+
+pub fn hypsometric_color(altitude: i8) -> (u8, u8, u8) {
+    if altitude <= SEA_LEVEL {
+        sample_ramp(&WATER_RAMP, altitude)
+    } else {
+        sample_ramp(&LAND_RAMP, altitude)
+    }
+}
+
+// Lambertian shading of the cell's surface, from the same four corners the renderer skews
+// land tiles with. Returns a brightness between AMBIENT and 1.
+pub fn hillshade(altitudes: &Altitudes) -> f32 {
+    let dz_dx = ((altitudes.x2y1 as f32 - altitudes.x1y1 as f32)
+        + (altitudes.x2y2 as f32 - altitudes.x1y2 as f32))
+        / 2.0
+        * Z_SCALE;
+    let dz_dy = ((altitudes.x1y2 as f32 - altitudes.x1y1 as f32)
+        + (altitudes.x2y2 as f32 - altitudes.x2y1 as f32))
+        / 2.0
+        * Z_SCALE;
+    let length = (dz_dx * dz_dx + dz_dy * dz_dy + 1.0).sqrt();
+    let normal = [-dz_dx / length, -dz_dy / length, 1.0 / length];
+    let lambert = normal[0] * LIGHT[0] + normal[1] * LIGHT[1] + normal[2] * LIGHT[2];
+    AMBIENT + (1.0 - AMBIENT) * lambert.max(0.0)
+}
+
+fn band(altitude: i8, interval: u8) -> i32 {
+    (altitude as i32 - SEA_LEVEL as i32).div_euclid(interval as i32)
+}
+
+// A cell sits on a contour when a band boundary falls between it and its east or south edge
+pub fn is_contour(altitudes: &Altitudes, interval: u8) -> bool {
+    if interval == 0 {
+        return false;
+    }
+    let here = band(altitudes.x1y1, interval);
+    here != band(altitudes.x2y1, interval) || here != band(altitudes.x1y2, interval)
+}
+
+pub fn shade((r, g, b): (u8, u8, u8), brightness: f32) -> (u8, u8, u8) {
+    let scale = |channel: u8| (channel as f32 * brightness).round().max(0.0).min(255.0) as u8;
+    (scale(r), scale(g), scale(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{hillshade, hypsometric_color, is_contour, AMBIENT, LIGHT, SEA_LEVEL};
+    use caches::facet_cache::Altitudes;
+
+    fn corners(x1y1: i8, x2y1: i8, x1y2: i8, x2y2: i8) -> Altitudes {
+        Altitudes {
+            x1y1,
+            x2y1,
+            x1y2,
+            x2y2,
+        }
+    }
+
+    #[test]
+    fn sea_level_is_the_last_of_the_water() {
+        assert_eq!(hypsometric_color(SEA_LEVEL), (96, 160, 210));
+        // A step above, the land ramp starts from its lowland green
+        assert_eq!(hypsometric_color(SEA_LEVEL + 1), (67, 130, 65));
+    }
+
+    #[test]
+    fn flat_cells_are_lit_by_the_light_alone() {
+        let expected = AMBIENT + (1.0 - AMBIENT) * LIGHT[2];
+        assert!((hillshade(&corners(20, 20, 20, 20)) - expected).abs() < 1e-6);
+        // Slopes rising away from the light face it and are brighter, the rest darker
+        assert!(hillshade(&corners(0, 10, 10, 20)) > expected);
+        assert!(hillshade(&corners(20, 10, 10, 0)) < expected);
+    }
+
+    #[test]
+    fn contours_fall_where_a_band_boundary_is_crossed() {
+        // Bands are counted up from sea level, so with an interval of 5 one starts at z 0
+        assert!(!is_contour(&corners(0, 4, 4, 4), 5));
+        assert!(is_contour(&corners(4, 5, 4, 4), 5));
+        assert!(is_contour(&corners(4, 4, 5, 4), 5));
+        // Only the east and south edges count
+        assert!(!is_contour(&corners(4, 4, 4, 9), 5));
+        assert!(is_contour(
+            &corners(SEA_LEVEL - 1, SEA_LEVEL, SEA_LEVEL, SEA_LEVEL),
+            5
+        ));
+        assert!(!is_contour(&corners(0, 50, 50, 50), 0));
+    }
+}
@@ -0,0 +1,309 @@
+use caches::image_art_cache::ImageArtCache;
+use cgmath::Point2;
+use image::{Rgba, RgbaImage};
+use map::render::{
+    collect_items, neighbourhood_blocks, skew_points, RenderOptions, ViewBlock, ViewPiece,
+    NEIGHBOURHOOD,
+};
+use map::Facet;
+use std::fs;
+use std::io::{self, Result};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+// Matches the texture coordinates generate_vertices gives each corner of a stretched texmap
+const UV: [[f32; 2]; 4] = [[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]];
+
+// Where the software renderer gets its art, which is the art files outside of tests
+pub trait ImageArt {
+    // A land tile's art and the texmap it's stretched with
+    fn land(&mut self, graphic: u16) -> Option<(Rc<RgbaImage>, u32)>;
+    // A static's art with its flags and height
+    fn item(&mut self, object_id: u16) -> Option<(Rc<RgbaImage>, u32, u8)>;
+    fn texmap(&mut self, texture_id: u32) -> Option<Rc<RgbaImage>>;
+}
+
+impl ImageArt for ImageArtCache {
+    fn land(&mut self, graphic: u16) -> Option<(Rc<RgbaImage>, u32)> {
+        self.read_tile(graphic as u32)
+            .map(|(tile, tiledata)| (tile, tiledata.texture_id as u32))
+    }
+
+    fn item(&mut self, object_id: u16) -> Option<(Rc<RgbaImage>, u32, u8)> {
+        self.read_static(object_id as u32)
+            .map(|(image, tiledata)| (image, tiledata.flags, tiledata.height))
+    }
+
+    fn texmap(&mut self, texture_id: u32) -> Option<Rc<RgbaImage>> {
+        self.read_texmap(texture_id)
+    }
+}
+
+fn add(a: Point2<f32>, b: Point2<f32>) -> Point2<f32> {
+    Point2::new(a.x + b.x, a.y + b.y)
+}
+
+// Lays a pixel over what's already there
+fn blend(target: &mut RgbaImage, x: i64, y: i64, color: Rgba<u8>, opacity: f32) {
+    if x < 0 || y < 0 || x >= target.width() as i64 || y >= target.height() as i64 {
+        return;
+    }
+    let alpha = color[3] as f32 / 255.0 * opacity;
+    if alpha <= 0.0 {
+        return;
+    }
+    let below = target.get_pixel_mut(x as u32, y as u32);
+    let below_alpha = below[3] as f32 / 255.0;
+    let out_alpha = alpha + below_alpha * (1.0 - alpha);
+    for channel in 0..3 {
+        let mixed = (color[channel] as f32 * alpha
+            + below[channel] as f32 * below_alpha * (1.0 - alpha))
+            / out_alpha;
+        below[channel] = mixed.round() as u8;
+    }
+    below[3] = (out_alpha * 255.0).round() as u8;
+}
+
+fn draw_image(target: &mut RgbaImage, image: &RgbaImage, dest: Point2<f32>, opacity: f32) {
+    let (left, top) = (dest.x.round() as i64, dest.y.round() as i64);
+    for (x, y, pixel) in image.enumerate_pixels() {
+        blend(target, left + x as i64, top + y as i64, *pixel, opacity);
+    }
+}
+
+// Twice the signed area of the triangle a, b, c
+fn edge(a: [f32; 2], b: [f32; 2], c: [f32; 2]) -> f32 {
+    (b[0] - a[0]) * (c[1] - a[1]) - (b[1] - a[1]) * (c[0] - a[0])
+}
+
+// Fills the pixels whose centres fall inside the triangle, sampling the texture with the
+// corners' coordinates spread across it
+fn draw_triangle(
+    target: &mut RgbaImage,
+    texture: &RgbaImage,
+    points: [[f32; 2]; 3],
+    uvs: [[f32; 2]; 3],
+) {
+    let area = edge(points[0], points[1], points[2]);
+    if area == 0.0 {
+        return;
+    }
+    let min = |axis: usize| points.iter().map(|p| p[axis]).fold(f32::MAX, f32::min);
+    let max = |axis: usize| points.iter().map(|p| p[axis]).fold(f32::MIN, f32::max);
+    let (left, right) = (min(0).floor().max(0.0), max(0).ceil());
+    let (top, bottom) = (min(1).floor().max(0.0), max(1).ceil());
+    let right = right.min(target.width() as f32);
+    let bottom = bottom.min(target.height() as f32);
+    let (width, height) = (texture.width(), texture.height());
+    let mut y = top;
+    while y < bottom {
+        let mut x = left;
+        while x < right {
+            let centre = [x + 0.5, y + 0.5];
+            let w0 = edge(points[1], points[2], centre) / area;
+            let w1 = edge(points[2], points[0], centre) / area;
+            let w2 = edge(points[0], points[1], centre) / area;
+            if w0 >= 0.0 && w1 >= 0.0 && w2 >= 0.0 {
+                let u = w0 * uvs[0][0] + w1 * uvs[1][0] + w2 * uvs[2][0];
+                let v = w0 * uvs[0][1] + w1 * uvs[1][1] + w2 * uvs[2][1];
+                let texel_x = ((u * width as f32) as u32).min(width - 1);
+                let texel_y = ((v * height as f32) as u32).min(height - 1);
+                let color = *texture.get_pixel(texel_x, texel_y);
+                blend(target, x as i64, y as i64, color, 1.0);
+            }
+            x += 1.0;
+        }
+        y += 1.0;
+    }
+}
+
+// Split into the same two triangles skew's mesh uses
+fn draw_stretched(
+    target: &mut RgbaImage,
+    texture: &RgbaImage,
+    corners: &[[f32; 2]; 4],
+    dest: Point2<f32>,
+) {
+    let placed = |index: usize| [corners[index][0] + dest.x, corners[index][1] + dest.y];
+    for triangle in [[0, 1, 2], [0, 2, 3]].iter() {
+        draw_triangle(
+            target,
+            texture,
+            [
+                placed(triangle[0]),
+                placed(triangle[1]),
+                placed(triangle[2]),
+            ],
+            [UV[triangle[0]], UV[triangle[1]], UV[triangle[2]]],
+        );
+    }
+}
+
+// Draws blocks into an image the way draw_blocks draws them to the screen, with each block's
+// transform relative to the image's top left
+pub fn render_blocks<A: ImageArt>(
+    target: &mut RgbaImage,
+    art: &mut A,
+    blocks: &[ViewBlock],
+    options: &RenderOptions,
+) {
+    let items = collect_items(blocks, options, |object_id| {
+        art.item(object_id)
+            .map(|(image, flags, height)| (flags, height, image.height()))
+    });
+    for item in items {
+        match item.piece {
+            ViewPiece::Land {
+                block, cell, flat, ..
+            } => {
+                let record = &blocks[block].record;
+                let (tile, texture_id) = match art.land(record.block.cells[cell].graphic) {
+                    Some(tile) => tile,
+                    None => continue,
+                };
+                if flat {
+                    draw_image(target, &tile, item.dest, 1.0);
+                } else if let Some(texmap) = art.texmap(texture_id) {
+                    let corners = skew_points(&record.altitudes[cell]);
+                    draw_stretched(target, &texmap, &corners, item.dest);
+                }
+            }
+            ViewPiece::Static {
+                object_id, faded, ..
+            } => {
+                if let Some((image, _, _)) = art.item(object_id) {
+                    draw_image(target, &image, item.dest, if faded { 0.25 } else { 1.0 });
+                }
+            }
+        }
+    }
+}
+
+// The same view as draw_neighbourhood, without the outline
+pub fn render_neighbourhood(
+    art: &mut ImageArtCache,
+    facet: &mut Facet,
+    block_x: u32,
+    block_y: u32,
+) -> RgbaImage {
+    let offset = Point2::new(-NEIGHBOURHOOD.x, -NEIGHBOURHOOD.y);
+    let blocks = neighbourhood_blocks(facet, block_x, block_y)
+        .into_iter()
+        .map(|block| ViewBlock {
+            transform: add(block.transform, offset),
+            ..block
+        })
+        .collect::<Vec<_>>();
+    let mut target = RgbaImage::from_pixel(
+        NEIGHBOURHOOD.w as u32,
+        NEIGHBOURHOOD.h as u32,
+        Rgba([0, 0, 0, 255]),
+    );
+    render_blocks(&mut target, art, &blocks, &RenderOptions::default());
+    target
+}
+
+pub fn export_neighbourhood(
+    facet: &mut Facet,
+    map_id: u8,
+    block_x: u32,
+    block_y: u32,
+    directory: &Path,
+) -> Result<PathBuf> {
+    fs::create_dir_all(directory)?;
+    let mut art = ImageArtCache::new();
+    let image = render_neighbourhood(&mut art, facet, block_x, block_y);
+    let path = directory.join(format!("render{}_{}_{}.png", map_id, block_x, block_y));
+    image
+        .save(&path)
+        .map_err(|error| io::Error::new(io::ErrorKind::Other, error.to_string()))?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{render_blocks, ImageArt};
+    use caches::facet_cache::BlockRecord;
+    use cgmath::Point2;
+    use image::{Rgba, RgbaImage};
+    use map::render::{RenderOptions, ViewBlock};
+    use std::rc::Rc;
+    use uorustlibs::map::{Block, Cell, StaticLocation};
+
+    const LAND: Rgba<u8> = Rgba([0, 255, 0, 255]);
+    const BACK: Rgba<u8> = Rgba([255, 0, 0, 255]);
+    const FRONT: Rgba<u8> = Rgba([0, 0, 255, 255]);
+    const BLACK: Rgba<u8> = Rgba([0, 0, 0, 255]);
+
+    // Land is a diamond like the real tiles, and statics are solid boxes
+    struct TestArt;
+
+    impl ImageArt for TestArt {
+        fn land(&mut self, _graphic: u16) -> Option<(Rc<RgbaImage>, u32)> {
+            let tile = RgbaImage::from_fn(44, 44, |x, y| {
+                if (x as i32 * 2 - 43).abs() + (y as i32 * 2 - 43).abs() <= 44 {
+                    LAND
+                } else {
+                    Rgba([0, 0, 0, 0])
+                }
+            });
+            Some((Rc::new(tile), 0))
+        }
+
+        fn item(&mut self, object_id: u16) -> Option<(Rc<RgbaImage>, u32, u8)> {
+            match object_id {
+                1 => Some((Rc::new(RgbaImage::from_pixel(44, 90, BACK)), 0, 20)),
+                2 => Some((Rc::new(RgbaImage::from_pixel(44, 66, FRONT)), 0, 10)),
+                _ => None,
+            }
+        }
+
+        fn texmap(&mut self, _texture_id: u32) -> Option<Rc<RgbaImage>> {
+            None
+        }
+    }
+
+    fn item(object_id: u16, x: u8, y: u8) -> StaticLocation {
+        StaticLocation {
+            object_id,
+            x,
+            y,
+            altitude: 0,
+            unknown: 0,
+        }
+    }
+
+    #[test]
+    fn statics_are_layered_over_land_and_each_other() {
+        let block = Block {
+            header: 0,
+            cells: [Cell {
+                graphic: 3,
+                altitude: 0,
+            }; 64],
+        };
+        // Listed front to back, so the sort has to put them the right way round
+        let statics = vec![item(2, 3, 3), item(1, 2, 2)];
+        let blocks = [ViewBlock {
+            record: Rc::new(BlockRecord::new(block, statics, None, None, None)),
+            position: (0, 0),
+            transform: Point2::new(0.0, 100.0),
+            origin: (0, 0),
+        }];
+        let mut target = RgbaImage::from_pixel(400, 400, BLACK);
+        render_blocks(
+            &mut target,
+            &mut TestArt,
+            &blocks,
+            &RenderOptions::default(),
+        );
+
+        // The back static covers the land of the cells behind it
+        assert_eq!(*target.get_pixel(176, 160), BACK);
+        // Where they overlap, the front static is over the back one
+        assert_eq!(*target.get_pixel(176, 220), FRONT);
+        // Land away from the statics, and nothing outside the block
+        assert_eq!(*target.get_pixel(264, 254), LAND);
+        assert_eq!(*target.get_pixel(5, 5), BLACK);
+    }
+}
@@ -0,0 +1,361 @@
+use caches::tiledata_cache::TileDataCache;
+use map::Facet;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{BufWriter, Result, Write};
+use std::path::{Path, PathBuf};
+use uorustlibs::map::{Block, StaticLocation};
+use uorustlibs::tiledata::Flags;
+
+// Cells are counted once, however many statics stand on them
+pub struct FacetStats {
+    pub map_id: u8,
+    pub width_blocks: u32,
+    pub height_blocks: u32,
+    // How far through the facet the scan has got, a column of blocks at a time
+    pub scanned_columns: u32,
+    pub land_counts: BTreeMap<u16, u64>,
+    pub static_counts: BTreeMap<u16, u64>,
+    // Indexed by altitude + 128
+    pub altitudes: Vec<u64>,
+    // Number of blocks holding each number of statics
+    pub density: BTreeMap<usize, u64>,
+    pub cells: u64,
+    pub water_cells: u64,
+    pub impassable_cells: u64,
+    pub statics: u64,
+    // World cell coordinates and altitude
+    pub highest: Option<(u32, u32, i8)>,
+    pub lowest: Option<(u32, u32, i8)>,
+}
+
+fn percent(count: u64, total: u64) -> f32 {
+    if total == 0 {
+        0.0
+    } else {
+        count as f32 * 100.0 / total as f32
+    }
+}
+
+// The most common entries first
+pub fn top_counts(counts: &BTreeMap<u16, u64>, limit: usize) -> Vec<(u16, u64)> {
+    let mut sorted = counts
+        .iter()
+        .map(|(&id, &count)| (id, count))
+        .collect::<Vec<_>>();
+    sorted.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    sorted.truncate(limit);
+    sorted
+}
+
+// The flags of the land and every static on each cell, together
+fn cell_flags(
+    block: &Block,
+    statics: &[StaticLocation],
+    tiledata: &mut TileDataCache,
+) -> [u32; 64] {
+    let mut flags = [0u32; 64];
+    for (index, cell) in block.cells.iter().enumerate() {
+        flags[index] = tiledata.land_flags(cell.graphic as u32);
+    }
+    for location in statics {
+        let (static_flags, _) = tiledata.static_flags_and_height(location.object_id as u32);
+        flags[location.x as usize % 8 + (location.y as usize % 8) * 8] |= static_flags;
+    }
+    flags
+}
+
+impl FacetStats {
+    pub fn new(map_id: u8, width_blocks: u32, height_blocks: u32) -> FacetStats {
+        FacetStats {
+            map_id,
+            width_blocks,
+            height_blocks,
+            scanned_columns: 0,
+            land_counts: BTreeMap::new(),
+            static_counts: BTreeMap::new(),
+            altitudes: vec![0; 256],
+            density: BTreeMap::new(),
+            cells: 0,
+            water_cells: 0,
+            impassable_cells: 0,
+            statics: 0,
+            highest: None,
+            lowest: None,
+        }
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.scanned_columns >= self.width_blocks
+    }
+
+    pub fn progress(&self) -> f32 {
+        percent(self.scanned_columns as u64, self.width_blocks as u64)
+    }
+
+    pub fn water_percent(&self) -> f32 {
+        percent(self.water_cells, self.cells)
+    }
+
+    pub fn impassable_percent(&self) -> f32 {
+        percent(self.impassable_cells, self.cells)
+    }
+
+    pub fn statics_per_block(&self) -> f32 {
+        let blocks = self.scanned_columns as u64 * self.height_blocks as u64;
+        if blocks == 0 {
+            0.0
+        } else {
+            self.statics as f32 / blocks as f32
+        }
+    }
+
+    // Takes each cell's flags from cell_flags rather than looking them up, so it can be tested
+    // without tiledata
+    fn add_block(
+        &mut self,
+        x: u32,
+        y: u32,
+        block: &Block,
+        statics: &[StaticLocation],
+        cell_flags: &[u32; 64],
+    ) {
+        for location in statics {
+            *self.static_counts.entry(location.object_id).or_insert(0) += 1;
+        }
+        self.statics += statics.len() as u64;
+        *self.density.entry(statics.len()).or_insert(0) += 1;
+
+        for (index, cell) in block.cells.iter().enumerate() {
+            *self.land_counts.entry(cell.graphic).or_insert(0) += 1;
+            self.altitudes[(cell.altitude as i16 + 128) as usize] += 1;
+            let flags = cell_flags[index];
+            if flags & Flags::WetFlag as u32 != 0 {
+                self.water_cells += 1;
+            } else if flags & Flags::ImpassableFlag as u32 != 0 {
+                self.impassable_cells += 1;
+            }
+            self.cells += 1;
+
+            let position = (x * 8 + (index % 8) as u32, y * 8 + (index / 8) as u32);
+            if self.highest.map_or(true, |(_, _, z)| cell.altitude > z) {
+                self.highest = Some((position.0, position.1, cell.altitude));
+            }
+            if self.lowest.map_or(true, |(_, _, z)| cell.altitude < z) {
+                self.lowest = Some((position.0, position.1, cell.altitude));
+            }
+        }
+    }
+
+    // Reads straight past the cache, so the scan doesn't push out what's on screen elsewhere
+    pub fn scan_column(&mut self, facet: &mut Facet, tiledata: &mut TileDataCache) {
+        if self.is_complete() {
+            return;
+        }
+        let x = self.scanned_columns;
+        for y in 0..self.height_blocks {
+            let statics = facet.read_statics(x, y);
+            if let Some(block) = facet.read_land_uncached(x, y) {
+                let flags = cell_flags(&block, &statics, tiledata);
+                self.add_block(x, y, &block, &statics, &flags);
+            }
+        }
+        self.scanned_columns += 1;
+    }
+
+    pub fn write_csv(&self, path: &Path) -> Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "section,key,value")?;
+        writeln!(writer, "summary,cells,{}", self.cells)?;
+        writeln!(writer, "summary,water_percent,{:.2}", self.water_percent())?;
+        writeln!(
+            writer,
+            "summary,impassable_percent,{:.2}",
+            self.impassable_percent()
+        )?;
+        writeln!(writer, "summary,statics,{}", self.statics)?;
+        writeln!(
+            writer,
+            "summary,statics_per_block,{:.2}",
+            self.statics_per_block()
+        )?;
+        if let Some((x, y, z)) = self.highest {
+            writeln!(writer, "summary,highest,{} {} {}", x, y, z)?;
+        }
+        if let Some((x, y, z)) = self.lowest {
+            writeln!(writer, "summary,lowest,{} {} {}", x, y, z)?;
+        }
+        for (altitude, &count) in self.altitudes.iter().enumerate() {
+            if count > 0 {
+                writeln!(writer, "altitude,{},{}", altitude as i32 - 128, count)?;
+            }
+        }
+        for (statics, count) in &self.density {
+            writeln!(writer, "density,{},{}", statics, count)?;
+        }
+        for (graphic, count) in &self.land_counts {
+            writeln!(writer, "land,0x{:04X},{}", graphic, count)?;
+        }
+        for (object_id, count) in &self.static_counts {
+            writeln!(writer, "static,0x{:04X},{}", object_id, count)?;
+        }
+        writer.flush()
+    }
+
+    pub fn write_json(&self, path: &Path) -> Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        let point = |point: Option<(u32, u32, i8)>| match point {
+            Some((x, y, z)) => format!("{{\"x\": {}, \"y\": {}, \"z\": {}}}", x, y, z),
+            None => "null".to_string(),
+        };
+        let pairs = |entries: Vec<String>| entries.join(", ");
+        writeln!(writer, "{{")?;
+        writeln!(writer, "  \"facet\": {},", self.map_id)?;
+        writeln!(writer, "  \"cells\": {},", self.cells)?;
+        writeln!(writer, "  \"water_percent\": {:.2},", self.water_percent())?;
+        writeln!(
+            writer,
+            "  \"impassable_percent\": {:.2},",
+            self.impassable_percent()
+        )?;
+        writeln!(writer, "  \"statics\": {},", self.statics)?;
+        writeln!(
+            writer,
+            "  \"statics_per_block\": {:.2},",
+            self.statics_per_block()
+        )?;
+        writeln!(writer, "  \"highest\": {},", point(self.highest))?;
+        writeln!(writer, "  \"lowest\": {},", point(self.lowest))?;
+        writeln!(
+            writer,
+            "  \"altitudes\": {{{}}},",
+            pairs(
+                self.altitudes
+                    .iter()
+                    .enumerate()
+                    .filter(|&(_, &count)| count > 0)
+                    .map(|(altitude, count)| format!("\"{}\": {}", altitude as i32 - 128, count))
+                    .collect()
+            )
+        )?;
+        writeln!(
+            writer,
+            "  \"density\": {{{}}},",
+            pairs(
+                self.density
+                    .iter()
+                    .map(|(statics, count)| format!("\"{}\": {}", statics, count))
+                    .collect()
+            )
+        )?;
+        writeln!(
+            writer,
+            "  \"land\": {{{}}},",
+            pairs(
+                self.land_counts
+                    .iter()
+                    .map(|(graphic, count)| format!("\"0x{:04X}\": {}", graphic, count))
+                    .collect()
+            )
+        )?;
+        writeln!(
+            writer,
+            "  \"statics_by_id\": {{{}}}",
+            pairs(
+                self.static_counts
+                    .iter()
+                    .map(|(object_id, count)| format!("\"0x{:04X}\": {}", object_id, count))
+                    .collect()
+            )
+        )?;
+        writeln!(writer, "}}")?;
+        writer.flush()
+    }
+
+    // Writes both reports side by side, returning the stem they share
+    pub fn export(&self, directory: &Path) -> Result<PathBuf> {
+        fs::create_dir_all(directory)?;
+        let stem = directory.join(format!("stats{}", self.map_id));
+        self.write_csv(&stem.with_extension("csv"))?;
+        self.write_json(&stem.with_extension("json"))?;
+        Ok(stem)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FacetStats;
+    use uorustlibs::map::{Block, Cell, StaticLocation};
+    use uorustlibs::tiledata::Flags;
+
+    fn block(altitudes: &[(usize, i8)]) -> Block {
+        let mut block = Block {
+            header: 0,
+            cells: [Cell {
+                graphic: 3,
+                altitude: 0,
+            }; 64],
+        };
+        for &(index, altitude) in altitudes {
+            block.cells[index].altitude = altitude;
+        }
+        block
+    }
+
+    fn location(object_id: u16, x: u8, y: u8) -> StaticLocation {
+        StaticLocation {
+            object_id,
+            x,
+            y,
+            altitude: 0,
+            unknown: 0,
+        }
+    }
+
+    #[test]
+    fn wet_cells_count_as_water_even_when_impassable() {
+        let (wet, impassable) = (Flags::WetFlag as u32, Flags::ImpassableFlag as u32);
+        let mut flags = [0; 64];
+        flags[0] = wet;
+        flags[1] = wet | impassable;
+        flags[2] = impassable;
+        flags[3] = impassable;
+        let mut stats = FacetStats::new(0, 1, 1);
+        stats.add_block(
+            0,
+            0,
+            &block(&[]),
+            &[location(7, 0, 0), location(7, 1, 0)],
+            &flags,
+        );
+        assert_eq!(
+            (stats.cells, stats.water_cells, stats.impassable_cells),
+            (64, 2, 2)
+        );
+        assert_eq!(stats.statics, 2);
+        assert_eq!(stats.static_counts.get(&7), Some(&2));
+        assert_eq!(stats.density.get(&2), Some(&1));
+        assert_eq!(stats.land_counts.get(&3), Some(&64));
+    }
+
+    #[test]
+    fn altitudes_are_counted_from_the_lowest() {
+        let mut stats = FacetStats::new(0, 2, 2);
+        stats.add_block(0, 0, &block(&[(0, -128), (1, 127), (2, -1)]), &[], &[0; 64]);
+        assert_eq!(stats.altitudes[0], 1);
+        assert_eq!(stats.altitudes[255], 1);
+        assert_eq!(stats.altitudes[127], 1);
+        assert_eq!(stats.altitudes[128], 61);
+        assert_eq!(stats.altitudes.iter().sum::<u64>(), 64);
+    }
+
+    #[test]
+    fn highest_and_lowest_keep_the_first_cell_found() {
+        let mut stats = FacetStats::new(0, 2, 2);
+        // Cell 9 of block (1, 0) is world cell (9, 1), and 62 of block (0, 1) is (6, 15)
+        stats.add_block(1, 0, &block(&[(9, 40), (10, 40), (20, -20)]), &[], &[0; 64]);
+        stats.add_block(0, 1, &block(&[(62, 41), (63, -20)]), &[], &[0; 64]);
+        assert_eq!(stats.highest, Some((6, 15, 41)));
+        assert_eq!(stats.lowest, Some((12, 2, -20)));
+    }
+}
@@ -0,0 +1,449 @@
+use caches::facet_cache::{Altitudes, BlockRecord};
+use caches::tiledata_cache::TileDataCache;
+use map::Facet;
+use std::cmp::{max, min};
+use std::rc::Rc;
+use uorustlibs::map::StaticLocation;
+use uorustlibs::tiledata::Flags;
+
+// Taken from the server's movement rules. Mobiles are 16 tall, and can only step up onto
+// something whose base is at most 2 above where they're standing.
+pub const PERSON_HEIGHT: i32 = 16;
+pub const STEP_HEIGHT: i32 = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Walkability {
+    Walkable,
+    Blocked,
+    Water,
+    // Somewhere to stand, but none of the neighbours can step up onto it
+    StepTooHigh,
+}
+
+impl Walkability {
+    pub fn color(self) -> (u8, u8, u8) {
+        match self {
+            Walkability::Walkable => (60, 200, 60),
+            Walkability::Blocked => (200, 40, 40),
+            Walkability::Water => (40, 90, 220),
+            Walkability::StepTooHigh => (240, 170, 0),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct WalkCell {
+    pub walkability: Walkability,
+    // Where a mobile would stand, or the land's altitude if nothing can be stood on
+    pub z: i32,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Surface {
+    bottom: i32,
+    top: i32,
+}
+
+// Everything in a cell that matters for moving through it
+pub struct CellSurfaces {
+    standable: Vec<Surface>,
+    wet: bool,
+    land_z: i32,
+}
+
+impl CellSurfaces {
+    pub fn highest(&self) -> Option<i32> {
+        self.standable.iter().map(|surface| surface.top).max()
+    }
+
+    // Like the server, a step lands on the highest surface whose base can be stepped up to
+    pub fn step_from(&self, z: i32) -> Option<i32> {
+        self.standable
+            .iter()
+            .filter(|surface| surface.bottom <= z + STEP_HEIGHT)
+            .map(|surface| surface.top)
+            .max()
+    }
+}
+
+// The land's lowest corner, the height stood at, which comes from whichever diagonal is less
+// steep, and its highest corner
+pub fn land_heights(altitudes: &Altitudes) -> (i32, i32, i32) {
+    let (top, right, left, bottom) = (
+        altitudes.x1y1 as i32,
+        altitudes.x2y1 as i32,
+        altitudes.x1y2 as i32,
+        altitudes.x2y2 as i32,
+    );
+    let low = min(min(top, right), min(left, bottom));
+    let average = if (top - bottom).abs() > (left - right).abs() {
+        (left + right).div_euclid(2)
+    } else {
+        (top + bottom).div_euclid(2)
+    };
+    let high = max(max(top, right), max(left, bottom));
+    (low, average, high)
+}
+
+// The rules for a single cell, given the land's flags and a lookup for each static's flags and
+// height
+pub fn cell_surfaces<F>(
+    land_flags: u32,
+    altitudes: &Altitudes,
+    statics: &[&StaticLocation],
+    mut static_data: F,
+) -> CellSurfaces
+where
+    F: FnMut(u16) -> (u32, u8),
+{
+    let (low, average, _) = land_heights(altitudes);
+    let mut wet = land_flags & Flags::WetFlag as u32 != 0;
+    // Each remembers the static it came from, so nothing gets in its own way
+    let mut candidates = vec![];
+    let mut obstacles = vec![];
+    if land_flags & Flags::ImpassableFlag as u32 == 0 {
+        candidates.push((
+            None,
+            Surface {
+                bottom: low,
+                top: average,
+            },
+        ));
+    }
+    for (index, location) in statics.iter().enumerate() {
+        let (flags, height) = static_data(location.object_id);
+        let z = location.altitude as i32;
+        let full_top = z + height as i32;
+        if flags & Flags::WetFlag as u32 != 0 {
+            wet = true;
+        }
+        if flags & (Flags::SurfaceFlag as u32 | Flags::ImpassableFlag as u32) == 0 {
+            continue;
+        }
+        obstacles.push((
+            index,
+            Surface {
+                bottom: z,
+                top: full_top,
+            },
+        ));
+        if flags & Flags::SurfaceFlag as u32 != 0 && flags & Flags::ImpassableFlag as u32 == 0 {
+            // Bridges are stood on halfway up, so their slopes join the land either side
+            let top = if flags & Flags::BridgeFlag as u32 != 0 {
+                z + height as i32 / 2
+            } else {
+                full_top
+            };
+            candidates.push((Some(index), Surface { bottom: z, top }));
+        }
+    }
+
+    // Only surfaces with headroom count, so land under a wall or a floor under a table is out
+    let standable = candidates
+        .into_iter()
+        .filter(|&(source, surface)| {
+            !obstacles.iter().any(|&(index, obstacle)| {
+                source != Some(index)
+                    && obstacle.bottom < surface.top + PERSON_HEIGHT
+                    && obstacle.top > surface.top
+            })
+        })
+        .map(|(_, surface)| surface)
+        .collect();
+    CellSurfaces {
+        standable,
+        wet,
+        land_z: average,
+    }
+}
+
+fn record_surfaces(
+    record: &BlockRecord,
+    cell_x: u8,
+    cell_y: u8,
+    tiledata: &mut TileDataCache,
+) -> CellSurfaces {
+    let index = (cell_y as usize) * 8 + cell_x as usize;
+    let statics = record
+        .statics
+        .iter()
+        .filter(|location| location.x == cell_x && location.y == cell_y)
+        .collect::<Vec<_>>();
+    let land_flags = tiledata.land_flags(record.block.cells[index].graphic as u32);
+    cell_surfaces(
+        land_flags,
+        &record.altitudes[index],
+        &statics,
+        |object_id| tiledata.static_flags_and_height(object_id as u32),
+    )
+}
+
+pub fn surfaces_at(
+    facet: &mut Facet,
+    tiledata: &mut TileDataCache,
+    x: i32,
+    y: i32,
+) -> Option<CellSurfaces> {
+    if x < 0
+        || y < 0
+        || x as u32 >= facet.width_blocks() * 8
+        || y as u32 >= facet.height_blocks() * 8
+    {
+        return None;
+    }
+    let record = facet.read_block(x as u32 / 8, y as u32 / 8)?;
+    Some(record_surfaces(&record, x as u8 % 8, y as u8 % 8, tiledata))
+}
+
+// The records classify_block works from: the block and the eight around it, a row at a time
+// from the top left, with None for anything off the facet or not read yet
+pub fn neighbourhood_records<F>(
+    block_x: u32,
+    block_y: u32,
+    mut read: F,
+) -> Vec<Option<Rc<BlockRecord>>>
+where
+    F: FnMut(u32, u32) -> Option<Rc<BlockRecord>>,
+{
+    let mut records = vec![];
+    for dy in -1..2 {
+        for dx in -1..2 {
+            let (x, y) = (block_x as i64 + dx, block_y as i64 + dy);
+            records.push(if x < 0 || y < 0 {
+                None
+            } else {
+                read(x as u32, y as u32)
+            });
+        }
+    }
+    records
+}
+
+fn can_step(from: &Surface, to: &Surface) -> bool {
+    to.bottom <= from.top + STEP_HEIGHT
+}
+
+// Works out every cell of a block from neighbourhood_records, taking the ring of cells around
+// it from the neighbouring blocks so the edges can be stepped onto from outside
+pub fn classify_block(
+    tiledata: &mut TileDataCache,
+    records: &[Option<Rc<BlockRecord>>],
+) -> Vec<WalkCell> {
+    let mut grid = vec![];
+    for y in 0..10 {
+        for x in 0..10 {
+            // Shifted a block along, so the ring starts on the last cell of the blocks before
+            let (x, y) = (x + 7, y + 7);
+            let surfaces = records[(y / 8) * 3 + x / 8]
+                .as_ref()
+                .map(|record| record_surfaces(record, (x % 8) as u8, (y % 8) as u8, tiledata));
+            grid.push(surfaces);
+        }
+    }
+
+    let mut cells = vec![];
+    for y in 1..9 {
+        for x in 1..9 {
+            let cell = match grid[y * 10 + x] {
+                Some(ref cell) => cell,
+                None => {
+                    cells.push(WalkCell {
+                        walkability: Walkability::Blocked,
+                        z: 0,
+                    });
+                    continue;
+                }
+            };
+            let walk_cell = match cell.highest() {
+                Some(z) => {
+                    let mut neighbours = vec![];
+                    for dy in 0..3 {
+                        for dx in 0..3 {
+                            if dx == 1 && dy == 1 {
+                                continue;
+                            }
+                            if let Some(ref neighbour) = grid[(y + dy - 1) * 10 + x + dx - 1] {
+                                neighbours.extend(neighbour.standable.iter().cloned());
+                            }
+                        }
+                    }
+                    let reachable = neighbours.is_empty()
+                        || neighbours
+                            .iter()
+                            .any(|from| cell.standable.iter().any(|to| can_step(from, to)));
+                    WalkCell {
+                        walkability: if reachable {
+                            Walkability::Walkable
+                        } else {
+                            Walkability::StepTooHigh
+                        },
+                        z,
+                    }
+                }
+                None => WalkCell {
+                    walkability: if cell.wet {
+                        Walkability::Water
+                    } else {
+                        Walkability::Blocked
+                    },
+                    z: cell.land_z,
+                },
+            };
+            cells.push(walk_cell);
+        }
+    }
+    cells
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{can_step, cell_surfaces, land_heights, CellSurfaces, Surface};
+    use caches::facet_cache::Altitudes;
+    use uorustlibs::map::StaticLocation;
+    use uorustlibs::tiledata::Flags;
+
+    const WALL: u16 = 1;
+    const FLOOR: u16 = 2;
+    const TABLE: u16 = 3;
+    const BRIDGE: u16 = 4;
+    const PLANT: u16 = 5;
+    const WATER: u16 = 6;
+
+    fn static_data(object_id: u16) -> (u32, u8) {
+        match object_id {
+            WALL => (Flags::ImpassableFlag as u32, 20),
+            FLOOR => (Flags::SurfaceFlag as u32, 0),
+            TABLE => (Flags::SurfaceFlag as u32, 6),
+            BRIDGE => (Flags::SurfaceFlag as u32 | Flags::BridgeFlag as u32, 10),
+            WATER => (Flags::WetFlag as u32, 0),
+            _ => (0, 3),
+        }
+    }
+
+    fn flat(z: i8) -> Altitudes {
+        Altitudes {
+            x1y1: z,
+            x2y1: z,
+            x1y2: z,
+            x2y2: z,
+        }
+    }
+
+    fn item(object_id: u16, altitude: i8) -> StaticLocation {
+        StaticLocation {
+            object_id,
+            x: 0,
+            y: 0,
+            altitude,
+            unknown: 0,
+        }
+    }
+
+    fn surfaces(land_flags: u32, altitudes: Altitudes, statics: &[StaticLocation]) -> CellSurfaces {
+        let statics = statics.iter().collect::<Vec<_>>();
+        cell_surfaces(land_flags, &altitudes, &statics, static_data)
+    }
+
+    fn tops(cell: &CellSurfaces) -> Vec<i32> {
+        cell.standable.iter().map(|surface| surface.top).collect()
+    }
+
+    #[test]
+    fn flat_land_heights_are_all_the_same() {
+        assert_eq!(land_heights(&flat(7)), (7, 7, 7));
+    }
+
+    #[test]
+    fn sloped_land_stands_on_the_gentler_diagonal() {
+        // Top to bottom rises 20, left to right only 4
+        let altitudes = Altitudes {
+            x1y1: 0,
+            x2y1: 8,
+            x1y2: 4,
+            x2y2: 20,
+        };
+        assert_eq!(land_heights(&altitudes), (0, 6, 20));
+        let altitudes = Altitudes {
+            x1y1: 0,
+            x2y1: -20,
+            x1y2: 3,
+            x2y2: -1,
+        };
+        assert_eq!(land_heights(&altitudes), (-20, -1, 3));
+    }
+
+    #[test]
+    fn land_heights_round_down_below_zero() {
+        let altitudes = Altitudes {
+            x1y1: -1,
+            x2y1: -5,
+            x1y2: 5,
+            x2y2: 0,
+        };
+        assert_eq!(land_heights(&altitudes).1, -1);
+    }
+
+    #[test]
+    fn open_land_is_stood_on() {
+        let cell = surfaces(0, flat(5), &[item(PLANT, 5)]);
+        assert_eq!(tops(&cell), vec![5]);
+        assert!(!cell.wet);
+    }
+
+    #[test]
+    fn impassable_land_has_nowhere_to_stand() {
+        let cell = surfaces(Flags::ImpassableFlag as u32, flat(0), &[]);
+        assert_eq!(cell.highest(), None);
+        assert_eq!(cell.land_z, 0);
+    }
+
+    #[test]
+    fn wet_statics_make_the_cell_water() {
+        let cell = surfaces(Flags::ImpassableFlag as u32, flat(-5), &[item(WATER, -5)]);
+        assert!(cell.wet);
+        assert_eq!(cell.highest(), None);
+    }
+
+    #[test]
+    fn walls_cover_the_land() {
+        let cell = surfaces(0, flat(0), &[item(WALL, 0)]);
+        assert_eq!(cell.highest(), None);
+    }
+
+    #[test]
+    fn land_keeps_its_headroom_under_something_high_up() {
+        let cell = surfaces(0, flat(0), &[item(WALL, 16)]);
+        assert_eq!(tops(&cell), vec![0]);
+    }
+
+    #[test]
+    fn tables_take_the_floor_under_them() {
+        let cell = surfaces(0, flat(0), &[item(FLOOR, 0), item(TABLE, 0)]);
+        assert_eq!(tops(&cell), vec![6]);
+    }
+
+    #[test]
+    fn bridges_are_stood_on_halfway_up() {
+        let cell = surfaces(0, flat(0), &[item(BRIDGE, 0)]);
+        assert_eq!(tops(&cell), vec![5]);
+    }
+
+    #[test]
+    fn steps_only_go_up_so_far() {
+        let ground = Surface { bottom: 0, top: 0 };
+        let stair = Surface { bottom: 2, top: 7 };
+        let ledge = Surface { bottom: 3, top: 3 };
+        assert!(can_step(&ground, &stair));
+        assert!(!can_step(&ground, &ledge));
+        assert!(can_step(&stair, &ground));
+    }
+
+    #[test]
+    fn stepping_lands_on_the_highest_reachable_surface() {
+        let cell = surfaces(0, flat(0), &[item(BRIDGE, 20)]);
+        assert_eq!(tops(&cell), vec![0, 25]);
+        assert_eq!(cell.step_from(0), Some(0));
+        assert_eq!(cell.step_from(18), Some(25));
+        assert_eq!(cell.step_from(-3), None);
+    }
+}
@@ -0,0 +1,357 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Result, Seek, SeekFrom, Write};
+use std::path::Path;
+use uorustlibs::map::{Block, StaticLocation};
+
+// A map block is a 4 byte header followed by 64 cells of graphic and altitude
+pub const CELL_SIZE: u64 = 3;
+pub const BLOCK_SIZE: u64 = 4 + 64 * CELL_SIZE;
+// Index entries are a lookup, a length and an unused extra field
+pub const INDEX_ENTRY_SIZE: u64 = 12;
+pub const EMPTY_LOOKUP: u32 = 0xFFFFFFFF;
+// Statics are an object id, x and y within the block, altitude and hue
+pub const STATIC_SIZE: u64 = 7;
+
+// Blocks are stored a column at a time, top to bottom
+pub fn block_index(x: u32, y: u32, height_blocks: u32) -> u64 {
+    x as u64 * height_blocks as u64 + y as u64
+}
+
+pub fn encode_cells(cells: &[(u16, i8)]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(cells.len() * CELL_SIZE as usize);
+    for &(graphic, altitude) in cells {
+        bytes.extend_from_slice(&graphic.to_le_bytes());
+        bytes.push(altitude as u8);
+    }
+    bytes
+}
+
+pub fn block_cells(block: &Block) -> Vec<(u16, i8)> {
+    block
+        .cells
+        .iter()
+        .map(|cell| (cell.graphic, cell.altitude))
+        .collect()
+}
+
+// Rewrites blocks of an existing map file in place. Headers are left as they were.
+pub struct MapWriter {
+    file: File,
+    height_blocks: u32,
+}
+
+impl MapWriter {
+    pub fn open(path: &Path, height_blocks: u32) -> Result<MapWriter> {
+        let file = OpenOptions::new().write(true).open(path)?;
+        Ok(MapWriter {
+            file,
+            height_blocks,
+        })
+    }
+
+    pub fn write_block(&mut self, x: u32, y: u32, block: &Block) -> Result<()> {
+        let offset = block_index(x, y, self.height_blocks) * BLOCK_SIZE + 4;
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.write_all(&encode_cells(&block_cells(block)))
+    }
+}
+
+pub fn encode_statics(statics: &[StaticLocation]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(statics.len() * STATIC_SIZE as usize);
+    for location in statics {
+        bytes.extend_from_slice(&location.object_id.to_le_bytes());
+        bytes.push(location.x);
+        bytes.push(location.y);
+        bytes.push(location.altitude as u8);
+        // uorustlibs doesn't name it, but the last field is the hue
+        bytes.extend_from_slice(&location.unknown.to_le_bytes());
+    }
+    bytes
+}
+
+// Changed blocks are appended to the end of the statics file and their index entries pointed
+// at the new copy. The old data is left behind as dead space, the same as other editors do.
+pub struct StaticWriter {
+    index: File,
+    statics: File,
+    height_blocks: u32,
+}
+
+impl StaticWriter {
+    pub fn open(
+        index_path: &Path,
+        statics_path: &Path,
+        height_blocks: u32,
+    ) -> Result<StaticWriter> {
+        Ok(StaticWriter {
+            index: OpenOptions::new().write(true).open(index_path)?,
+            statics: OpenOptions::new().write(true).open(statics_path)?,
+            height_blocks,
+        })
+    }
+
+    pub fn write_block(&mut self, x: u32, y: u32, statics: &[StaticLocation]) -> Result<()> {
+        let (lookup, length) = if statics.is_empty() {
+            (EMPTY_LOOKUP, 0)
+        } else {
+            let lookup = self.statics.seek(SeekFrom::End(0))?;
+            self.statics.write_all(&encode_statics(statics))?;
+            (lookup as u32, statics.len() as u32 * STATIC_SIZE as u32)
+        };
+        // Only the lookup and length are rewritten, whatever is in the extra field stays put
+        let entry = block_index(x, y, self.height_blocks) * INDEX_ENTRY_SIZE;
+        self.index.seek(SeekFrom::Start(entry))?;
+        self.index.write_all(&lookup.to_le_bytes())?;
+        self.index.write_all(&length.to_le_bytes())
+    }
+}
+
+fn create(path: &Path) -> Result<BufWriter<File>> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    Ok(BufWriter::new(File::create(path)?))
+}
+
+// Writes a whole facet in file order, asking for each block's 64 cells in turn
+pub fn write_map<F>(
+    path: &Path,
+    width_blocks: u32,
+    height_blocks: u32,
+    mut cells_at: F,
+) -> Result<()>
+where
+    F: FnMut(u32, u32) -> Vec<(u16, i8)>,
+{
+    let mut writer = create(path)?;
+    for x in 0..width_blocks {
+        for y in 0..height_blocks {
+            writer.write_all(&0u32.to_le_bytes())?;
+            writer.write_all(&encode_cells(&cells_at(x, y)))?;
+        }
+    }
+    writer.flush()
+}
+
+// Writes a whole statics file and its index in file order, asking for each block's statics
+// in turn
+pub fn write_statics<F>(
+    index_path: &Path,
+    statics_path: &Path,
+    width_blocks: u32,
+    height_blocks: u32,
+    mut statics_at: F,
+) -> Result<()>
+where
+    F: FnMut(u32, u32) -> Vec<StaticLocation>,
+{
+    let mut index = create(index_path)?;
+    let mut data = create(statics_path)?;
+    let mut lookup: u64 = 0;
+    for x in 0..width_blocks {
+        for y in 0..height_blocks {
+            let statics = statics_at(x, y);
+            if statics.is_empty() {
+                index.write_all(&EMPTY_LOOKUP.to_le_bytes())?;
+                index.write_all(&0u32.to_le_bytes())?;
+            } else {
+                let bytes = encode_statics(&statics);
+                data.write_all(&bytes)?;
+                index.write_all(&(lookup as u32).to_le_bytes())?;
+                index.write_all(&(bytes.len() as u32).to_le_bytes())?;
+                lookup += bytes.len() as u64;
+            }
+            index.write_all(&0u32.to_le_bytes())?;
+        }
+    }
+    index.flush()?;
+    data.flush()
+}
+
+// A statics pair where every block is empty, for facets that only have land
+pub fn write_empty_statics(
+    index_path: &Path,
+    statics_path: &Path,
+    width_blocks: u32,
+    height_blocks: u32,
+) -> Result<()> {
+    write_statics(
+        index_path,
+        statics_path,
+        width_blocks,
+        height_blocks,
+        |_, _| vec![],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use std::{env, process};
+    use uorustlibs::map::{Cell, StaticReader};
+
+    fn temp_directory(name: &str) -> PathBuf {
+        let directory = env::temp_dir().join(format!("writer_{}_{}", name, process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    fn location(object_id: u16, x: u8, y: u8, altitude: i8, hue: u16) -> StaticLocation {
+        StaticLocation {
+            object_id,
+            x,
+            y,
+            altitude,
+            unknown: hue,
+        }
+    }
+
+    fn fields(statics: &[StaticLocation]) -> Vec<(u16, u8, u8, i8, u16)> {
+        statics
+            .iter()
+            .map(|s| (s.object_id, s.x, s.y, s.altitude, s.unknown))
+            .collect()
+    }
+
+    fn u32_at(bytes: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+    }
+
+    #[test]
+    fn statics_are_encoded_in_file_order() {
+        let bytes = encode_statics(&[location(0x1234, 3, 7, -2, 0x0481)]);
+        assert_eq!(bytes, vec![0x34, 0x12, 3, 7, 0xFE, 0x81, 0x04]);
+    }
+
+    #[test]
+    fn map_blocks_are_rewritten_in_place_under_their_headers() {
+        let directory = temp_directory("map");
+        let path = directory.join("map.mul");
+        // Headers are written as 0, so mark them to show they survive
+        write_map(&path, 2, 3, |_, _| vec![(3, 0); 64]).unwrap();
+        let mut bytes = fs::read(&path).unwrap();
+        for header in bytes.chunks_mut(BLOCK_SIZE as usize) {
+            header[..4].copy_from_slice(&[0xAB; 4]);
+        }
+        fs::write(&path, &bytes).unwrap();
+
+        let block = Block {
+            header: 0,
+            cells: [Cell {
+                graphic: 0x0102,
+                altitude: -1,
+            }; 64],
+        };
+        MapWriter::open(&path, 3)
+            .unwrap()
+            .write_block(1, 2, &block)
+            .unwrap();
+        let written = fs::read(&path).unwrap();
+        assert_eq!(written.len(), bytes.len());
+        // Column 1, row 2 of a 3 block high facet is the sixth block in the file
+        let offset = (5 * BLOCK_SIZE) as usize;
+        assert_eq!(written[..offset], bytes[..offset]);
+        assert_eq!(written[offset..offset + 4], [0xAB; 4]);
+        assert_eq!(written[offset + 4..offset + 7], [0x02, 0x01, 0xFF]);
+        assert_eq!(
+            written[offset + 4..],
+            encode_cells(&block_cells(&block))[..]
+        );
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn static_blocks_are_appended_and_reindexed() {
+        let directory = temp_directory("statics");
+        let (index, data) = (directory.join("staidx.mul"), directory.join("statics.mul"));
+        write_statics(&index, &data, 2, 2, |x, y| {
+            vec![location(0x100 + (x * 2 + y) as u16, 0, 0, 0, 0)]
+        })
+        .unwrap();
+        let original_length = fs::read(&data).unwrap().len() as u32;
+        // Mark the extra fields, which should be left alone
+        let mut entries = fs::read(&index).unwrap();
+        for entry in entries.chunks_mut(INDEX_ENTRY_SIZE as usize) {
+            entry[8..].copy_from_slice(&[0xCD; 4]);
+        }
+        fs::write(&index, &entries).unwrap();
+
+        let replacement = vec![location(0x200, 1, 2, 3, 4), location(0x201, 5, 6, -7, 8)];
+        let mut writer = StaticWriter::open(&index, &data, 2).unwrap();
+        writer.write_block(0, 1, &replacement).unwrap();
+        writer.write_block(1, 0, &[]).unwrap();
+
+        let written = fs::read(&data).unwrap();
+        assert_eq!(
+            written.len() as u32,
+            original_length + 2 * STATIC_SIZE as u32
+        );
+        assert_eq!(
+            written[original_length as usize..],
+            encode_statics(&replacement)[..]
+        );
+        let entries = fs::read(&index).unwrap();
+        let entry = |x: u32, y: u32| {
+            let at = (block_index(x, y, 2) * INDEX_ENTRY_SIZE) as usize;
+            (
+                u32_at(&entries, at),
+                u32_at(&entries, at + 4),
+                u32_at(&entries, at + 8),
+            )
+        };
+        assert_eq!(entry(0, 1), (original_length, 14, 0xCDCDCDCD));
+        assert_eq!(entry(1, 0), (EMPTY_LOOKUP, 0, 0xCDCDCDCD));
+        // Untouched blocks still point at their first copy
+        assert_eq!(entry(1, 1), (3 * STATIC_SIZE as u32, 7, 0xCDCDCDCD));
+
+        let mut reader = StaticReader::new(&index, &data, 2, 2).unwrap();
+        let mut read = |x, y| {
+            reader
+                .read_block_from_coordinates(x, y, None)
+                .unwrap_or_default()
+        };
+        assert_eq!(fields(&read(0, 1)), fields(&replacement));
+        assert_eq!(fields(&read(1, 0)), vec![]);
+        assert_eq!(fields(&read(0, 0)), vec![(0x100, 0, 0, 0, 0)]);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn whole_statics_files_read_back() {
+        let directory = temp_directory("round_trip");
+        let (index, data) = (directory.join("staidx.mul"), directory.join("statics.mul"));
+        let statics_at = |x: u32, y: u32| {
+            (0..x * 3 + y)
+                .map(|n| {
+                    location(
+                        n as u16,
+                        n as u8 % 8,
+                        y as u8,
+                        -(n as i8),
+                        0x0400 + n as u16,
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        write_statics(&index, &data, 3, 3, statics_at).unwrap();
+        assert_eq!(fs::read(&index).unwrap().len() as u64, 9 * INDEX_ENTRY_SIZE);
+
+        let mut reader = StaticReader::new(&index, &data, 3, 3).unwrap();
+        for x in 0..3 {
+            for y in 0..3 {
+                let read = reader
+                    .read_block_from_coordinates(x, y, None)
+                    .unwrap_or_default();
+                assert_eq!(
+                    fields(&read),
+                    fields(&statics_at(x, y)),
+                    "block {}, {}",
+                    x,
+                    y
+                );
+            }
+        }
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -1,135 +1,114 @@
 use caches::art_cache::ArtCache;
-use caches::facet_cache::read_altitudes;
+use caches::land_mesh_cache::LandMeshCache;
 use caches::texmap_cache::TexMapCache;
 use cgmath::Point2;
+use engine::EngineData;
 use ggez::event::{KeyCode, KeyMods};
-use ggez::graphics::{self, DrawParam, Text};
+use ggez::graphics::{self, Text};
 use ggez::{Context, GameResult};
-use map::render::draw_block;
-use map::{map_id_to_facet, Facet, MAP_DETAILS};
+use map::patches::patch_coordinates;
+use map::render::{draw_neighbourhood, NEIGHBOURHOOD};
+use map::{map_id_to_facet, Facet};
 use scene::{BoxedScene, Scene, SceneChangeEvent, SceneName};
-use std::collections::HashMap;
-use std::io::Result;
-use std::path::Path;
-use uorustlibs::map::{Block, MapDiffReader, StaticDiffReader, StaticLocation};
 
 pub struct MapDiffScene {
     art_cache: ArtCache,
     texmap_cache: TexMapCache,
-    map_patches: HashMap<u32, Result<Block>>,
-    static_patches: HashMap<u32, Result<Vec<StaticLocation>>>,
+    land_meshes: LandMeshCache,
+    facet: Facet,
+    patch_indices: Vec<u32>,
     map_id: u8,
     patch_id: u32,
     exiting: bool,
 }
 
 impl<'a> MapDiffScene {
-    pub fn new(ctx: &mut Context) -> BoxedScene<'a, SceneName, ()> {
+    pub fn new(_ctx: &mut Context) -> BoxedScene<'a, SceneName, EngineData> {
         let mut scene = Box::new(MapDiffScene {
             exiting: false,
             map_id: 0,
             patch_id: 0,
-            map_patches: MapDiffReader::new(
-                &Path::new("./assets/mapdifl0.mul"),
-                &Path::new("./assets/mapdif0.mul"),
-            )
-            .unwrap()
-            .read_all(),
-            static_patches: StaticDiffReader::new(
-                &Path::new("./assets/stadifl0.mul"),
-                &Path::new("./assets/stadifi0.mul"),
-                &Path::new("./assets/stadif0.mul"),
-            )
-            .unwrap()
-            .read_all(),
+            facet: map_id_to_facet(0),
+            patch_indices: vec![],
             art_cache: ArtCache::new(),
             texmap_cache: TexMapCache::new(),
+            land_meshes: LandMeshCache::new(),
         });
-        scene.get_next_patch();
+        scene.get_patch_data();
         scene
     }
 
     pub fn get_patch_data(&mut self) {
         self.patch_id = 0;
-        self.map_patches = MapDiffReader::new(
-            &Path::new(&format!("./assets/mapdifl{}.mul", self.map_id)),
-            &Path::new(&format!("./assets/mapdif{}.mul", self.map_id)),
-        )
-        .unwrap()
-        .read_all();
-        self.static_patches = StaticDiffReader::new(
-            &Path::new(&format!("./assets/stadifl{}.mul", self.map_id)),
-            &Path::new(&format!("./assets/stadifi{}.mul", self.map_id)),
-            &Path::new(&format!("./assets/stadif{}.mul", self.map_id)),
-        )
-        .unwrap()
-        .read_all();
-        self.get_next_patch();
+        self.facet = map_id_to_facet(self.map_id);
+        self.land_meshes.flush();
+        self.facet.set_patched(true);
+        self.patch_indices = self.facet.patch_indices();
+        match self.patch_indices.first() {
+            Some(&first) => self.patch_id = first,
+            None => (),
+        }
     }
 
     pub fn get_next_patch(&mut self) {
-        let mut keys = self.map_patches.keys().collect::<Vec<_>>();
-        let mut more_keys = self.static_patches.keys().collect::<Vec<_>>();
-
-        keys.append(&mut more_keys);
-        keys.sort();
-        keys.dedup();
-        let next_key = keys.iter().find(|key| ***key > self.patch_id);
+        let next_key = self.patch_indices.iter().find(|key| **key > self.patch_id);
         match next_key {
-            Some(key) => self.patch_id = **key,
+            Some(key) => self.patch_id = *key,
             _ => (),
         }
     }
 
     pub fn get_last_patch(&mut self) {
-        let mut keys = self.map_patches.keys().collect::<Vec<_>>();
-        let mut more_keys = self.static_patches.keys().collect::<Vec<_>>();
-
-        keys.append(&mut more_keys);
-        keys.sort();
-        keys.dedup();
-        keys.reverse();
-        let next_key = keys.iter().find(|key| ***key < self.patch_id);
+        let next_key = self
+            .patch_indices
+            .iter()
+            .rev()
+            .find(|key| **key < self.patch_id);
         match next_key {
-            Some(key) => self.patch_id = **key,
+            Some(key) => self.patch_id = *key,
             _ => (),
         }
     }
+}
 
-    pub fn draw_page(&mut self, ctx: &mut Context) -> GameResult<()> {
-        let block = match self.map_patches.get(&self.patch_id) {
-            Some(Ok(block)) => Some(block),
-            _ => None,
-        };
-        let default = vec![];
-        let statics = match self.static_patches.get(&self.patch_id) {
-            Some(Ok(statics)) => statics,
-            _ => &default,
-        };
-        let altitudes = if block.is_some() {
-            read_altitudes(block.unwrap(), None, None, None)
-        } else {
-            vec![]
-        };
-        let transform = Point2::new(300.0, 200.0);
-        draw_block(
+impl Scene<SceneName, EngineData> for MapDiffScene {
+    fn draw(&mut self, ctx: &mut Context, _engine_data: &mut EngineData) -> GameResult<()> {
+        graphics::clear(ctx, graphics::BLACK);
+        let screen = graphics::screen_coordinates(ctx);
+        graphics::set_screen_coordinates(ctx, NEIGHBOURHOOD)?;
+        let (block_x, block_y) = patch_coordinates(self.patch_id, self.facet.height_blocks());
+        draw_neighbourhood(
             ctx,
             &mut self.art_cache,
             &mut self.texmap_cache,
-            block,
-            &statics,
-            &altitudes,
-            transform,
-        );
-        Ok(())
-    }
-}
+            &mut self.land_meshes,
+            &mut self.facet,
+            block_x,
+            block_y,
+        )?;
+        graphics::set_screen_coordinates(ctx, screen)?;
 
-impl Scene<SceneName, ()> for MapDiffScene {
-    fn draw(&mut self, ctx: &mut Context, _engine_data: &mut ()) -> GameResult<()> {
-        graphics::clear(ctx, graphics::BLACK);
-        self.draw_page(ctx);
-        let label = Text::new(format!("Map {} patch {}", self.map_id, self.patch_id));
+        let position = self
+            .patch_indices
+            .iter()
+            .position(|&index| index == self.patch_id)
+            .map(|index| index + 1)
+            .unwrap_or(0);
+        let label = Text::new(format!(
+            "Map {} patch {} of {}, block {},{} ({},{})\n{} - Space to flip",
+            self.map_id,
+            position,
+            self.patch_indices.len(),
+            block_x,
+            block_y,
+            block_x * 8,
+            block_y * 8,
+            if self.facet.patched() {
+                "Patched"
+            } else {
+                "Original"
+            }
+        ));
         graphics::draw(ctx, &label, (Point2::new(0.0, 0.0), graphics::WHITE))?;
         Ok(())
     }
@@ -140,7 +119,7 @@ impl Scene<SceneName, ()> for MapDiffScene {
         keycode: KeyCode,
         _keymods: KeyMods,
         _repeat: bool,
-        _engine_data: &mut (),
+        _engine_data: &mut EngineData,
     ) {
         match keycode {
             KeyCode::Escape => self.exiting = true,
@@ -150,6 +129,10 @@ impl Scene<SceneName, ()> for MapDiffScene {
             KeyCode::Right => {
                 self.get_next_patch();
             }
+            KeyCode::Space => {
+                let patched = !self.facet.patched();
+                self.facet.set_patched(patched);
+            }
             KeyCode::Tab => {
                 self.map_id = (self.map_id + 1) % 3 as u8;
                 self.get_patch_data();
@@ -161,7 +144,7 @@ impl Scene<SceneName, ()> for MapDiffScene {
     fn update(
         &mut self,
         _ctx: &mut Context,
-        _engine_data: &mut (),
+        _engine_data: &mut EngineData,
     ) -> GameResult<Option<SceneChangeEvent<SceneName>>> {
         if self.exiting {
             Ok(Some(SceneChangeEvent::PopScene))
@@ -1,24 +1,47 @@
+use caches::facet_cache::BlockRecord;
+use caches::tiledata_cache::TileDataCache;
 use cgmath::Point2;
-use ggez::event::{KeyCode, KeyMods};
-use ggez::graphics::{self, Canvas, DrawParam, Image};
+use engine::EngineData;
+use ggez::event::{KeyCode, KeyMods, MouseButton};
+use ggez::graphics::{self, Canvas, DrawParam, Image, Text};
 use ggez::{Context, GameResult};
-use map::{map_id_to_facet, Facet, MAP_DETAILS};
+use map::compare::{compare_block, BlockChange};
+use map::export::{AltitudeGrid, Region, EXPORT_DIRECTORY};
+use map::pathfinding::PathSearch;
+use map::radar::{land_color, static_color, topmost_static};
+use map::shading::{hillshade, hypsometric_color, is_contour, shade, SEA_LEVEL};
+use map::walkability::{classify_block, neighbourhood_records, surfaces_at, WalkCell};
+use map::{facet_count, facet_details, facet_in, map_id_to_facet, Facet};
 use scene::{BoxedScene, Scene, SceneChangeEvent, SceneName};
+use std::cmp::min;
 use std::io::Result;
 use std::path::Path;
-use uorustlibs::color::{Color as ColorTrait, Color16};
+use std::thread;
+use uorustlibs::color::Color16;
 use uorustlibs::map::{Block, RadarColReader, StaticLocation};
 
 const MAX_BLOCKS_WIDTH: u32 = 800 / 8;
 const MAX_BLOCKS_HEIGHT: u32 = 600 / 8;
 const STEP_X: u32 = MAX_BLOCKS_WIDTH / 4;
 const STEP_Y: u32 = MAX_BLOCKS_HEIGHT / 4;
+const DEFAULT_CONTOUR_INTERVAL: u8 = 10;
+const LEGEND_WIDTH: u16 = 16;
+const LEGEND_TICKS: [i8; 7] = [127, 90, 60, 30, 10, SEA_LEVEL, -128];
+const PATCH_HIGHLIGHT: (u8, u8, u8) = (255, 0, 255);
+// Enough to keep the scene responsive while a whole facet is exported
+const EXPORT_ROWS_PER_UPDATE: u32 = 4;
+// Cells a path search explores each update, which keeps the map responsive while it runs
+const PATH_CELLS_PER_UPDATE: usize = 2000;
 
 enum MapRenderMode {
     HeightMap,
     RadarMap,
     StaticsMap,
     FullMap,
+    HypsometricMap,
+    HillshadeMap,
+    ChangeMap,
+    WalkMap,
 }
 
 pub struct MapScene {
@@ -27,6 +50,23 @@ pub struct MapScene {
     radar_colors: Result<Vec<Color16>>,
     mode: MapRenderMode,
     texture: Option<Canvas>,
+    legend: Image,
+    contours: bool,
+    contour_interval: u8,
+    tiledata: TileDataCache,
+    hide_roofs: bool,
+    highlight_patches: bool,
+    // The same facet from the --compare directory, while showing what changed
+    comparison: Option<Facet>,
+    next_scene: Option<SceneName>,
+    // Clicks pick a start and then an end cell for a path, while finding paths
+    finding_paths: bool,
+    path_start: Option<(i32, i32)>,
+    path_end: Option<(i32, i32)>,
+    path_search: Option<PathSearch>,
+    path_overlay: Option<Image>,
+    // A heightmap being read in, and the next row of blocks to read
+    heightmap_export: Option<(AltitudeGrid, u32)>,
     exiting: bool,
     x: u32,
     y: u32,
@@ -59,11 +99,7 @@ pub fn draw_radarcol_block(
     for y in 0..8 {
         for x in 0..8 {
             let target = x + (y * 8);
-            let index = block.cells[target].graphic;
-            let (r, g, b, _) = match radar_cols {
-                &Ok(ref colors) => colors[index as usize].to_rgba(),
-                _ => index.to_rgba(),
-            };
+            let (r, g, b) = land_color(radar_cols, block.cells[target].graphic);
             bitmap[target * 4] = r;
             bitmap[target * 4 + 1] = g;
             bitmap[target * 4 + 2] = b;
@@ -72,44 +108,162 @@ pub fn draw_radarcol_block(
     }
 }
 
+fn statics_by_cell(statics: &Vec<StaticLocation>) -> Vec<Vec<&StaticLocation>> {
+    let mut cells = vec![vec![]; 64];
+    for stat in statics {
+        let lookup = stat.x as usize + (stat.y as usize * 8);
+        if lookup < 64 {
+            cells[lookup].push(stat);
+        }
+    }
+    cells
+}
+
 pub fn draw_statics_block(
     bitmap: &mut Vec<u8>,
     _block: &Block,
     statics: &Vec<StaticLocation>,
     radar_cols: &Result<Vec<Color16>>,
+    tiledata: &mut TileDataCache,
+    hide_roofs: bool,
 ) {
-    let mut last_height_locs = vec![-127; 64];
-    for stat in statics {
-        let lookup = (stat.x + (stat.y * 8)) as usize;
-        if last_height_locs[lookup] < stat.altitude {
-            // Paint the cell as we're higher.
-            // Inefficient, but probably no worse than trying to keep track of which items are in which cell
-            let (r, g, b, _) = match radar_cols {
-                &Ok(ref colors) => colors[stat.color_idx() as usize].to_rgba(),
-                _ => (0, 0, 0, 0),
-            };
-            bitmap[lookup * 4] = r;
-            bitmap[lookup * 4 + 1] = g;
-            bitmap[lookup * 4 + 2] = b;
-            bitmap[lookup * 4 + 3] = 255;
-
-            last_height_locs[lookup] = stat.altitude;
+    for (lookup, cell_statics) in statics_by_cell(statics).iter().enumerate() {
+        let static_data = |object_id| tiledata.static_flags_and_height(object_id as u32);
+        match topmost_static(cell_statics, None, static_data, hide_roofs) {
+            Some(stat) => {
+                let (r, g, b) = static_color(radar_cols, stat.object_id);
+                bitmap[lookup * 4] = r;
+                bitmap[lookup * 4 + 1] = g;
+                bitmap[lookup * 4 + 2] = b;
+                bitmap[lookup * 4 + 3] = 255;
+            }
+            None => (),
         }
     }
 }
 
+// Matches the client's radar, where the land only shows through if nothing stands on it
 pub fn draw_full_block(
     bitmap: &mut Vec<u8>,
     block: &Block,
     statics: &Vec<StaticLocation>,
     radar_cols: &Result<Vec<Color16>>,
+    tiledata: &mut TileDataCache,
+    hide_roofs: bool,
 ) {
-    draw_radarcol_block(bitmap, block, statics, radar_cols);
-    draw_statics_block(bitmap, block, statics, radar_cols);
+    let cells = statics_by_cell(statics);
+    for target in 0..64 {
+        let cell = block.cells[target];
+        let static_data = |object_id| tiledata.static_flags_and_height(object_id as u32);
+        let (r, g, b) =
+            match topmost_static(&cells[target], Some(cell.altitude), static_data, hide_roofs) {
+                Some(stat) => static_color(radar_cols, stat.object_id),
+                None => land_color(radar_cols, cell.graphic),
+            };
+        bitmap[target * 4] = r;
+        bitmap[target * 4 + 1] = g;
+        bitmap[target * 4 + 2] = b;
+        bitmap[target * 4 + 3] = 255;
+    }
+}
+
+pub fn draw_hypsometric_block(
+    bitmap: &mut Vec<u8>,
+    record: &BlockRecord,
+    hillshaded: bool,
+    contour_interval: Option<u8>,
+) {
+    for y in 0..8 {
+        for x in 0..8 {
+            let target = x + (y * 8);
+            let altitudes = &record.altitudes[target];
+            let color = hypsometric_color(record.block.cells[target].altitude);
+            let color = if hillshaded {
+                shade(color, hillshade(altitudes))
+            } else {
+                color
+            };
+            let on_contour = contour_interval
+                .map(|interval| is_contour(altitudes, interval))
+                .unwrap_or(false);
+            let (r, g, b) = if on_contour {
+                shade(color, 0.45)
+            } else {
+                color
+            };
+            bitmap[target * 4] = r;
+            bitmap[target * 4 + 1] = g;
+            bitmap[target * 4 + 2] = b;
+            bitmap[target * 4 + 3] = 255;
+        }
+    }
+}
+
+// Walkability colours, darkened towards the low ground so the terrain still reads
+pub fn draw_walk_block(bitmap: &mut Vec<u8>, cells: &[WalkCell]) {
+    for (target, cell) in cells.iter().enumerate() {
+        let brightness = 0.6 + 0.4 * ((cell.z + 128).max(0).min(255) as f32 / 255.0);
+        let (r, g, b) = shade(cell.walkability.color(), brightness);
+        bitmap[target * 4] = r;
+        bitmap[target * 4 + 1] = g;
+        bitmap[target * 4 + 2] = b;
+        bitmap[target * 4 + 3] = 255;
+    }
+}
+
+// Red for a block with a few changes, through to yellow for one that's been redone
+fn change_color(score: usize) -> (u8, u8, u8) {
+    let heat = (score as f32 / 64.0).min(1.0);
+    (255, (255.0 * heat) as u8, 0)
+}
+
+// Changed cells are lit up against a dimmed heightmap, so the changes can be placed
+pub fn draw_change_block(bitmap: &mut Vec<u8>, block: &Block, change: &BlockChange) {
+    let color = change_color(change.score());
+    for target in 0..64 {
+        let (r, g, b) = if change.cell_changed(target) {
+            color
+        } else {
+            let height = ((block.cells[target].altitude as i16 + 128) / 3) as u8;
+            (height, height, height)
+        };
+        bitmap[target * 4] = r;
+        bitmap[target * 4 + 1] = g;
+        bitmap[target * 4 + 2] = b;
+        bitmap[target * 4 + 3] = 255;
+    }
+}
+
+// Outlines the block, so patches stand out without hiding what they changed
+fn highlight_block(bitmap: &mut Vec<u8>, (r, g, b): (u8, u8, u8)) {
+    for y in 0..8 {
+        for x in 0..8 {
+            if x == 0 || y == 0 || x == 7 || y == 7 {
+                let target = x + (y * 8);
+                bitmap[target * 4] = r;
+                bitmap[target * 4 + 1] = g;
+                bitmap[target * 4 + 2] = b;
+                bitmap[target * 4 + 3] = 255;
+            }
+        }
+    }
+}
+
+// A strip running from the highest altitude at the top down to the lowest
+fn build_legend(ctx: &mut Context) -> Image {
+    let mut bitmap = vec![];
+    for row in 0..256 {
+        let altitude = (127 - row) as i8;
+        let (r, g, b) = hypsometric_color(altitude);
+        for _ in 0..LEGEND_WIDTH {
+            bitmap.extend_from_slice(&[r, g, b, 255]);
+        }
+    }
+    Image::from_rgba8(ctx, LEGEND_WIDTH, 256, &bitmap).expect("Failed to create legend")
 }
 
 impl<'a> MapScene {
-    pub fn new(ctx: &mut Context) -> BoxedScene<'a, SceneName, ()> {
+    pub fn new(ctx: &mut Context) -> BoxedScene<'a, SceneName, EngineData> {
         let colors = RadarColReader::new(&Path::new("./assets/radarcol.mul"))
             .and_then(|mut reader| reader.read_colors());
 
@@ -118,70 +272,438 @@ impl<'a> MapScene {
             map_id: 0,
             texture: None,
             mode: MapRenderMode::HeightMap,
+            legend: build_legend(ctx),
+            contours: false,
+            contour_interval: DEFAULT_CONTOUR_INTERVAL,
+            tiledata: TileDataCache::new(),
+            hide_roofs: false,
+            highlight_patches: false,
+            comparison: None,
+            next_scene: None,
+            finding_paths: false,
+            path_start: None,
+            path_end: None,
+            path_search: None,
+            path_overlay: None,
+            heightmap_export: None,
             radar_colors: colors,
             exiting: false,
             x: 0,
             y: 0,
         });
 
+        scene.prefetch((0, 0));
         scene.draw_page(ctx).expect("Failed to draw map");
         scene
     }
 
+    // Reads a few more rows of the heightmap being exported. Once it's all in, the files are
+    // written on their own thread, as a whole facet takes a while.
+    fn continue_export(&mut self) {
+        let (mut grid, row) = match self.heightmap_export.take() {
+            Some(export) => export,
+            None => return,
+        };
+        let last = min(row + EXPORT_ROWS_PER_UPDATE, grid.rows());
+        for row in row..last {
+            grid.read_row(&mut self.facet, row);
+        }
+        if last < grid.rows() {
+            self.heightmap_export = Some((grid, last));
+            return;
+        }
+        let (map_id, map_path) = (self.map_id, facet_details(self.map_id).0);
+        thread::spawn(move || {
+            match grid.write_all(map_id, map_path, &Path::new(EXPORT_DIRECTORY)) {
+                Ok(stem) => println!("Exported heightmap to {}", stem.display()),
+                Err(error) => println!("Could not export heightmap: {}", error),
+            }
+        });
+    }
+
+    // Explores a few more cells of the path being searched for, and draws it once it's done
+    fn continue_path_search(&mut self, ctx: &mut Context) -> GameResult<()> {
+        let finished = match self.path_search {
+            Some(ref mut search) if !search.finished() => {
+                let (facet, tiledata) = (&mut self.facet, &mut self.tiledata);
+                search.advance(PATH_CELLS_PER_UPDATE, |x, y| {
+                    surfaces_at(facet, tiledata, x, y)
+                })
+            }
+            _ => return Ok(()),
+        };
+        if finished {
+            println!("{}", self.path_status());
+            self.build_path_overlay(ctx)?;
+        }
+        Ok(())
+    }
+
+    fn prefetch(&mut self, direction: (i32, i32)) {
+        self.facet.prefetch(
+            self.x,
+            self.y,
+            MAX_BLOCKS_WIDTH,
+            MAX_BLOCKS_HEIGHT,
+            direction,
+        );
+        if let Some(ref mut old) = self.comparison {
+            old.prefetch(
+                self.x,
+                self.y,
+                MAX_BLOCKS_WIDTH,
+                MAX_BLOCKS_HEIGHT,
+                direction,
+            );
+        }
+    }
+
+    fn draw_map_block(&mut self, ctx: &mut Context, x: u32, y: u32) -> GameResult<()> {
+        let contour_interval = if self.contours {
+            Some(self.contour_interval)
+        } else {
+            None
+        };
+        match self.facet.try_read_block(x + self.x, y + self.y) {
+            Some(record) => {
+                let mut bitmap = vec![0; 8 * 8 * 4];
+                let (block, statics, radar_colors) =
+                    (&record.block, &record.statics, &self.radar_colors);
+                match self.mode {
+                    MapRenderMode::HeightMap => {
+                        draw_heightmap_block(&mut bitmap, block, statics, radar_colors)
+                    }
+                    MapRenderMode::RadarMap => {
+                        draw_radarcol_block(&mut bitmap, block, statics, radar_colors)
+                    }
+                    MapRenderMode::StaticsMap => draw_statics_block(
+                        &mut bitmap,
+                        block,
+                        statics,
+                        radar_colors,
+                        &mut self.tiledata,
+                        self.hide_roofs,
+                    ),
+                    MapRenderMode::FullMap => draw_full_block(
+                        &mut bitmap,
+                        block,
+                        statics,
+                        radar_colors,
+                        &mut self.tiledata,
+                        self.hide_roofs,
+                    ),
+                    MapRenderMode::HypsometricMap => {
+                        draw_hypsometric_block(&mut bitmap, &record, false, contour_interval)
+                    }
+                    MapRenderMode::HillshadeMap => {
+                        draw_hypsometric_block(&mut bitmap, &record, true, contour_interval)
+                    }
+                    MapRenderMode::ChangeMap => {
+                        let (block_x, block_y) = (x + self.x, y + self.y);
+                        let change = match self.comparison {
+                            Some(ref mut old) => {
+                                let old_record = old.try_read_block(block_x, block_y);
+                                // Left for draw_arrived, rather than shown as all changed until
+                                // the old block turns up
+                                if old_record.is_none() && old.is_pending(block_x, block_y) {
+                                    return Ok(());
+                                }
+                                compare_block(
+                                    block_x,
+                                    block_y,
+                                    old_record.as_ref().map(|record| &record.block),
+                                    Some(block),
+                                    old_record.as_ref().map_or(&[], |record| &record.statics),
+                                    statics,
+                                )
+                            }
+                            None => BlockChange::default(),
+                        };
+                        draw_change_block(&mut bitmap, block, &change)
+                    }
+                    MapRenderMode::WalkMap => {
+                        let facet = &mut self.facet;
+                        // Edges are drawn again as the neighbours they depend on turn up
+                        let records = neighbourhood_records(x + self.x, y + self.y, |x, y| {
+                            facet.try_read_block(x, y)
+                        });
+                        let cells = classify_block(&mut self.tiledata, &records);
+                        draw_walk_block(&mut bitmap, &cells)
+                    }
+                };
+                if self.highlight_patches && self.facet.is_patched_block(x + self.x, y + self.y) {
+                    highlight_block(&mut bitmap, PATCH_HIGHLIGHT);
+                }
+                let block_surface =
+                    Image::from_rgba8(ctx, 8, 8, &bitmap).expect("Failed to create surface");
+                graphics::draw(
+                    ctx,
+                    &block_surface,
+                    DrawParam::default().dest(Point2::new(x as f32 * 8.0, y as f32 * 8.0)),
+                )?;
+            }
+            None => (),
+        }
+        Ok(())
+    }
+
     pub fn draw_page(&mut self, ctx: &mut Context) -> GameResult<()> {
         let dest = Canvas::with_window_size(ctx)?;
         graphics::set_canvas(ctx, Some(&dest));
         graphics::clear(ctx, graphics::BLACK);
 
-        let block_drawer = match self.mode {
-            MapRenderMode::HeightMap => draw_heightmap_block,
-            MapRenderMode::RadarMap => draw_radarcol_block,
-            MapRenderMode::StaticsMap => draw_statics_block,
-            MapRenderMode::FullMap => draw_full_block,
-        };
+        // Anything not loaded yet is drawn by draw_arrived once the prefetcher hands it over
         for y in 0..MAX_BLOCKS_HEIGHT {
             for x in 0..MAX_BLOCKS_WIDTH {
-                match self.facet.read_block(x + self.x, y + self.y) {
-                    ((ref block, ref statics), _) => {
-                        let mut bitmap = vec![0; 8 * 8 * 4];
-                        block_drawer(&mut bitmap, block, statics, &self.radar_colors);
-                        let block_surface = Image::from_rgba8(ctx, 8, 8, &bitmap)
-                            .expect("Failed to create surface");
-                        graphics::draw(
-                            ctx,
-                            &block_surface,
-                            DrawParam::default().dest(Point2::new(x as f32 * 8.0, y as f32 * 8.0)),
-                        )?;
+                self.draw_map_block(ctx, x, y)?;
+            }
+        }
+        graphics::set_canvas(ctx, None);
+        self.texture = Some(dest);
+        self.build_path_overlay(ctx)
+    }
+
+    // The path in yellow, or everywhere the search reached when it found no path, over the
+    // part of the facet in view
+    fn build_path_overlay(&mut self, ctx: &mut Context) -> GameResult<()> {
+        if !self.finding_paths {
+            self.path_overlay = None;
+            return Ok(());
+        }
+        let (width, height) = (MAX_BLOCKS_WIDTH * 8, MAX_BLOCKS_HEIGHT * 8);
+        let (left, top) = (self.x as i32 * 8, self.y as i32 * 8);
+        let mut bitmap = vec![0; (width * height * 4) as usize];
+        let mut plot = |x: i32, y: i32, (r, g, b, a): (u8, u8, u8, u8)| {
+            let (x, y) = (x - left, y - top);
+            if x >= 0 && y >= 0 && (x as u32) < width && (y as u32) < height {
+                let target = ((y as u32 * width + x as u32) * 4) as usize;
+                bitmap[target..target + 4].copy_from_slice(&[r, g, b, a]);
+            }
+        };
+        if let Some(ref search) = self.path_search {
+            match search.path {
+                Some(ref path) => {
+                    for &(x, y, _) in path {
+                        plot(x, y, (255, 255, 0, 255));
+                    }
+                }
+                None => {
+                    for &(x, y) in &search.explored {
+                        plot(x, y, (0, 160, 160, 160));
                     }
                 }
             }
         }
+        for &(x, y) in self.path_start.iter().chain(self.path_end.iter()) {
+            for dy in -1..=1 {
+                for dx in -1..=1 {
+                    plot(x + dx, y + dy, (255, 255, 255, 255));
+                }
+            }
+        }
+        self.path_overlay = Some(Image::from_rgba8(
+            ctx,
+            width as u16,
+            height as u16,
+            &bitmap,
+        )?);
+        Ok(())
+    }
+
+    fn path_status(&self) -> String {
+        match (self.path_start, self.path_end, &self.path_search) {
+            (None, _, _) => "Click a start point".to_string(),
+            (Some(_), None, _) => "Click an end point".to_string(),
+            (Some(_), Some(_), Some(search)) if !search.finished() => {
+                format!("Searching, {} cells so far", search.explored.len())
+            }
+            (Some(_), Some(_), Some(search)) => match search.length() {
+                Some(length) => format!("Path of {} steps", length),
+                None if search.gave_up => format!(
+                    "Gave up after {} cells without a path",
+                    search.explored.len()
+                ),
+                None => format!(
+                    "No path, only {} cells can be reached",
+                    search.explored.len()
+                ),
+            },
+            _ => String::new(),
+        }
+    }
+
+    fn pick_path_point(&mut self, ctx: &mut Context, x: f32, y: f32) -> GameResult<()> {
+        let point = (self.x as i32 * 8 + x as i32, self.y as i32 * 8 + y as i32);
+        match (self.path_start, self.path_end) {
+            (Some(start), None) => {
+                self.path_end = Some(point);
+                self.path_search = Some(PathSearch::new(start, point));
+            }
+            _ => {
+                self.path_start = Some(point);
+                self.path_end = None;
+                self.path_search = None;
+            }
+        }
+        self.build_path_overlay(ctx)
+    }
+
+    fn draw_arrived(&mut self, ctx: &mut Context, arrived: &[(u32, u32)]) -> GameResult<()> {
+        let dest = match self.texture.take() {
+            Some(dest) => dest,
+            None => return Ok(()),
+        };
+        graphics::set_canvas(ctx, Some(&dest));
+        for &(x, y) in arrived {
+            if x >= self.x
+                && y >= self.y
+                && x < self.x + MAX_BLOCKS_WIDTH
+                && y < self.y + MAX_BLOCKS_HEIGHT
+            {
+                self.draw_map_block(ctx, x - self.x, y - self.y)?;
+            }
+        }
         graphics::set_canvas(ctx, None);
         self.texture = Some(dest);
         Ok(())
     }
+
+    fn draw_legend(&mut self, ctx: &mut Context) -> GameResult<()> {
+        let left = graphics::screen_coordinates(ctx).w - 64.0;
+        let top = 16.0;
+        graphics::draw(
+            ctx,
+            &self.legend,
+            DrawParam::default().dest(Point2::new(left, top)),
+        )?;
+        for &altitude in LEGEND_TICKS.iter() {
+            let label = if altitude == SEA_LEVEL {
+                Text::new(format!("{} sea", altitude))
+            } else {
+                Text::new(format!("{}", altitude))
+            };
+            let row = (127 - altitude as i32) as f32;
+            graphics::draw(
+                ctx,
+                &label,
+                (
+                    Point2::new(left + LEGEND_WIDTH as f32 + 4.0, top + row - 6.0),
+                    graphics::WHITE,
+                ),
+            )?;
+        }
+        if self.contours {
+            let label = Text::new(format!("Contours every {}", self.contour_interval));
+            graphics::draw(
+                ctx,
+                &label,
+                (Point2::new(left - 64.0, top + 264.0), graphics::WHITE),
+            )?;
+        }
+        Ok(())
+    }
 }
 
-impl Scene<SceneName, ()> for MapScene {
-    fn draw(&mut self, ctx: &mut Context, _engine_data: &mut ()) -> GameResult<()> {
+impl Scene<SceneName, EngineData> for MapScene {
+    fn draw(&mut self, ctx: &mut Context, _engine_data: &mut EngineData) -> GameResult<()> {
         match self.texture {
             Some(ref texture) => {
                 graphics::draw(ctx, texture, DrawParam::default())?;
             }
             None => (),
         };
+        match self.mode {
+            MapRenderMode::HypsometricMap | MapRenderMode::HillshadeMap => {
+                self.draw_legend(ctx)?;
+            }
+            _ => (),
+        }
+        if let Some(ref overlay) = self.path_overlay {
+            graphics::draw(ctx, overlay, DrawParam::default())?;
+        }
+        if let Some((ref grid, row)) = self.heightmap_export {
+            let label = Text::new(format!(
+                "Exporting heightmap {:.0}%",
+                row as f32 * 100.0 / grid.rows().max(1) as f32
+            ));
+            let height = graphics::screen_coordinates(ctx).h;
+            graphics::draw(
+                ctx,
+                &label,
+                (Point2::new(0.0, height - 40.0), graphics::WHITE),
+            )?;
+        }
+        if self.finding_paths {
+            let label = Text::new(self.path_status());
+            let height = graphics::screen_coordinates(ctx).h;
+            graphics::draw(
+                ctx,
+                &label,
+                (Point2::new(0.0, height - 20.0), graphics::WHITE),
+            )?;
+        }
         Ok(())
     }
 
     fn update(
         &mut self,
-        _ctx: &mut Context,
-        _engine_data: &mut (),
+        ctx: &mut Context,
+        _engine_data: &mut EngineData,
     ) -> GameResult<Option<SceneChangeEvent<SceneName>>> {
+        let mut arrived = self.facet.receive_prefetched();
+        // Changes are drawn once both sides of a block are in, whichever turns up last
+        if let Some(ref mut old) = self.comparison {
+            let old_arrived = old.receive_prefetched();
+            if let MapRenderMode::ChangeMap = self.mode {
+                arrived.extend(old_arrived);
+            }
+        }
+        // Walkability reaches into the blocks around, so their edges change with each arrival
+        if let MapRenderMode::WalkMap = self.mode {
+            arrived = arrived
+                .iter()
+                .flat_map(|&(x, y)| {
+                    (y.saturating_sub(1)..y + 2)
+                        .flat_map(move |y| (x.saturating_sub(1)..x + 2).map(move |x| (x, y)))
+                })
+                .collect();
+            arrived.sort();
+            arrived.dedup();
+        }
+        if !arrived.is_empty() {
+            self.draw_arrived(ctx, &arrived)?;
+        }
+        self.continue_export();
+        self.continue_path_search(ctx)?;
         if self.exiting {
             Ok(Some(SceneChangeEvent::PopScene))
         } else {
-            Ok(None)
+            Ok(self.next_scene.take().map(SceneChangeEvent::PushScene))
+        }
+    }
+
+    // Picks a block to look at old and new side by side, while comparing
+    fn mouse_button_down_event(
+        &mut self,
+        ctx: &mut Context,
+        button: MouseButton,
+        x: f32,
+        y: f32,
+        engine_data: &mut EngineData,
+    ) {
+        if button == MouseButton::Left && self.finding_paths {
+            self.pick_path_point(ctx, x, y)
+                .expect("Failed to draw path");
+            return;
+        }
+        match (button, &self.mode, &self.comparison) {
+            (MouseButton::Left, MapRenderMode::ChangeMap, Some(_)) => {
+                let block_x = self.x + x as u32 / 8;
+                let block_y = self.y + y as u32 / 8;
+                engine_data.compare_block = Some((self.map_id, block_x, block_y));
+                self.next_scene = Some(SceneName::CompareScene);
+            }
+            _ => (),
         }
     }
 
@@ -189,31 +711,63 @@ impl Scene<SceneName, ()> for MapScene {
         &mut self,
         ctx: &mut Context,
         keycode: KeyCode,
-        _keymods: KeyMods,
+        keymods: KeyMods,
         _repeat: bool,
-        _engine_data: &mut (),
+        engine_data: &mut EngineData,
     ) {
         match keycode {
             KeyCode::Escape => self.exiting = true,
+            KeyCode::E => {
+                if self.heightmap_export.is_some() {
+                    return;
+                }
+                // Shift exports the whole facet rather than just what's on screen
+                let region = if keymods.contains(KeyMods::SHIFT) {
+                    Region {
+                        x_block: 0,
+                        y_block: 0,
+                        width_blocks: self.facet.width_blocks(),
+                        height_blocks: self.facet.height_blocks(),
+                    }
+                } else {
+                    Region {
+                        x_block: self.x,
+                        y_block: self.y,
+                        width_blocks: MAX_BLOCKS_WIDTH,
+                        height_blocks: MAX_BLOCKS_HEIGHT,
+                    }
+                };
+                self.heightmap_export = Some((AltitudeGrid::new(&self.facet, region), 0));
+            }
             KeyCode::Left => {
                 if self.x >= STEP_X as u32 {
                     self.x -= STEP_X as u32;
+                    self.prefetch((-1, 0));
                     self.draw_page(ctx).expect("Failed to draw map");
                 }
             }
             KeyCode::Right => {
-                self.x += STEP_X as u32;
-                self.draw_page(ctx).expect("Failed to draw map");
+                let max_x = self.facet.width_blocks().saturating_sub(MAX_BLOCKS_WIDTH);
+                if self.x < max_x {
+                    self.x = min(self.x + STEP_X as u32, max_x);
+                    self.prefetch((1, 0));
+                    self.draw_page(ctx).expect("Failed to draw map");
+                }
             }
             KeyCode::Up => {
                 if self.y >= STEP_Y as u32 {
                     self.y -= STEP_Y as u32;
+                    self.prefetch((0, -1));
                     self.draw_page(ctx).expect("Failed to draw map");
                 }
             }
             KeyCode::Down => {
-                self.y += STEP_Y as u32;
-                self.draw_page(ctx).expect("Failed to draw map");
+                let max_y = self.facet.height_blocks().saturating_sub(MAX_BLOCKS_HEIGHT);
+                if self.y < max_y {
+                    self.y = min(self.y + STEP_Y as u32, max_y);
+                    self.prefetch((0, 1));
+                    self.draw_page(ctx).expect("Failed to draw map");
+                }
             }
             KeyCode::Key1 => {
                 self.mode = MapRenderMode::HeightMap;
@@ -231,10 +785,100 @@ impl Scene<SceneName, ()> for MapScene {
                 self.mode = MapRenderMode::FullMap;
                 self.draw_page(ctx).expect("Failed to draw map");
             }
+            KeyCode::Key5 => {
+                self.mode = MapRenderMode::HypsometricMap;
+                self.draw_page(ctx).expect("Failed to draw map");
+            }
+            KeyCode::Key6 => {
+                self.mode = MapRenderMode::HillshadeMap;
+                self.draw_page(ctx).expect("Failed to draw map");
+            }
+            KeyCode::Key7 => match engine_data.compare_directory {
+                Some(ref directory) => {
+                    self.comparison = facet_in(directory, self.map_id);
+                    if let Some(ref mut old) = self.comparison {
+                        old.set_patched(self.facet.patched());
+                    } else {
+                        println!(
+                            "No facet {} to compare in {}",
+                            self.map_id,
+                            directory.display()
+                        );
+                    }
+                    self.mode = MapRenderMode::ChangeMap;
+                    self.draw_page(ctx).expect("Failed to draw map");
+                }
+                None => println!("Start with --compare <data directory> to compare facets"),
+            },
+            KeyCode::Key8 => {
+                self.mode = MapRenderMode::WalkMap;
+                self.draw_page(ctx).expect("Failed to draw map");
+            }
+            KeyCode::F => {
+                self.finding_paths = !self.finding_paths;
+                self.path_start = None;
+                self.path_end = None;
+                self.path_search = None;
+                self.build_path_overlay(ctx).expect("Failed to draw path");
+            }
+            KeyCode::R => {
+                self.hide_roofs = !self.hide_roofs;
+                self.draw_page(ctx).expect("Failed to draw map");
+            }
+            KeyCode::P => {
+                let patched = !self.facet.patched();
+                self.facet.set_patched(patched);
+                // Patched against unpatched would show every patch as a change
+                if let Some(ref mut old) = self.comparison {
+                    old.set_patched(patched);
+                }
+                println!(
+                    "Showing {} map",
+                    if patched { "patched" } else { "unpatched" }
+                );
+                self.prefetch((0, 0));
+                self.draw_page(ctx).expect("Failed to draw map");
+            }
+            KeyCode::H => {
+                self.highlight_patches = !self.highlight_patches;
+                self.draw_page(ctx).expect("Failed to draw map");
+            }
+            KeyCode::C => {
+                self.contours = !self.contours;
+                self.draw_page(ctx).expect("Failed to draw map");
+            }
+            KeyCode::LBracket => {
+                if self.contour_interval > 1 {
+                    self.contour_interval -= 1;
+                    self.draw_page(ctx).expect("Failed to draw map");
+                }
+            }
+            KeyCode::RBracket => {
+                if self.contour_interval < 64 {
+                    self.contour_interval += 1;
+                    self.draw_page(ctx).expect("Failed to draw map");
+                }
+            }
             KeyCode::Tab => {
                 self.mode = MapRenderMode::HeightMap;
-                self.map_id = (self.map_id + 1) % MAP_DETAILS.len() as u8;
+                self.comparison = None;
+                self.path_start = None;
+                self.path_end = None;
+                self.path_search = None;
+                self.heightmap_export = None;
+                self.map_id = (self.map_id + 1) % facet_count();
+                let patched = self.facet.patched();
                 self.facet = map_id_to_facet(self.map_id);
+                self.facet.set_patched(patched);
+                self.x = min(
+                    self.x,
+                    self.facet.width_blocks().saturating_sub(MAX_BLOCKS_WIDTH),
+                );
+                self.y = min(
+                    self.y,
+                    self.facet.height_blocks().saturating_sub(MAX_BLOCKS_HEIGHT),
+                );
+                self.prefetch((0, 0));
                 self.draw_page(ctx).expect("Failed to draw map");
             }
             _ => (),
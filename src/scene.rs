@@ -15,6 +15,8 @@ pub enum SceneName {
     WorldScene,
     FontScene,
     MapDiffScene,
+    CompareScene,
+    StatsScene,
 }
 
 pub type BoxedScene<'a, SceneChangeParamsT, EngineDataT> =
@@ -45,6 +47,33 @@ pub trait Scene<SceneChangeParamsT, EngineDataT> {
         _engine_data: &mut EngineDataT,
     ) {
     }
+    fn mouse_button_up_event(
+        &mut self,
+        _ctx: &mut Context,
+        _button: MouseButton,
+        _x: f32,
+        _y: f32,
+        _engine_data: &mut EngineDataT,
+    ) {
+    }
+    fn mouse_motion_event(
+        &mut self,
+        _ctx: &mut Context,
+        _x: f32,
+        _y: f32,
+        _dx: f32,
+        _dy: f32,
+        _engine_data: &mut EngineDataT,
+    ) {
+    }
+    fn mouse_wheel_event(
+        &mut self,
+        _ctx: &mut Context,
+        _x: f32,
+        _y: f32,
+        _engine_data: &mut EngineDataT,
+    ) {
+    }
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -147,4 +176,58 @@ impl<'a, SceneChangeParamsT, EngineDataT> SceneStack<'a, SceneChangeParamsT, Eng
             None => (),
         }
     }
+
+    pub fn mouse_button_up_event(
+        &mut self,
+        ctx: &mut Context,
+        button: MouseButton,
+        x: f32,
+        y: f32,
+        engine_data: &mut EngineDataT,
+    ) {
+        let maybe_last_scene = self.scenes.pop();
+        match maybe_last_scene {
+            Some(mut scene) => {
+                scene.mouse_button_up_event(ctx, button, x, y, engine_data);
+                self.scenes.push(scene);
+            }
+            None => (),
+        }
+    }
+
+    pub fn mouse_motion_event(
+        &mut self,
+        ctx: &mut Context,
+        x: f32,
+        y: f32,
+        dx: f32,
+        dy: f32,
+        engine_data: &mut EngineDataT,
+    ) {
+        let maybe_last_scene = self.scenes.pop();
+        match maybe_last_scene {
+            Some(mut scene) => {
+                scene.mouse_motion_event(ctx, x, y, dx, dy, engine_data);
+                self.scenes.push(scene);
+            }
+            None => (),
+        }
+    }
+
+    pub fn mouse_wheel_event(
+        &mut self,
+        ctx: &mut Context,
+        x: f32,
+        y: f32,
+        engine_data: &mut EngineDataT,
+    ) {
+        let maybe_last_scene = self.scenes.pop();
+        match maybe_last_scene {
+            Some(mut scene) => {
+                scene.mouse_wheel_event(ctx, x, y, engine_data);
+                self.scenes.push(scene);
+            }
+            None => (),
+        }
+    }
 }
@@ -2,6 +2,7 @@ use std::path::Path;
 use uorustlibs::skills::Skills;
 
 use cgmath::Point2;
+use engine::EngineData;
 use ggez::event::{KeyCode, KeyMods};
 use ggez::graphics::{self, Text};
 use ggez::{Context, GameResult};
@@ -13,7 +14,7 @@ pub struct SkillsScene {
 }
 
 impl<'a> SkillsScene {
-    pub fn new() -> BoxedScene<'a, SceneName, ()> {
+    pub fn new() -> BoxedScene<'a, SceneName, EngineData> {
         let skills = Skills::new(
             &Path::new("./assets/skills.idx"),
             &Path::new("./assets/skills.mul"),
@@ -49,8 +50,8 @@ impl<'a> SkillsScene {
     }
 }
 
-impl Scene<SceneName, ()> for SkillsScene {
-    fn draw(&mut self, ctx: &mut Context, _engine_data: &mut ()) -> GameResult<()> {
+impl Scene<SceneName, EngineData> for SkillsScene {
+    fn draw(&mut self, ctx: &mut Context, _engine_data: &mut EngineData) -> GameResult<()> {
         graphics::clear(ctx, graphics::BLACK);
         let mut last_width = 0;
         for page in self.pages.iter() {
@@ -68,7 +69,7 @@ impl Scene<SceneName, ()> for SkillsScene {
     fn update(
         &mut self,
         _ctx: &mut Context,
-        _engine_data: &mut (),
+        _engine_data: &mut EngineData,
     ) -> GameResult<Option<SceneChangeEvent<SceneName>>> {
         if self.exiting {
             Ok(Some(SceneChangeEvent::PopScene))
@@ -83,7 +84,7 @@ impl Scene<SceneName, ()> for SkillsScene {
         keycode: KeyCode,
         _keymods: KeyMods,
         _repeat: bool,
-        _engine_data: &mut (),
+        _engine_data: &mut EngineData,
     ) {
         match keycode {
             KeyCode::Escape => self.exiting = true,
@@ -1,4 +1,5 @@
 use cgmath::Point2;
+use engine::EngineData;
 use ggez::event::{KeyCode, KeyMods, MouseButton};
 use ggez::graphics::{self, Canvas, DrawParam, Text};
 use ggez::{Context, GameResult};
@@ -23,7 +24,7 @@ pub struct StaticsScene {
 }
 
 impl<'a> StaticsScene {
-    pub fn new(ctx: &mut Context) -> BoxedScene<'a, SceneName, ()> {
+    pub fn new(ctx: &mut Context) -> BoxedScene<'a, SceneName, EngineData> {
         let reader = ArtReader::new(
             &Path::new("./assets/artidx.mul"),
             &Path::new("./assets/art.mul"),
@@ -96,8 +97,8 @@ impl<'a> StaticsScene {
     }
 }
 
-impl Scene<SceneName, ()> for StaticsScene {
-    fn draw(&mut self, ctx: &mut Context, _engine_data: &mut ()) -> GameResult<()> {
+impl Scene<SceneName, EngineData> for StaticsScene {
+    fn draw(&mut self, ctx: &mut Context, _engine_data: &mut EngineData) -> GameResult<()> {
         match self.texture {
             Some(ref texture) => {
                 graphics::draw(ctx, texture, DrawParam::default())?;
@@ -110,7 +111,7 @@ impl Scene<SceneName, ()> for StaticsScene {
     fn update(
         &mut self,
         _ctx: &mut Context,
-        _engine_data: &mut (),
+        _engine_data: &mut EngineData,
     ) -> GameResult<Option<SceneChangeEvent<SceneName>>> {
         if self.exiting {
             Ok(Some(SceneChangeEvent::PopScene))
@@ -125,7 +126,7 @@ impl Scene<SceneName, ()> for StaticsScene {
         keycode: KeyCode,
         _keymods: KeyMods,
         _repeat: bool,
-        _engine_data: &mut (),
+        _engine_data: &mut EngineData,
     ) {
         match keycode {
             KeyCode::Escape => self.exiting = true,
@@ -149,7 +150,7 @@ impl Scene<SceneName, ()> for StaticsScene {
         _button: MouseButton,
         x: f32,
         y: f32,
-        _engine_data: &mut (),
+        engine_data: &mut EngineData,
     ) {
         let actual_x = (x / 128.0) as u32;
         let actual_y = (y / (128.0 + 16.0)) as u32;
@@ -159,6 +160,9 @@ impl Scene<SceneName, ()> for StaticsScene {
                 match self.tile_data[actual_index] {
                     Ok(ref data) => {
                         println!("{}", data.name);
+                        // Picked up by the world editor as the static to place
+                        let start = MAX_X * MAX_Y * self.index;
+                        engine_data.selected_static = Some((start + actual_index as u32) as u16);
                     }
                     _ => (),
                 }
@@ -0,0 +1,180 @@
+use caches::tiledata_cache::TileDataCache;
+use cgmath::Point2;
+use engine::EngineData;
+use ggez::event::{KeyCode, KeyMods};
+use ggez::graphics::{self, DrawParam, Image, Text};
+use ggez::{Context, GameResult};
+use map::export::EXPORT_DIRECTORY;
+use map::shading::hypsometric_color;
+use map::stats::{top_counts, FacetStats};
+use map::{facet_count, map_id_to_facet, Facet};
+use scene::{BoxedScene, Scene, SceneChangeEvent, SceneName};
+use std::path::Path;
+
+// Enough to keep the scene responsive while a facet is scanned
+const COLUMNS_PER_UPDATE: u32 = 4;
+const TOP_ENTRIES: usize = 12;
+const HISTOGRAM_HEIGHT: u16 = 128;
+
+pub struct StatsScene {
+    facet: Facet,
+    tiledata: TileDataCache,
+    stats: FacetStats,
+    // Rebuilt as columns are scanned, rather than every frame
+    histogram: Option<Image>,
+    map_id: u8,
+    exiting: bool,
+}
+
+// One column per altitude, lowest on the left, scaled against the most common altitude
+fn build_histogram(ctx: &mut Context, altitudes: &[u64]) -> GameResult<Image> {
+    let most = altitudes.iter().cloned().max().unwrap_or(0).max(1);
+    let mut bitmap = vec![0; 256 * HISTOGRAM_HEIGHT as usize * 4];
+    for (column, &count) in altitudes.iter().enumerate() {
+        let bar = (count * HISTOGRAM_HEIGHT as u64 / most) as usize;
+        let (r, g, b) = hypsometric_color((column as i16 - 128) as i8);
+        for row in (HISTOGRAM_HEIGHT as usize - bar)..HISTOGRAM_HEIGHT as usize {
+            let target = (row * 256 + column) * 4;
+            bitmap[target] = r;
+            bitmap[target + 1] = g;
+            bitmap[target + 2] = b;
+            bitmap[target + 3] = 255;
+        }
+    }
+    Image::from_rgba8(ctx, 256, HISTOGRAM_HEIGHT, &bitmap)
+}
+
+impl<'a> StatsScene {
+    pub fn new(_ctx: &mut Context) -> BoxedScene<'a, SceneName, EngineData> {
+        let facet = map_id_to_facet(0);
+        let stats = FacetStats::new(0, facet.width_blocks(), facet.height_blocks());
+        Box::new(StatsScene {
+            facet,
+            tiledata: TileDataCache::new(),
+            stats,
+            histogram: None,
+            map_id: 0,
+            exiting: false,
+        })
+    }
+
+    fn load_facet(&mut self) {
+        self.facet = map_id_to_facet(self.map_id);
+        self.stats = FacetStats::new(
+            self.map_id,
+            self.facet.width_blocks(),
+            self.facet.height_blocks(),
+        );
+        self.histogram = None;
+    }
+
+    fn summary(&self) -> String {
+        let stats = &self.stats;
+        let point = |point: Option<(u32, u32, i8)>| match point {
+            Some((x, y, z)) => format!("{} at {},{}", z, x, y),
+            None => "-".to_string(),
+        };
+        let mut summary = format!(
+            "Map {} - {}\n{} cells, {:.1}% water, {:.1}% impassable\n{} statics, {:.2} per block\nHighest {}, lowest {}",
+            stats.map_id,
+            if stats.is_complete() {
+                "E to export".to_string()
+            } else {
+                format!("{:.0}% scanned", stats.progress())
+            },
+            stats.cells,
+            stats.water_percent(),
+            stats.impassable_percent(),
+            stats.statics,
+            stats.statics_per_block(),
+            point(stats.highest),
+            point(stats.lowest)
+        );
+        summary.push_str("\n\nLand");
+        for (graphic, count) in top_counts(&stats.land_counts, TOP_ENTRIES) {
+            summary.push_str(&format!("\n0x{:04X}  {}", graphic, count));
+        }
+        summary
+    }
+
+    fn statics_summary(&self) -> String {
+        let mut summary = String::from("Statics");
+        for (object_id, count) in top_counts(&self.stats.static_counts, TOP_ENTRIES) {
+            summary.push_str(&format!("\n0x{:04X}  {}", object_id, count));
+        }
+        summary
+    }
+}
+
+impl Scene<SceneName, EngineData> for StatsScene {
+    fn draw(&mut self, ctx: &mut Context, _engine_data: &mut EngineData) -> GameResult<()> {
+        graphics::clear(ctx, graphics::BLACK);
+        let summary = Text::new(self.summary());
+        graphics::draw(ctx, &summary, (Point2::new(0.0, 0.0), graphics::WHITE))?;
+        let statics = Text::new(self.statics_summary());
+        graphics::draw(ctx, &statics, (Point2::new(400.0, 80.0), graphics::WHITE))?;
+
+        let top = graphics::screen_coordinates(ctx).h - HISTOGRAM_HEIGHT as f32 - 24.0;
+        if let Some(ref histogram) = self.histogram {
+            graphics::draw(
+                ctx,
+                histogram,
+                DrawParam::default().dest(Point2::new(16.0, top)),
+            )?;
+        }
+        let axis = Text::new("-128                        Altitude                        127");
+        graphics::draw(
+            ctx,
+            &axis,
+            (
+                Point2::new(16.0, top + HISTOGRAM_HEIGHT as f32 + 4.0),
+                graphics::WHITE,
+            ),
+        )?;
+        Ok(())
+    }
+
+    fn update(
+        &mut self,
+        ctx: &mut Context,
+        _engine_data: &mut EngineData,
+    ) -> GameResult<Option<SceneChangeEvent<SceneName>>> {
+        if !self.stats.is_complete() || self.histogram.is_none() {
+            for _ in 0..COLUMNS_PER_UPDATE {
+                self.stats.scan_column(&mut self.facet, &mut self.tiledata);
+            }
+            self.histogram = Some(build_histogram(ctx, &self.stats.altitudes)?);
+        }
+        if self.exiting {
+            Ok(Some(SceneChangeEvent::PopScene))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn key_down_event(
+        &mut self,
+        _ctx: &mut Context,
+        keycode: KeyCode,
+        _keymods: KeyMods,
+        _repeat: bool,
+        _engine_data: &mut EngineData,
+    ) {
+        match keycode {
+            KeyCode::Escape => self.exiting = true,
+            KeyCode::E => {
+                if self.stats.is_complete() {
+                    match self.stats.export(&Path::new(EXPORT_DIRECTORY)) {
+                        Ok(stem) => println!("Exported statistics to {}", stem.display()),
+                        Err(error) => println!("Could not export statistics: {}", error),
+                    }
+                }
+            }
+            KeyCode::Tab => {
+                self.map_id = (self.map_id + 1) % facet_count();
+                self.load_facet();
+            }
+            _ => (),
+        }
+    }
+}
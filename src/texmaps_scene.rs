@@ -1,4 +1,5 @@
 use cgmath::Point2;
+use engine::EngineData;
 use ggez::event::{KeyCode, KeyMods};
 use ggez::graphics::{self, Canvas, DrawParam, Text};
 use ggez::{Context, GameResult};
@@ -21,7 +22,7 @@ pub struct TexMapsScene {
 }
 
 impl<'a> TexMapsScene {
-    pub fn new(ctx: &mut Context) -> BoxedScene<'a, SceneName, ()> {
+    pub fn new(ctx: &mut Context) -> BoxedScene<'a, SceneName, EngineData> {
         let reader = TexMapsReader::new(
             &Path::new("./assets/texidx.mul"),
             &Path::new("./assets/texmaps.mul"),
@@ -86,8 +87,8 @@ impl<'a> TexMapsScene {
     }
 }
 
-impl Scene<SceneName, ()> for TexMapsScene {
-    fn draw(&mut self, ctx: &mut Context, _engine_data: &mut ()) -> GameResult<()> {
+impl Scene<SceneName, EngineData> for TexMapsScene {
+    fn draw(&mut self, ctx: &mut Context, _engine_data: &mut EngineData) -> GameResult<()> {
         match self.texture {
             Some(ref texture) => {
                 graphics::draw(ctx, texture, DrawParam::default())?;
@@ -100,7 +101,7 @@ impl Scene<SceneName, ()> for TexMapsScene {
     fn update(
         &mut self,
         _ctx: &mut Context,
-        _engine_data: &mut (),
+        _engine_data: &mut EngineData,
     ) -> GameResult<Option<SceneChangeEvent<SceneName>>> {
         if self.exiting {
             Ok(Some(SceneChangeEvent::PopScene))
@@ -115,7 +116,7 @@ impl Scene<SceneName, ()> for TexMapsScene {
         keycode: KeyCode,
         _keymods: KeyMods,
         _repeat: bool,
-        _engine_data: &mut (),
+        _engine_data: &mut EngineData,
     ) {
         match keycode {
             KeyCode::Escape => self.exiting = true,
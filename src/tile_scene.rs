@@ -1,4 +1,5 @@
 use cgmath::Point2;
+use engine::EngineData;
 use ggez::event::{KeyCode, KeyMods, MouseButton};
 use ggez::graphics::{self, Canvas, DrawParam, Text};
 use ggez::{Context, GameResult};
@@ -23,7 +24,7 @@ pub struct TileScene {
 }
 
 impl<'a> TileScene {
-    pub fn new(ctx: &mut Context) -> BoxedScene<'a, SceneName, ()> {
+    pub fn new(ctx: &mut Context) -> BoxedScene<'a, SceneName, EngineData> {
         let reader = ArtReader::new(
             &Path::new("./assets/artidx.mul"),
             &Path::new("./assets/art.mul"),
@@ -93,8 +94,8 @@ impl<'a> TileScene {
     }
 }
 
-impl Scene<SceneName, ()> for TileScene {
-    fn draw(&mut self, ctx: &mut Context, _engine_data: &mut ()) -> GameResult<()> {
+impl Scene<SceneName, EngineData> for TileScene {
+    fn draw(&mut self, ctx: &mut Context, _engine_data: &mut EngineData) -> GameResult<()> {
         match self.texture {
             Some(ref texture) => {
                 graphics::draw(ctx, texture, DrawParam::default())?;
@@ -107,7 +108,7 @@ impl Scene<SceneName, ()> for TileScene {
     fn update(
         &mut self,
         _ctx: &mut Context,
-        _engine_data: &mut (),
+        _engine_data: &mut EngineData,
     ) -> GameResult<Option<SceneChangeEvent<SceneName>>> {
         if self.exiting {
             Ok(Some(SceneChangeEvent::PopScene))
@@ -122,7 +123,7 @@ impl Scene<SceneName, ()> for TileScene {
         keycode: KeyCode,
         _keymods: KeyMods,
         _repeat: bool,
-        _engine_data: &mut (),
+        _engine_data: &mut EngineData,
     ) {
         match keycode {
             KeyCode::Escape => self.exiting = true,
@@ -146,7 +147,7 @@ impl Scene<SceneName, ()> for TileScene {
         _button: MouseButton,
         x: f32,
         y: f32,
-        _engine_data: &mut (),
+        engine_data: &mut EngineData,
     ) {
         let actual_x = (x / 44.0) as u32;
         let actual_y = (y / (44.0 + 16.0)) as u32;
@@ -156,6 +157,9 @@ impl Scene<SceneName, ()> for TileScene {
                 match self.tile_data[actual_index] {
                     Ok(ref data) => {
                         println!("{}", data.name);
+                        // Picked up by the world editor as the tile to paint with
+                        let start = MAX_X * MAX_Y * self.index;
+                        engine_data.selected_tile = Some((start + actual_index as u32) as u16);
                     }
                     _ => (),
                 }
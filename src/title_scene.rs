@@ -1,4 +1,5 @@
 use cgmath::Point2;
+use engine::EngineData;
 use ggez::event::{KeyCode, KeyMods};
 use ggez::graphics::{self, Text};
 use ggez::{Context, GameResult};
@@ -10,16 +11,16 @@ pub struct TitleScene {
 }
 
 impl<'a> TitleScene {
-    pub fn new() -> BoxedScene<'a, SceneName, ()> {
+    pub fn new() -> BoxedScene<'a, SceneName, EngineData> {
         Box::new(TitleScene {
-            text: Text::new("1. Skills Scene\n2. Tile Scene\n3. Statics Scene\n4. Hues Scene\n5. Map Scene\n6. Gump Scene\n7. Anim Scene\n8. TexMaps Scene\n9. World Scene\n0. Font Scene\nA. Map Diff Scene"),
+            text: Text::new("1. Skills Scene\n2. Tile Scene\n3. Statics Scene\n4. Hues Scene\n5. Map Scene\n6. Gump Scene\n7. Anim Scene\n8. TexMaps Scene\n9. World Scene\n0. Font Scene\nA. Map Diff Scene\nB. Stats Scene"),
             last_event: None
         })
     }
 }
 
-impl Scene<SceneName, ()> for TitleScene {
-    fn draw(&mut self, ctx: &mut Context, _engine_data: &mut ()) -> GameResult<()> {
+impl Scene<SceneName, EngineData> for TitleScene {
+    fn draw(&mut self, ctx: &mut Context, _engine_data: &mut EngineData) -> GameResult<()> {
         graphics::clear(ctx, graphics::BLACK);
         graphics::draw(ctx, &self.text, (Point2::new(0.0, 0.0), graphics::WHITE))
     }
@@ -27,7 +28,7 @@ impl Scene<SceneName, ()> for TitleScene {
     fn update(
         &mut self,
         _ctx: &mut Context,
-        _engine_data: &mut (),
+        _engine_data: &mut EngineData,
     ) -> GameResult<Option<SceneChangeEvent<SceneName>>> {
         Ok(self.last_event.take())
     }
@@ -38,7 +39,7 @@ impl Scene<SceneName, ()> for TitleScene {
         keycode: KeyCode,
         _keymods: KeyMods,
         _repeat: bool,
-        _engine_data: &mut (),
+        _engine_data: &mut EngineData,
     ) {
         self.last_event = match keycode {
             KeyCode::Escape => Some(SceneChangeEvent::PopScene),
@@ -53,6 +54,7 @@ impl Scene<SceneName, ()> for TitleScene {
             KeyCode::Key9 => Some(SceneChangeEvent::PushScene(SceneName::WorldScene)),
             KeyCode::Key0 => Some(SceneChangeEvent::PushScene(SceneName::FontScene)),
             KeyCode::A => Some(SceneChangeEvent::PushScene(SceneName::MapDiffScene)),
+            KeyCode::B => Some(SceneChangeEvent::PushScene(SceneName::StatsScene)),
             _ => None,
         }
     }
@@ -1,118 +1,1235 @@
 use caches::art_cache::ArtCache;
+use caches::art_mask_cache::ArtMaskCache;
+use caches::land_mesh_cache::LandMeshCache;
 use caches::texmap_cache::TexMapCache;
+use caches::tiledata_cache::TileDataCache;
+use caches::walk_mesh_cache::WalkMeshCache;
 use cgmath::Point2;
-use ggez::event::{KeyCode, KeyMods};
-use ggez::graphics::{self};
-use ggez::{Context, GameResult};
-use map::render::draw_block;
-use map::{map_id_to_facet, Facet, MAP_DETAILS};
+use engine::EngineData;
+use ggez::event::{KeyCode, KeyMods, MouseButton};
+use ggez::graphics::{self, DrawMode, DrawParam, Mesh, MeshBuilder, Rect, Text, Vertex};
+use ggez::input::{keyboard, mouse};
+use ggez::{timer, Context, GameResult};
+use map::editor::{LandEditor, LandTool, StaticEditor, StaticHandle};
+use map::line_of_sight::{line_of_sight, Blocker, SightLine, EYE_HEIGHT};
+use map::picking::{flag_names, pick, Pick, PickedItem};
+use map::render::{cell_at, draw_blocks, Ceiling, ViewBlock, TILE_SIZE};
+use map::walkability::{classify_block, neighbourhood_records, surfaces_at, WalkCell};
+use map::{facet_count, map_id_to_facet, Facet};
 use scene::{BoxedScene, Scene, SceneChangeEvent, SceneName};
+use std::cmp::min;
+use std::rc::Rc;
+use uorustlibs::map::StaticLocation;
 
-const STEP_X: u32 = 1;
-const STEP_Y: u32 = 1;
-const MAX_BLOCKS_WIDTH: u32 = 6;
-const MAX_BLOCKS_HEIGHT: u32 = 6;
+const ZOOM_LEVELS: [f32; 5] = [0.5, 0.75, 1.0, 1.5, 2.0];
+const DEFAULT_ZOOM: usize = 2;
+// Screen pixels a second, whatever the zoom
+const SCROLL_SPEED: f32 = 600.0;
+const BLOCK_PIXELS: f32 = 22.0 * 8.0;
+// How far altitude can move a block's art up or down the screen, and how far tall statics
+// reach above that
+const ALTITUDE_REACH: f32 = 128.0 * 4.0;
+const STATIC_REACH: f32 = 256.0;
+// Zoomed out, a lot more blocks are in view than the default holds
+const LAND_MESH_CAPACITY: usize = 512;
+const MAX_BRUSH_RADIUS: u32 = 8;
+// How far above where someone stands the client starts taking things away
+const CEILING_CLEARANCE: i32 = 20;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum EditMode {
+    Off,
+    Land,
+    Statics,
+}
 
 pub struct WorldScene {
     art_cache: ArtCache,
     texmap_cache: TexMapCache,
+    land_meshes: LandMeshCache,
+    walk_meshes: WalkMeshCache,
     facet: Facet,
-    x: u32,
-    y: u32,
+    // The point of the facet in the middle of the screen, in pixels from its top left
+    camera: Point2<f32>,
+    zoom: usize,
+    // Panning with the right mouse button held down
+    dragging: bool,
+    // The blocks last asked for, as x, y, width and height
+    prefetched: Option<(u32, u32, u32, u32)>,
     map_id: u8,
+    show_debug: bool,
+    highlight_patches: bool,
+    show_walkability: bool,
+    ceiling: Option<Ceiling>,
+    // Clicks pick the eye and then the target, while checking line of sight
+    checking_sight: bool,
+    sight_points: Vec<(i32, i32, i32)>,
+    sight_line: Option<SightLine>,
+    // Whatever was last clicked on, outside of the other modes, and the art to hit test with
+    picked: Option<Pick>,
+    art_masks: ArtMaskCache,
+    tiledata: TileDataCache,
+    edit_mode: EditMode,
+    land_editor: LandEditor,
+    static_editor: StaticEditor,
+    tool: LandTool,
+    brush_radius: u32,
+    selected: Option<StaticHandle>,
+    // A picker scene to open, whose choice comes back through the engine data
+    picker: Option<SceneName>,
+    // Leaving or switching facets with unsaved edits waits for the same key to be pressed again
+    discarding: Option<KeyCode>,
     exiting: bool,
 }
 
-// TODO: Make this less nasty
+// Where a block is drawn, in pixels from the facet's top left, with cell_at placing its cells
 fn block_at(x: i32, y: i32) -> Point2<f32> {
-    Point2::new(
-        ((22 * 8) * x - (y * (22 * 8)) + (22 * 16)) as f32,
-        ((22 * 8) * y + (x * (22 * 8)) - (22 * 24)) as f32,
-    )
+    Point2::new(BLOCK_PIXELS * (x - y) as f32, BLOCK_PIXELS * (x + y) as f32)
+}
+
+fn walk_mesh(
+    ctx: &mut Context,
+    block_x: u32,
+    block_y: u32,
+    cells: &[WalkCell],
+) -> GameResult<Mesh> {
+    let mut builder = MeshBuilder::new();
+    for (index, cell) in cells.iter().enumerate() {
+        let origin = cell_origin(
+            block_x as i32 * 8 + (index % 8) as i32,
+            block_y as i32 * 8 + (index / 8) as i32,
+        );
+        let (left, top) = (origin.x, origin.y - cell.z as f32 * 4.0);
+        let (r, g, b) = cell.walkability.color();
+        let color = [r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0, 0.45];
+        let half = TILE_SIZE / 2.0;
+        let corner = |dx: f32, dy: f32| Vertex {
+            pos: [left + dx, top + dy],
+            uv: [0.0, 0.0],
+            color,
+        };
+        let vertices = [
+            corner(half, 0.0),
+            corner(TILE_SIZE, half),
+            corner(half, TILE_SIZE),
+            corner(0.0, half),
+        ];
+        builder.raw(&vertices, &[0, 1, 2, 0, 2, 3], None)?;
+    }
+    builder.build(ctx)
+}
+
+// Where a cell's tile is drawn at z 0, in pixels from the facet's top left
+fn cell_origin(x: i32, y: i32) -> Point2<f32> {
+    let block = block_at(x.div_euclid(8), y.div_euclid(8));
+    let cell = cell_at(x.rem_euclid(8), y.rem_euclid(8));
+    Point2::new(block.x + cell.x, block.y + cell.y)
 }
 
 impl<'a> WorldScene {
-    pub fn new() -> BoxedScene<'a, SceneName, ()> {
-        let scene = Box::new(WorldScene {
+    pub fn new() -> BoxedScene<'a, SceneName, EngineData> {
+        let mut scene = Box::new(WorldScene {
             exiting: false,
+            show_debug: false,
+            highlight_patches: false,
+            show_walkability: false,
+            ceiling: None,
+            checking_sight: false,
+            sight_points: vec![],
+            sight_line: None,
+            picked: None,
+            art_masks: ArtMaskCache::new(),
+            tiledata: TileDataCache::new(),
+            edit_mode: EditMode::Off,
+            land_editor: LandEditor::new(),
+            static_editor: StaticEditor::new(),
+            tool: LandTool::Paint,
+            brush_radius: 0,
+            selected: None,
+            picker: None,
+            discarding: None,
             map_id: 0,
             facet: map_id_to_facet(0),
             art_cache: ArtCache::new(),
             texmap_cache: TexMapCache::new(),
-            x: 160,
-            y: 208,
+            land_meshes: LandMeshCache::with_capacity(LAND_MESH_CAPACITY),
+            walk_meshes: WalkMeshCache::with_capacity(LAND_MESH_CAPACITY),
+            camera: Point2::new(0.0, 0.0),
+            zoom: DEFAULT_ZOOM,
+            dragging: false,
+            prefetched: None,
         });
+        // Britain
+        scene.centre_on(1304, 1688);
         scene
     }
 
+    fn centre_on(&mut self, x: i32, y: i32) {
+        let origin = cell_origin(x, y);
+        let half = TILE_SIZE / 2.0;
+        self.camera = Point2::new(origin.x + half, origin.y + half);
+    }
+
+    fn scale(&self) -> f32 {
+        ZOOM_LEVELS[self.zoom]
+    }
+
+    // The part of the facet on screen, in pixels from its top left
+    fn view(&self, ctx: &Context) -> Rect {
+        let (width, height) = graphics::drawable_size(ctx);
+        let (width, height) = (width / self.scale(), height / self.scale());
+        Rect::new(
+            self.camera.x - width / 2.0,
+            self.camera.y - height / 2.0,
+            width,
+            height,
+        )
+    }
+
+    fn screen_to_world(&self, ctx: &Context, x: f32, y: f32) -> Point2<f32> {
+        let view = self.view(ctx);
+        Point2::new(view.x + x / self.scale(), view.y + y / self.scale())
+    }
+
+    fn move_camera(&mut self, dx: f32, dy: f32) {
+        let width = self.facet.width_blocks() as f32 * BLOCK_PIXELS;
+        let height = self.facet.height_blocks() as f32 * BLOCK_PIXELS;
+        self.camera.x = (self.camera.x + dx).max(-height).min(width);
+        self.camera.y = (self.camera.y + dy).max(0.0).min(width + height);
+    }
+
+    // Keeps whatever's under the given point on screen where it is
+    fn zoom_at(&mut self, ctx: &Context, steps: i32, x: f32, y: f32) {
+        let before = self.screen_to_world(ctx, x, y);
+        let zoom = self.zoom as i32 + steps;
+        self.zoom = zoom.max(0).min(ZOOM_LEVELS.len() as i32 - 1) as usize;
+        let after = self.screen_to_world(ctx, x, y);
+        self.move_camera(before.x - after.x, before.y - after.y);
+    }
+
+    // Every block that could draw into the view. Blocks are diamonds, so they're walked in
+    // rows of x + y and columns of x - y, widened to allow for altitude and tall statics.
+    fn visible_blocks(&self, ctx: &Context) -> Vec<(u32, u32)> {
+        let view = self.view(ctx);
+        let span = BLOCK_PIXELS * 2.0;
+        let first_across = ((view.x - span) / BLOCK_PIXELS).floor() as i32;
+        let last_across = ((view.x + view.w) / BLOCK_PIXELS).ceil() as i32;
+        let first_down = ((view.y - span - ALTITUDE_REACH) / BLOCK_PIXELS).floor() as i32;
+        let last_down =
+            ((view.y + view.h + ALTITUDE_REACH + STATIC_REACH) / BLOCK_PIXELS).ceil() as i32;
+        let width = self.facet.width_blocks() as i32;
+        let height = self.facet.height_blocks() as i32;
+        let mut blocks = vec![];
+        for down in first_down..=last_down {
+            for across in first_across..=last_across {
+                if (down + across).rem_euclid(2) != 0 {
+                    continue;
+                }
+                let (x, y) = ((down + across) / 2, (down - across) / 2);
+                if x >= 0 && y >= 0 && x < width && y < height {
+                    blocks.push((x as u32, y as u32));
+                }
+            }
+        }
+        blocks
+    }
+
+    // The rectangle of blocks around everything visible, as x, y, width and height
+    fn visible_area(&self, ctx: &Context) -> (u32, u32, u32, u32) {
+        let blocks = self.visible_blocks(ctx);
+        let left = blocks.iter().map(|block| block.0).min().unwrap_or(0);
+        let top = blocks.iter().map(|block| block.1).min().unwrap_or(0);
+        let right = blocks.iter().map(|block| block.0 + 1).max().unwrap_or(0);
+        let bottom = blocks.iter().map(|block| block.1 + 1).max().unwrap_or(0);
+        (left, top, right - left, bottom - top)
+    }
+
+    // Asks for what's in view whenever it changes, reaching ahead the way the view moved
+    fn prefetch(&mut self, ctx: &Context) {
+        let area = self.visible_area(ctx);
+        if self.prefetched == Some(area) {
+            return;
+        }
+        let direction = match self.prefetched {
+            Some((x, y, _, _)) => (
+                (area.0 as i64 - x as i64).signum() as i32,
+                (area.1 as i64 - y as i64).signum() as i32,
+            ),
+            None => (0, 0),
+        };
+        let (x, y, width, height) = area;
+        self.facet.prefetch(x, y, width, height, direction);
+        self.prefetched = Some(area);
+    }
+
+    // Whichever visible blocks we have so far, placed in the facet
+    fn view_blocks(&mut self, ctx: &Context) -> Vec<ViewBlock> {
+        let mut blocks = vec![];
+        for (x, y) in self.visible_blocks(ctx) {
+            if let Some(record) = self.facet.try_read_block(x, y) {
+                blocks.push(ViewBlock {
+                    record,
+                    position: (x, y),
+                    transform: block_at(x as i32, y as i32),
+                    origin: (x as i32 * 8, y as i32 * 8),
+                });
+            }
+        }
+        blocks
+    }
+
     pub fn draw_page(&mut self, ctx: &mut Context) -> GameResult<()> {
-        for y in 0..MAX_BLOCKS_HEIGHT {
-            for x in 0..MAX_BLOCKS_WIDTH {
-                let ((ref block, ref statics), ref altitudes) =
-                    self.facet.read_block(x + self.x, y + self.y);
-                let transform = block_at(x as i32, y as i32);
-                draw_block(
-                    ctx,
-                    &mut self.art_cache,
-                    &mut self.texmap_cache,
-                    Some(block),
-                    statics,
-                    altitudes,
-                    transform,
-                )?;
+        let blocks = self.view_blocks(ctx);
+        draw_blocks(
+            ctx,
+            &mut self.art_cache,
+            &mut self.texmap_cache,
+            &mut self.land_meshes,
+            &blocks,
+            self.ceiling,
+        )
+    }
+
+    // Traces the edge of every patched block in view, at z 0
+    fn draw_patch_highlights(&mut self, ctx: &mut Context) -> GameResult<()> {
+        for (x, y) in self.visible_blocks(ctx) {
+            if !self.facet.is_patched_block(x, y) {
+                continue;
             }
+            let (left, top) = (x as i32 * 8, y as i32 * 8);
+            let half = TILE_SIZE / 2.0;
+            let corner = |cell_x: i32, cell_y: i32, dx: f32, dy: f32| {
+                let point = cell_origin(left + cell_x, top + cell_y);
+                Point2::new(point.x + dx, point.y + dy)
+            };
+            let outline = [
+                corner(0, 0, half, 0.0),
+                corner(7, 0, TILE_SIZE, half),
+                corner(7, 7, half, TILE_SIZE),
+                corner(0, 7, 0.0, half),
+            ];
+            let mesh = Mesh::new_polygon(
+                ctx,
+                DrawMode::stroke(2.0),
+                &outline,
+                graphics::Color::new(1.0, 0.0, 1.0, 1.0),
+            )?;
+            graphics::draw(ctx, &mesh, DrawParam::default())?;
         }
         Ok(())
     }
+
+    // A translucent diamond over every cell in view, raised to where a mobile would stand there.
+    // Each block's diamonds are one mesh, kept until any of the records it came from changes.
+    fn draw_walkability(&mut self, ctx: &mut Context) -> GameResult<()> {
+        for (x, y) in self.visible_blocks(ctx) {
+            let facet = &mut self.facet;
+            let records = neighbourhood_records(x, y, |x, y| facet.try_read_block(x, y));
+            if records[4].is_none() {
+                continue;
+            }
+            let mesh = match self.walk_meshes.get((x, y), &records) {
+                Some(mesh) => mesh,
+                None => {
+                    let cells = classify_block(&mut self.tiledata, &records);
+                    let mesh = Rc::new(walk_mesh(ctx, x, y, &cells)?);
+                    self.walk_meshes.insert((x, y), records, mesh.clone());
+                    mesh
+                }
+            };
+            graphics::draw(ctx, &*mesh, DrawParam::default())?;
+        }
+        Ok(())
+    }
+
+    // Where a point in the world is drawn, in pixels from the facet's top left
+    fn world_to_pixels(&self, (x, y, z): (i32, i32, i32)) -> Point2<f32> {
+        let half = TILE_SIZE / 2.0;
+        let origin = cell_origin(x, y);
+        Point2::new(origin.x + half, origin.y + half - z as f32 * 4.0)
+    }
+
+    // Eye height above the highest place to stand, or above the land if there's nowhere
+    fn sight_point(&mut self, x: u32, y: u32) -> (i32, i32, i32) {
+        let standing = surfaces_at(&mut self.facet, &mut self.tiledata, x as i32, y as i32)
+            .and_then(|surfaces| surfaces.highest())
+            .unwrap_or_else(|| self.altitude_at(x, y).unwrap_or(0) as i32);
+        (x as i32, y as i32, standing + EYE_HEIGHT)
+    }
+
+    fn check_sight(&mut self) {
+        self.sight_line = match self.sight_points.as_slice() {
+            &[from, to] => Some(line_of_sight(&mut self.facet, &mut self.tiledata, from, to)),
+            _ => None,
+        };
+    }
+
+    fn pick_sight_point(&mut self, point: Point2<f32>) {
+        if let Some((cell_x, cell_y)) = self.cell_under(point) {
+            if self.sight_points.len() >= 2 {
+                self.sight_points.clear();
+            }
+            let point = self.sight_point(cell_x, cell_y);
+            self.sight_points.push(point);
+            self.check_sight();
+        }
+    }
+
+    // The ray in green up to where it's blocked, then red, with the blocker outlined
+    fn draw_sight_line(&mut self, ctx: &mut Context) -> GameResult<()> {
+        for &point in &self.sight_points {
+            let centre = self.world_to_pixels(point);
+            let marker =
+                Mesh::new_circle(ctx, DrawMode::fill(), centre, 4.0, 0.5, graphics::WHITE)?;
+            graphics::draw(ctx, &marker, DrawParam::default())?;
+        }
+        let (path, blocked) = match self.sight_line {
+            Some(ref line) => (
+                line.path
+                    .iter()
+                    .map(|&point| self.world_to_pixels(point))
+                    .collect::<Vec<_>>(),
+                line.blocked,
+            ),
+            None => return Ok(()),
+        };
+        let clear = graphics::Color::new(0.2, 1.0, 0.2, 1.0);
+        let red = graphics::Color::new(1.0, 0.2, 0.2, 1.0);
+        let split = blocked.map_or(path.len(), |(step, _)| step + 1);
+        if split >= 2 {
+            let mesh = Mesh::new_line(ctx, &path[..split], 2.0, clear)?;
+            graphics::draw(ctx, &mesh, DrawParam::default())?;
+        }
+        if split < path.len() {
+            let mesh = Mesh::new_line(ctx, &path[split - 1..], 2.0, red)?;
+            graphics::draw(ctx, &mesh, DrawParam::default())?;
+        }
+        let (step, blocker) = match blocked {
+            Some(blocked) => blocked,
+            None => return Ok(()),
+        };
+        match blocker {
+            Blocker::Static {
+                block_x,
+                block_y,
+                index,
+                ..
+            } => {
+                let handle = StaticHandle {
+                    block_x,
+                    block_y,
+                    index,
+                };
+                if let Some(rect) = self.static_rect(ctx, handle) {
+                    let outline = Mesh::new_rectangle(ctx, DrawMode::stroke(2.0), rect, red)?;
+                    graphics::draw(ctx, &outline, DrawParam::default())?;
+                }
+            }
+            Blocker::Land(_) => {
+                let (x, y, _) = self.sight_line.as_ref().unwrap().path[step];
+                let altitude = self.altitude_at(x as u32, y as u32).unwrap_or(0) as i32;
+                let centre = self.world_to_pixels((x, y, altitude));
+                let half = TILE_SIZE / 2.0;
+                let outline = [
+                    Point2::new(centre.x, centre.y - half),
+                    Point2::new(centre.x + half, centre.y),
+                    Point2::new(centre.x, centre.y + half),
+                    Point2::new(centre.x - half, centre.y),
+                ];
+                let mesh = Mesh::new_polygon(ctx, DrawMode::stroke(2.0), &outline, red)?;
+                graphics::draw(ctx, &mesh, DrawParam::default())?;
+            }
+        }
+        Ok(())
+    }
+
+    fn sight_status(&self) -> String {
+        let line = match self.sight_line {
+            Some(ref line) => line,
+            None if self.sight_points.is_empty() => {
+                return "Line of sight: click the eye".to_string()
+            }
+            None => return "Line of sight: click the target".to_string(),
+        };
+        let (from, to) = (self.sight_points[0], self.sight_points[1]);
+        let blocked = match line.blocked {
+            None => "clear".to_string(),
+            Some((step, blocker)) => {
+                let (x, y, z) = line.path[step];
+                match blocker {
+                    Blocker::Land(graphic) => {
+                        format!("blocked by land 0x{:04X} at {},{},{}", graphic, x, y, z)
+                    }
+                    Blocker::Static { object_id, .. } => {
+                        format!("blocked by static 0x{:04X} at {},{},{}", object_id, x, y, z)
+                    }
+                }
+            }
+        };
+        format!(
+            "Line of sight from {},{},{} to {},{},{}: {}",
+            from.0, from.1, from.2, to.0, to.1, to.2, blocked
+        )
+    }
+
+    // Starts the ceiling just over whoever would be standing in the middle of the view
+    fn toggle_ceiling(&mut self) {
+        if self.ceiling.take().is_some() {
+            return;
+        }
+        let camera = self.camera;
+        let standing = match self.cell_under(camera) {
+            Some((x, y)) => self.sight_point(x, y).2 - EYE_HEIGHT,
+            None => 0,
+        };
+        self.ceiling = Some(Ceiling {
+            z: min(standing + CEILING_CLEARANCE, 127) as i8,
+            hide_roofs: true,
+            translucent: false,
+        });
+    }
+
+    fn draw_ceiling_status(&mut self, ctx: &mut Context, ceiling: Ceiling) -> GameResult<()> {
+        let label = Text::new(format!(
+            "Ceiling z {}{}{}",
+            ceiling.z,
+            if ceiling.hide_roofs {
+                ", roofs hidden"
+            } else {
+                ""
+            },
+            if ceiling.translucent {
+                ", translucent"
+            } else {
+                ""
+            }
+        ));
+        let height = graphics::drawable_size(ctx).1;
+        graphics::draw(
+            ctx,
+            &label,
+            (Point2::new(0.0, height - 60.0), graphics::WHITE),
+        )
+    }
+
+    fn inspect(&mut self, ctx: &mut Context, point: Point2<f32>) {
+        let blocks = self.view_blocks(ctx);
+        self.picked = pick(
+            ctx,
+            &mut self.art_cache,
+            &mut self.art_masks,
+            &blocks,
+            point,
+            self.ceiling,
+        );
+    }
+
+    // Outlines the picked static's art, or the picked land's diamond
+    fn draw_picked_outline(&mut self, ctx: &mut Context) -> GameResult<()> {
+        let picked = match self.picked {
+            Some(ref picked) => picked.clone(),
+            None => return Ok(()),
+        };
+        let color = graphics::Color::new(1.0, 1.0, 0.0, 1.0);
+        match picked.item {
+            PickedItem::Static { index, .. } => {
+                let handle = StaticHandle {
+                    block_x: picked.block_x,
+                    block_y: picked.block_y,
+                    index,
+                };
+                if let Some(rect) = self.static_rect(ctx, handle) {
+                    let outline = Mesh::new_rectangle(ctx, DrawMode::stroke(2.0), rect, color)?;
+                    graphics::draw(ctx, &outline, DrawParam::default())?;
+                }
+            }
+            PickedItem::Land(_) => {
+                let centre = self.world_to_pixels((picked.x as i32, picked.y as i32, picked.z));
+                let half = TILE_SIZE / 2.0;
+                let outline = [
+                    Point2::new(centre.x, centre.y - half),
+                    Point2::new(centre.x + half, centre.y),
+                    Point2::new(centre.x, centre.y + half),
+                    Point2::new(centre.x - half, centre.y),
+                ];
+                let mesh = Mesh::new_polygon(ctx, DrawMode::stroke(2.0), &outline, color)?;
+                graphics::draw(ctx, &mesh, DrawParam::default())?;
+            }
+        }
+        Ok(())
+    }
+
+    fn draw_inspector(&mut self, ctx: &mut Context) -> GameResult<()> {
+        let picked = match self.picked {
+            Some(ref picked) => picked,
+            None => return Ok(()),
+        };
+        let what = match picked.item {
+            PickedItem::Land(graphic) => format!("Land 0x{:04X} {}", graphic, picked.name),
+            PickedItem::Static { object_id, hue, .. } => {
+                format!("Static 0x{:04X} {}, hue {}", object_id, picked.name, hue)
+            }
+        };
+        let flags = flag_names(picked.flags);
+        let label = Text::new(format!(
+            "{}\nAt {},{} z {}\nBlock {},{}\nFlags 0x{:08X}{}{}",
+            what,
+            picked.x,
+            picked.y,
+            picked.z,
+            picked.block_x,
+            picked.block_y,
+            picked.flags,
+            if flags.is_empty() { "" } else { ": " },
+            flags.join(", ")
+        ));
+        let width = graphics::drawable_size(ctx).0;
+        let left = width - label.width(ctx) as f32 - 10.0;
+        graphics::draw(ctx, &label, (Point2::new(left, 10.0), graphics::WHITE))
+    }
+
+    fn altitude_at(&mut self, x: u32, y: u32) -> Option<i8> {
+        self.facet
+            .try_read_block(x / 8, y / 8)
+            .map(|record| record.block.cells[((y % 8) * 8 + x % 8) as usize].altitude)
+    }
+
+    // Finds the cell whose tile is drawn under a point, in pixels from the facet's top left.
+    // Altitude can push a tile a long way up or down the screen, so every cell in the column
+    // that could reach the point is tried, and the one drawn last wins.
+    fn cell_under(&mut self, point: Point2<f32>) -> Option<(u32, u32)> {
+        let (x, y) = (point.x, point.y);
+        let half = TILE_SIZE / 2.0;
+        let origin = cell_origin(0, 0);
+        let across = ((x - origin.x - half) / half).round() as i32;
+        let down = ((y - origin.y - half) / half).round() as i32;
+        let reach = (ALTITUDE_REACH / half).ceil() as i32;
+        let mut found = None;
+        for depth in (down - reach)..=(down + reach) {
+            for offset in (across - 1)..=(across + 1) {
+                if (depth + offset) % 2 != 0 {
+                    continue;
+                }
+                let cell_x = (depth + offset) / 2;
+                let cell_y = (depth - offset) / 2;
+                if cell_x < 0 || cell_y < 0 {
+                    continue;
+                }
+                let world = (cell_x as u32, cell_y as u32);
+                let altitude = match self.altitude_at(world.0, world.1) {
+                    Some(altitude) => altitude,
+                    None => continue,
+                };
+                let point = cell_origin(cell_x, cell_y);
+                let centre_x = point.x + half;
+                let centre_y = point.y + half - altitude as f32 * 4.0;
+                if (x - centre_x).abs() + (y - centre_y).abs() <= half {
+                    found = Some(world);
+                }
+            }
+        }
+        found
+    }
+
+    fn static_rect(&mut self, ctx: &mut Context, handle: StaticHandle) -> Option<Rect> {
+        let location = self.static_editor.get(&mut self.facet, handle)?;
+        self.location_rect(ctx, handle.block_x, handle.block_y, &location)
+    }
+
+    // Where a static's art is drawn, matching the offsets draw_blocks uses
+    fn location_rect(
+        &mut self,
+        ctx: &mut Context,
+        block_x: u32,
+        block_y: u32,
+        location: &StaticLocation,
+    ) -> Option<Rect> {
+        let (width, height) = match self.art_cache.read_static(ctx, location.object_id as u32) {
+            Some((ref art, _)) => (art.width() as f32, art.height() as f32),
+            None => return None,
+        };
+        let block = block_at(block_x as i32, block_y as i32);
+        let cell = cell_at(location.x as i32, location.y as i32);
+        Some(Rect::new(
+            block.x + cell.x,
+            block.y + cell.y - location.altitude as f32 * 4.0 - height + TILE_SIZE,
+            width,
+            height,
+        ))
+    }
+
+    // The static whose art covers a point, preferring whichever is drawn in front
+    fn static_under(&mut self, ctx: &mut Context, point: Point2<f32>) -> Option<StaticHandle> {
+        let (x, y) = (point.x, point.y);
+        let mut found: Option<((u32, i8, usize), StaticHandle)> = None;
+        for (block_x, block_y) in self.visible_blocks(ctx) {
+            let record = match self.facet.try_read_block(block_x, block_y) {
+                Some(record) => record,
+                None => continue,
+            };
+            for (index, location) in record.statics.iter().enumerate() {
+                if let Some(ceiling) = self.ceiling {
+                    let (flags, _) = self
+                        .tiledata
+                        .static_flags_and_height(location.object_id as u32);
+                    if ceiling.hides(location.altitude, flags) {
+                        continue;
+                    }
+                }
+                let rect = match self.location_rect(ctx, block_x, block_y, location) {
+                    Some(rect) => rect,
+                    None => continue,
+                };
+                if x < rect.x || y < rect.y || x >= rect.x + rect.w || y >= rect.y + rect.h {
+                    continue;
+                }
+                let depth = block_x * 8 + location.x as u32 + block_y * 8 + location.y as u32;
+                let order = (depth, location.altitude, index);
+                if found.map_or(true, |(best, _)| order > best) {
+                    let handle = StaticHandle {
+                        block_x,
+                        block_y,
+                        index,
+                    };
+                    found = Some((order, handle));
+                }
+            }
+        }
+        found.map(|(_, handle)| handle)
+    }
+
+    fn unsaved_blocks(&self) -> usize {
+        self.land_editor.unsaved_blocks() + self.static_editor.unsaved_blocks()
+    }
+
+    fn draw_discard_prompt(&mut self, ctx: &mut Context, key: KeyCode) -> GameResult<()> {
+        let label = Text::new(format!(
+            "{} unsaved blocks. Ctrl+S to save them, {} again to throw them away, anything else to stay",
+            self.unsaved_blocks(),
+            if key == KeyCode::Tab { "Tab" } else { "Escape" }
+        ));
+        let height = graphics::drawable_size(ctx).1;
+        graphics::draw(
+            ctx,
+            &label,
+            (
+                Point2::new(0.0, height - 60.0),
+                graphics::Color::new(1.0, 1.0, 0.0, 1.0),
+            ),
+        )
+    }
+
+    fn save_edits(&mut self) {
+        match self.land_editor.save(&mut self.facet) {
+            Ok(count) => println!("Saved {} land blocks", count),
+            Err(error) => println!("Could not save map: {}", error),
+        }
+        match self.static_editor.save(&mut self.facet) {
+            Ok(count) => println!("Saved {} static blocks", count),
+            Err(error) => println!("Could not save statics: {}", error),
+        }
+    }
+
+    // Returns true if the key was an editing one
+    fn land_key_down_event(&mut self, keycode: KeyCode, keymods: KeyMods) -> bool {
+        let ctrl = keymods.contains(KeyMods::CTRL);
+        match keycode {
+            KeyCode::Key1 => self.tool = LandTool::Paint,
+            KeyCode::Key2 => self.tool = LandTool::Raise,
+            KeyCode::Key3 => self.tool = LandTool::Lower,
+            KeyCode::LBracket => self.brush_radius = self.brush_radius.saturating_sub(1),
+            KeyCode::RBracket => self.brush_radius = min(self.brush_radius + 1, MAX_BRUSH_RADIUS),
+            KeyCode::T => self.picker = Some(SceneName::TileScene),
+            KeyCode::Z if ctrl => {
+                if !self.land_editor.undo(&mut self.facet) {
+                    println!("Nothing to undo");
+                }
+            }
+            KeyCode::Y if ctrl => {
+                if !self.land_editor.redo(&mut self.facet) {
+                    println!("Nothing to redo");
+                }
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    fn statics_key_down_event(
+        &mut self,
+        ctx: &mut Context,
+        keycode: KeyCode,
+        keymods: KeyMods,
+        selected_static: Option<u16>,
+    ) -> bool {
+        let step = if keymods.contains(KeyMods::SHIFT) {
+            10
+        } else {
+            1
+        };
+        if keycode == KeyCode::T {
+            self.picker = Some(SceneName::StaticsScene);
+            return true;
+        }
+        if keycode == KeyCode::N {
+            let position = mouse::position(ctx);
+            let point = self.screen_to_world(ctx, position.x, position.y);
+            match (selected_static, self.cell_under(point)) {
+                (Some(object_id), Some((cell_x, cell_y))) => {
+                    let altitude = self.altitude_at(cell_x, cell_y).unwrap_or(0);
+                    self.picked = None;
+                    self.selected = Some(self.static_editor.place(
+                        &mut self.facet,
+                        cell_x,
+                        cell_y,
+                        altitude,
+                        object_id,
+                    ));
+                }
+                (None, _) => println!("Press T to pick a static to place"),
+                _ => (),
+            }
+            return true;
+        }
+        let handle = match self.selected {
+            Some(handle) => handle,
+            None => return false,
+        };
+        let facet = &mut self.facet;
+        let editor = &mut self.static_editor;
+        let nudged = match keycode {
+            KeyCode::Left => editor.nudge(facet, handle, -1, 0, 0),
+            KeyCode::Right => editor.nudge(facet, handle, 1, 0, 0),
+            KeyCode::Up => editor.nudge(facet, handle, 0, -1, 0),
+            KeyCode::Down => editor.nudge(facet, handle, 0, 1, 0),
+            KeyCode::PageUp => editor.nudge(facet, handle, 0, 0, step),
+            KeyCode::PageDown => editor.nudge(facet, handle, 0, 0, -step),
+            KeyCode::Equals | KeyCode::Minus => {
+                let hue = editor
+                    .get(facet, handle)
+                    .map_or(0, |location| location.unknown);
+                let hue = if keycode == KeyCode::Equals {
+                    hue.saturating_add(step as u16)
+                } else {
+                    hue.saturating_sub(step as u16)
+                };
+                editor.set_hue(facet, handle, hue);
+                println!("Hue {}", hue);
+                Some(handle)
+            }
+            KeyCode::Delete => {
+                editor.delete(facet, handle);
+                None
+            }
+            _ => return false,
+        };
+        // Whatever was inspected may have moved or gone, and indexes shift when statics do
+        self.picked = None;
+        self.selected = nudged;
+        true
+    }
+
+    pub fn draw_edit_status(
+        &mut self,
+        ctx: &mut Context,
+        engine_data: &EngineData,
+    ) -> GameResult<()> {
+        let picked = |id: Option<u16>| match id {
+            Some(id) => format!("{}", id),
+            None => "none".to_string(),
+        };
+        let status = match self.edit_mode {
+            EditMode::Land => format!(
+                "Editing land: {:?}, brush {}, tile {}",
+                self.tool,
+                self.brush_radius,
+                picked(engine_data.selected_tile)
+            ),
+            _ => {
+                let selected = self
+                    .selected
+                    .and_then(|handle| self.static_editor.get(&mut self.facet, handle));
+                match selected {
+                    Some(location) => format!(
+                        "Editing statics: {} at {},{} z {} hue {}, placing {}",
+                        location.object_id,
+                        location.x,
+                        location.y,
+                        location.altitude,
+                        location.unknown,
+                        picked(engine_data.selected_static)
+                    ),
+                    None => format!(
+                        "Editing statics: nothing selected, placing {}",
+                        picked(engine_data.selected_static)
+                    ),
+                }
+            }
+        };
+        let label = Text::new(format!(
+            "{}, {} unsaved land blocks, {} unsaved static blocks",
+            status,
+            self.land_editor.unsaved_blocks(),
+            self.static_editor.unsaved_blocks()
+        ));
+        let height = graphics::drawable_size(ctx).1;
+        graphics::draw(
+            ctx,
+            &label,
+            (Point2::new(0.0, height - 20.0), graphics::WHITE),
+        )
+    }
+
+    pub fn draw_debug_overlay(&mut self, ctx: &mut Context) -> GameResult<()> {
+        let label = Text::new(format!(
+            "FPS {:.1}\nPending {}\nRecords {}\nLand {}\nTiles {}\nStatics {}\nTexmaps {}\nLand meshes {}\nWalk meshes {}",
+            timer::fps(ctx),
+            self.facet.pending_blocks(),
+            self.facet.record_stats(),
+            self.facet.land_stats(),
+            self.art_cache.tile_stats(),
+            self.art_cache.static_stats(),
+            self.texmap_cache.stats(),
+            self.land_meshes.stats(),
+            self.walk_meshes.stats()
+        ));
+        graphics::draw(ctx, &label, (Point2::new(0.0, 0.0), graphics::WHITE))
+    }
 }
 
-impl Scene<SceneName, ()> for WorldScene {
-    fn draw(&mut self, ctx: &mut Context, _engine_data: &mut ()) -> GameResult<()> {
+impl Scene<SceneName, EngineData> for WorldScene {
+    fn draw(&mut self, ctx: &mut Context, engine_data: &mut EngineData) -> GameResult<()> {
         graphics::clear(ctx, graphics::BLACK);
-        self.draw_page(ctx)
+        let screen = graphics::screen_coordinates(ctx);
+        let view = self.view(ctx);
+        graphics::set_screen_coordinates(ctx, view)?;
+        self.draw_page(ctx)?;
+        if self.highlight_patches {
+            self.draw_patch_highlights(ctx)?;
+        }
+        if self.show_walkability {
+            self.draw_walkability(ctx)?;
+        }
+        if self.checking_sight {
+            self.draw_sight_line(ctx)?;
+        }
+        self.draw_picked_outline(ctx)?;
+        if self.edit_mode == EditMode::Statics {
+            if let Some(rect) = self
+                .selected
+                .and_then(|handle| self.static_rect(ctx, handle))
+            {
+                let outline =
+                    Mesh::new_rectangle(ctx, DrawMode::stroke(1.0), rect, graphics::WHITE)?;
+                graphics::draw(ctx, &outline, DrawParam::default())?;
+            }
+        }
+        graphics::set_screen_coordinates(ctx, screen)?;
+
+        self.draw_inspector(ctx)?;
+        if let Some(ceiling) = self.ceiling {
+            self.draw_ceiling_status(ctx, ceiling)?;
+        }
+        if self.checking_sight {
+            let label = Text::new(self.sight_status());
+            let height = graphics::drawable_size(ctx).1;
+            graphics::draw(
+                ctx,
+                &label,
+                (Point2::new(0.0, height - 40.0), graphics::WHITE),
+            )?;
+        }
+        if self.edit_mode != EditMode::Off {
+            self.draw_edit_status(ctx, engine_data)?;
+        }
+        if let Some(key) = self.discarding {
+            self.draw_discard_prompt(ctx, key)?;
+        }
+        if self.show_debug {
+            self.draw_debug_overlay(ctx)?;
+        }
+        Ok(())
     }
 
     fn key_down_event(
         &mut self,
-        _ctx: &mut Context,
+        ctx: &mut Context,
         keycode: KeyCode,
-        _keymods: KeyMods,
+        keymods: KeyMods,
         _repeat: bool,
-        _engine_data: &mut (),
+        engine_data: &mut EngineData,
     ) {
+        let ctrl_s = keycode == KeyCode::S && keymods.contains(KeyMods::CTRL);
+        let confirmed = match self.discarding.take() {
+            Some(_) if ctrl_s => {
+                self.save_edits();
+                return;
+            }
+            Some(key) if key == keycode => true,
+            Some(_) => return,
+            None => false,
+        };
+        let must_confirm = !confirmed && self.unsaved_blocks() > 0;
+        let handled = match self.edit_mode {
+            EditMode::Off => false,
+            _ if ctrl_s => {
+                self.save_edits();
+                true
+            }
+            EditMode::Land => self.land_key_down_event(keycode, keymods),
+            EditMode::Statics => {
+                self.statics_key_down_event(ctx, keycode, keymods, engine_data.selected_static)
+            }
+        };
+        if handled {
+            return;
+        }
         match keycode {
+            KeyCode::Escape | KeyCode::Tab if must_confirm => self.discarding = Some(keycode),
             KeyCode::Escape => self.exiting = true,
-            KeyCode::Left => {
-                if self.x >= STEP_X as u32 {
-                    self.x -= STEP_X as u32;
+            // Held arrows scroll smoothly in update, with Shift they step a tile at a time
+            KeyCode::Left | KeyCode::Right | KeyCode::Up | KeyCode::Down
+                if keymods.contains(KeyMods::SHIFT) =>
+            {
+                let (dx, dy) = match keycode {
+                    KeyCode::Left => (-TILE_SIZE, 0.0),
+                    KeyCode::Right => (TILE_SIZE, 0.0),
+                    KeyCode::Up => (0.0, -TILE_SIZE),
+                    _ => (0.0, TILE_SIZE),
+                };
+                self.move_camera(dx, dy);
+            }
+            KeyCode::Equals | KeyCode::Minus => {
+                let (width, height) = graphics::drawable_size(ctx);
+                let steps = if keycode == KeyCode::Equals { 1 } else { -1 };
+                self.zoom_at(ctx, steps, width / 2.0, height / 2.0);
+            }
+            KeyCode::F1 => self.show_debug = !self.show_debug,
+            // Edits are made over the map files as they are, which is what gets saved
+            KeyCode::P if self.edit_mode != EditMode::Off => {
+                println!("Leave edit mode before showing patches")
+            }
+            KeyCode::P => {
+                let patched = !self.facet.patched();
+                self.facet.set_patched(patched);
+                println!(
+                    "Showing {} map",
+                    if patched { "patched" } else { "unpatched" }
+                );
+                self.prefetched = None;
+            }
+            KeyCode::H => self.highlight_patches = !self.highlight_patches,
+            KeyCode::W => self.show_walkability = !self.show_walkability,
+            KeyCode::C => self.toggle_ceiling(),
+            KeyCode::Comma | KeyCode::Period if self.ceiling.is_some() => {
+                let step = if keymods.contains(KeyMods::SHIFT) {
+                    10
+                } else {
+                    1
+                };
+                if let Some(ref mut ceiling) = self.ceiling {
+                    ceiling.z = if keycode == KeyCode::Period {
+                        ceiling.z.saturating_add(step)
+                    } else {
+                        ceiling.z.saturating_sub(step)
+                    };
                 }
             }
-            KeyCode::Right => {
-                self.x += STEP_X as u32;
+            KeyCode::R => {
+                if let Some(ref mut ceiling) = self.ceiling {
+                    ceiling.hide_roofs = !ceiling.hide_roofs;
+                }
             }
-            KeyCode::Up => {
-                if self.y >= STEP_Y as u32 {
-                    self.y -= STEP_Y as u32;
+            KeyCode::V => {
+                if let Some(ref mut ceiling) = self.ceiling {
+                    ceiling.translucent = !ceiling.translucent;
                 }
             }
-            KeyCode::Down => {
-                self.y += STEP_Y as u32;
+            KeyCode::L => {
+                self.checking_sight = !self.checking_sight;
+                self.sight_points.clear();
+                self.sight_line = None;
+            }
+            // Raises or lowers whichever point was picked last
+            KeyCode::PageUp | KeyCode::PageDown if self.checking_sight => {
+                let step = if keymods.contains(KeyMods::SHIFT) {
+                    10
+                } else {
+                    1
+                };
+                if let Some(point) = self.sight_points.last_mut() {
+                    point.2 += if keycode == KeyCode::PageUp {
+                        step
+                    } else {
+                        -step
+                    };
+                }
+                self.check_sight();
+            }
+            KeyCode::E if self.edit_mode == EditMode::Off && self.facet.patched() => {
+                println!("Press P to hide patches before editing")
+            }
+            KeyCode::E => {
+                self.edit_mode = match self.edit_mode {
+                    EditMode::Off => EditMode::Land,
+                    EditMode::Land => EditMode::Statics,
+                    EditMode::Statics => EditMode::Off,
+                };
+                self.selected = None;
+            }
+            KeyCode::F5 => {
+                self.facet.flush();
+                self.art_cache.flush();
+                self.texmap_cache.flush();
+                self.land_meshes.flush();
+                self.walk_meshes.flush();
+                self.art_masks.flush();
+                self.prefetched = None;
             }
             KeyCode::Tab => {
-                self.map_id = (self.map_id + 1) % MAP_DETAILS.len() as u8;
+                self.land_editor = LandEditor::new();
+                self.static_editor = StaticEditor::new();
+                self.selected = None;
+                self.picked = None;
+                self.sight_points.clear();
+                self.sight_line = None;
+                self.ceiling = None;
+                self.map_id = (self.map_id + 1) % facet_count();
+                let patched = self.facet.patched();
                 self.facet = map_id_to_facet(self.map_id);
-                self.x = 0;
-                self.y = 0;
+                self.facet.set_patched(patched);
+                self.land_meshes.flush();
+                self.walk_meshes.flush();
+                self.centre_on(24, 24);
+                self.prefetched = None;
             }
             _ => (),
         }
     }
 
-    fn update(
+    fn mouse_button_down_event(
+        &mut self,
+        ctx: &mut Context,
+        button: MouseButton,
+        x: f32,
+        y: f32,
+        engine_data: &mut EngineData,
+    ) {
+        if button == MouseButton::Right {
+            self.dragging = true;
+            return;
+        }
+        if button != MouseButton::Left {
+            return;
+        }
+        let point = self.screen_to_world(ctx, x, y);
+        if self.checking_sight {
+            self.pick_sight_point(point);
+            return;
+        }
+        match self.edit_mode {
+            EditMode::Off => {
+                self.inspect(ctx, point);
+                return;
+            }
+            EditMode::Statics => {
+                self.selected = self.static_under(ctx, point);
+                return;
+            }
+            EditMode::Land => (),
+        }
+        let graphic = match (self.tool, engine_data.selected_tile) {
+            (LandTool::Paint, None) => {
+                println!("Press T to pick a tile to paint with");
+                return;
+            }
+            (_, selected_tile) => selected_tile.unwrap_or(0),
+        };
+        if let Some((cell_x, cell_y)) = self.cell_under(point) {
+            self.land_editor.apply(
+                &mut self.facet,
+                cell_x,
+                cell_y,
+                self.brush_radius,
+                self.tool,
+                graphic,
+            );
+        }
+    }
+
+    fn mouse_button_up_event(
         &mut self,
         _ctx: &mut Context,
-        _engine_data: &mut (),
+        button: MouseButton,
+        _x: f32,
+        _y: f32,
+        _engine_data: &mut EngineData,
+    ) {
+        if button == MouseButton::Right {
+            self.dragging = false;
+        }
+    }
+
+    fn mouse_motion_event(
+        &mut self,
+        _ctx: &mut Context,
+        _x: f32,
+        _y: f32,
+        dx: f32,
+        dy: f32,
+        _engine_data: &mut EngineData,
+    ) {
+        if self.dragging {
+            let scale = self.scale();
+            self.move_camera(-dx / scale, -dy / scale);
+        }
+    }
+
+    fn mouse_wheel_event(
+        &mut self,
+        ctx: &mut Context,
+        _x: f32,
+        y: f32,
+        _engine_data: &mut EngineData,
+    ) {
+        let position = mouse::position(ctx);
+        let steps = if y > 0.0 {
+            1
+        } else if y < 0.0 {
+            -1
+        } else {
+            0
+        };
+        self.zoom_at(ctx, steps, position.x, position.y);
+    }
+
+    fn update(
+        &mut self,
+        ctx: &mut Context,
+        _engine_data: &mut EngineData,
     ) -> GameResult<Option<SceneChangeEvent<SceneName>>> {
+        // The arrows nudge the selected static instead while editing statics
+        let nudging = self.edit_mode == EditMode::Statics && self.selected.is_some();
+        if !nudging && !keyboard::active_mods(ctx).contains(KeyMods::SHIFT) {
+            let held = |keycode: KeyCode| {
+                if keyboard::is_key_pressed(ctx, keycode) {
+                    1.0
+                } else {
+                    0.0
+                }
+            };
+            let dx = held(KeyCode::Right) - held(KeyCode::Left);
+            let dy = held(KeyCode::Down) - held(KeyCode::Up);
+            if dx != 0.0 || dy != 0.0 {
+                let distance = SCROLL_SPEED * timer::delta(ctx).as_secs_f32() / self.scale();
+                self.move_camera(dx * distance, dy * distance);
+            }
+        }
+        self.prefetch(ctx);
+        self.facet.receive_prefetched();
+        if let Some(picker) = self.picker.take() {
+            return Ok(Some(SceneChangeEvent::PushScene(picker)));
+        }
         if self.exiting {
             Ok(Some(SceneChangeEvent::PopScene))
         } else {